@@ -0,0 +1,197 @@
+//! Simulates thousands of Sporz games between bots for each table size, to
+//! tune the compositions with data:
+//!
+//! `cargo run --release --bin simulate -- --preset interludes --games 5000`
+
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::{env, fs, io, process};
+
+use sporz_bot::sporz::{
+    bot::{self, Statistics, Strategies},
+    Composition, Preset, Side, Variants,
+};
+
+const USAGE: &str = "usage: simulate [--games N] [--players 8,10,14] [--config FILE] \
+                     [--preset NAME] [--astronauts STRATEGY] [--mutants STRATEGY] [--seed N]
+strategies: random, suspicion";
+const DEFAULT_CONFIG: &str = "sporz.toml";
+const DEFAULT_GAMES: usize = 1000;
+/// Table sizes simulated with the standard compositions.
+const STANDARD_SIZES: [usize; 5] = [6, 8, 10, 12, 14];
+
+/// The part of the configuration file read by the simulator.
+#[derive(Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    sporz: Rules,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Rules {
+    variants: Variants,
+    presets: Vec<Preset>,
+}
+
+struct Options {
+    games: usize,
+    players: Vec<usize>,
+    config: Option<String>,
+    preset: Option<String>,
+    strategies: Strategies,
+    seed: Option<u64>,
+}
+
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        games: DEFAULT_GAMES,
+        players: Vec::new(),
+        config: None,
+        preset: None,
+        strategies: Strategies::default(),
+        seed: None,
+    };
+    while let Some(flag) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        let invalid = |_| format!("invalid value `{}` for {}", value, flag);
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(invalid)?,
+            "--players" => {
+                options.players = value
+                    .split(',')
+                    .map(|players| players.trim().parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(invalid)?
+            }
+            "--config" => options.config = Some(value),
+            "--preset" => options.preset = Some(value),
+            "--astronauts" => {
+                options.strategies.astronauts = value.parse().map_err(|why| format!("{}", why))?
+            }
+            "--mutants" => {
+                options.strategies.mutants = value.parse().map_err(|why| format!("{}", why))?
+            }
+            "--seed" => options.seed = Some(value.parse().map_err(invalid)?),
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    Ok(options)
+}
+
+/// Reads the variants and presets of the configuration file, which is only
+/// required when given.
+fn load(path: Option<&str>) -> Result<Rules, String> {
+    let content = match fs::read_to_string(path.unwrap_or(DEFAULT_CONFIG)) {
+        Ok(content) => content,
+        Err(why) if why.kind() == io::ErrorKind::NotFound && path.is_none() => {
+            return Ok(Rules::default())
+        }
+        Err(why) => return Err(format!("could not read the configuration: {}", why)),
+    };
+    toml::from_str::<ConfigFile>(&content)
+        .map(|file| file.sporz)
+        .map_err(|why| format!("invalid configuration file: {}", why))
+}
+
+fn report(players: usize, composition: &Composition, statistics: &Statistics) {
+    let roles: Vec<&str> = composition.roles.iter().map(|role| role.name()).collect();
+    println!(
+        "\n{} players: {}, {} hosts, {} resistants",
+        players,
+        roles.join(", "),
+        composition.hosts,
+        composition.resistants
+    );
+    println!(
+        "  astronauts win {:.1}%, mutants {:.1}%, {} games given up",
+        statistics.win_rate(Side::Astronauts),
+        statistics.win_rate(Side::Mutants),
+        statistics.given_up()
+    );
+    println!("  {:.2} nights on average", statistics.average_nights());
+    println!(
+        "  {:<20} {:>8} {:>8} {:>9} {:>8}",
+        "role", "players", "won", "survived", "mutated"
+    );
+    for (role, fared) in &statistics.roles {
+        println!(
+            "  {:<20} {:>8} {:>7.1}% {:>8.1}% {:>7.1}%",
+            role.name(),
+            fared.players,
+            fared.rate(fared.winners),
+            fared.rate(fared.survivors),
+            fared.rate(fared.mutated)
+        );
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let rules = load(options.config.as_deref())?;
+    let preset = match &options.preset {
+        Some(name) => Some(
+            rules
+                .presets
+                .iter()
+                .find(|preset| preset.name == *name)
+                .ok_or_else(|| format!("unknown preset `{}`", name))?,
+        ),
+        None => None,
+    };
+    let sizes = match (&options.players[..], preset) {
+        ([], Some(preset)) => preset
+            .compositions
+            .iter()
+            .map(|sized| sized.players)
+            .collect(),
+        ([], None) => STANDARD_SIZES.to_vec(),
+        (players, _) => players.to_vec(),
+    };
+    let seed = options.seed.unwrap_or_else(|| thread_rng().gen());
+    println!(
+        "{} games per table, astronauts playing {}, mutants {}, seed {}",
+        options.games, options.strategies.astronauts, options.strategies.mutants, seed
+    );
+    for players in sizes {
+        let (composition, variants) = match preset {
+            Some(preset) => (
+                preset
+                    .composition(players)
+                    .map_err(|why| why.to_string())?
+                    .clone(),
+                preset.variants.clone(),
+            ),
+            None => (Composition::standard(players), rules.variants.clone()),
+        };
+        // Each table size replays the same draws for a given seed.
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut statistics = Statistics::default();
+        for _ in 0..options.games {
+            let simulation = bot::simulate(
+                players,
+                &composition,
+                &variants,
+                options.strategies,
+                &mut rng,
+            )
+            .map_err(|why| format!("{} players: {}", players, why))?;
+            statistics.add(&simulation);
+        }
+        report(players, &composition, &statistics);
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = parse(env::args().skip(1)).unwrap_or_else(|why| {
+        eprintln!("{}\n{}", why, USAGE);
+        process::exit(2);
+    });
+    if let Err(why) = run(options) {
+        eprintln!("{}", why);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,411 @@
+use serde::Deserialize;
+use serenity::{
+    client::bridge::gateway::GatewayIntents,
+    model::id::{ChannelId, UserId},
+    prelude::TypeMapKey,
+};
+use std::{env, fs, io, net::SocketAddr, path::PathBuf};
+use thiserror::Error;
+
+use crate::{
+    sporz::{Preset, SporzError, Variants},
+    vote::{Rule, TieBreak},
+};
+
+const DEFAULT_PATH: &str = "sporz.toml";
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read {path}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("invalid configuration file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("no bot token: set `token` in the configuration file or DISCORD_TOKEN")]
+    MissingToken,
+    #[error("at least one command prefix is required")]
+    NoPrefix,
+    #[error("delimiters cannot be empty strings")]
+    EmptyDelimiter,
+    #[error("unknown gateway intent `{0}`")]
+    UnknownIntent(String),
+    #[error("unknown log rotation `{0}`, expected daily, hourly or never")]
+    UnknownRotation(String),
+    #[error("invalid Sporz preset `{name}`: {source}")]
+    InvalidPreset { name: String, source: SporzError },
+    #[error("the Sporz preset `{0}` is defined twice")]
+    DuplicatePreset(String),
+    #[error("invalid value `{value}` for {var}")]
+    InvalidEnv { var: &'static str, value: String },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub token: Option<String>,
+    pub prefixes: Vec<String>,
+    pub delimiters: Vec<String>,
+    /// Gateway intents by name, `all` enabling every intent.
+    pub intents: Vec<String>,
+    /// Owners in addition to the ones of the Discord application.
+    pub owners: Vec<UserId>,
+    /// When not empty, commands are only accepted in these channels.
+    pub allowed_channels: Vec<ChannelId>,
+    pub database_path: PathBuf,
+    pub features: Features,
+    pub logging: Logging,
+    pub metrics: Metrics,
+    pub sporz: Sporz,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Features {
+    pub presence: bool,
+    pub stats: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Logging {
+    /// Filter directives, overridden by `RUST_LOG`.
+    pub level: String,
+    /// Directory of the JSON log files, none when unset.
+    pub dir: Option<PathBuf>,
+    pub rotation: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metrics {
+    /// Only used when the bot is built with the `metrics` feature.
+    pub enabled: bool,
+    pub address: SocketAddr,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sporz {
+    /// Seconds the players have to choose their night actions.
+    pub night_seconds: u64,
+    /// Seconds the players have to elect the captain.
+    pub captain_seconds: u64,
+    /// Seconds the players have to vote an elimination.
+    pub day_seconds: u64,
+    pub vote_rule: Rule,
+    pub tie_break: TieBreak,
+    /// Variants of the tables playing the standard compositions.
+    pub variants: Variants,
+    pub presets: Vec<Preset>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            token: None,
+            prefixes: vec!["!".to_string()],
+            delimiters: vec![", ".to_string(), ",".to_string()],
+            intents: vec!["all".to_string()],
+            owners: Vec::new(),
+            allowed_channels: Vec::new(),
+            database_path: PathBuf::from("sporz.db"),
+            features: Features::default(),
+            logging: Logging::default(),
+            metrics: Metrics::default(),
+            sporz: Sporz::default(),
+        }
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            presence: true,
+            stats: true,
+        }
+    }
+}
+
+impl Default for Logging {
+    fn default() -> Self {
+        Logging {
+            level: "info".to_string(),
+            dir: None,
+            rotation: "daily".to_string(),
+        }
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            enabled: true,
+            address: ([127, 0, 0, 1], 9100).into(),
+        }
+    }
+}
+
+impl Default for Sporz {
+    fn default() -> Self {
+        Sporz {
+            night_seconds: 180,
+            captain_seconds: 180,
+            day_seconds: 600,
+            vote_rule: Rule::Plurality,
+            tie_break: TieBreak::Captain,
+            variants: Variants::default(),
+            presets: Vec::new(),
+        }
+    }
+}
+
+impl TypeMapKey for Config {
+    type Value = std::sync::Arc<Config>;
+}
+
+fn intent(name: &str) -> Option<GatewayIntents> {
+    Some(match name {
+        "all" => GatewayIntents::all(),
+        "guilds" => GatewayIntents::GUILDS,
+        "guild_members" => GatewayIntents::GUILD_MEMBERS,
+        "guild_bans" => GatewayIntents::GUILD_BANS,
+        "guild_emojis" => GatewayIntents::GUILD_EMOJIS,
+        "guild_integrations" => GatewayIntents::GUILD_INTEGRATIONS,
+        "guild_webhooks" => GatewayIntents::GUILD_WEBHOOKS,
+        "guild_invites" => GatewayIntents::GUILD_INVITES,
+        "guild_voice_states" => GatewayIntents::GUILD_VOICE_STATES,
+        "guild_presences" => GatewayIntents::GUILD_PRESENCES,
+        "guild_messages" => GatewayIntents::GUILD_MESSAGES,
+        "guild_message_reactions" => GatewayIntents::GUILD_MESSAGE_REACTIONS,
+        "guild_message_typing" => GatewayIntents::GUILD_MESSAGE_TYPING,
+        "direct_messages" => GatewayIntents::DIRECT_MESSAGES,
+        "direct_message_reactions" => GatewayIntents::DIRECT_MESSAGE_REACTIONS,
+        "direct_message_typing" => GatewayIntents::DIRECT_MESSAGE_TYPING,
+        _ => return None,
+    })
+}
+
+fn env_var(var: &'static str) -> Option<String> {
+    env::var(var).ok().filter(|value| !value.is_empty())
+}
+
+fn env_list(var: &'static str) -> Option<Vec<String>> {
+    env_var(var).map(|value| value.split(',').map(|s| s.trim().to_string()).collect())
+}
+
+fn env_parse<T: std::str::FromStr>(var: &'static str) -> Result<Option<T>, ConfigError> {
+    match env_var(var) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::InvalidEnv { var, value }),
+        None => Ok(None),
+    }
+}
+
+impl Config {
+    /// Reads the file named by `SPORZ_CONFIG` (`sporz.toml` by default, which
+    /// may be missing), applies the environment overrides and validates the
+    /// result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let (path, required) = match env_var("SPORZ_CONFIG") {
+            Some(path) => (PathBuf::from(path), true),
+            None => (PathBuf::from(DEFAULT_PATH), false),
+        };
+        let mut config: Config = match fs::read_to_string(&path) {
+            Ok(content) => toml::from_str(&content)?,
+            Err(why) if why.kind() == io::ErrorKind::NotFound && !required => Config::default(),
+            Err(source) => return Err(ConfigError::Io { path, source }),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(token) = env_var("DISCORD_TOKEN") {
+            self.token = Some(token);
+        }
+        if let Some(prefixes) = env_list("SPORZ_PREFIXES") {
+            self.prefixes = prefixes;
+        }
+        if let Some(intents) = env_list("SPORZ_INTENTS") {
+            self.intents = intents;
+        }
+        if let Some(path) = env_var("SPORZ_DATABASE_PATH") {
+            self.database_path = PathBuf::from(path);
+        }
+        if let Some(level) = env_var("SPORZ_LOG_LEVEL") {
+            self.logging.level = level;
+        }
+        if let Some(dir) = env_var("SPORZ_LOG_DIR") {
+            self.logging.dir = Some(PathBuf::from(dir));
+        }
+        if let Some(rotation) = env_var("SPORZ_LOG_ROTATION") {
+            self.logging.rotation = rotation;
+        }
+        if let Some(address) = env_parse("SPORZ_METRICS_ADDRESS")? {
+            self.metrics.address = address;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.token.as_deref().map_or(true, str::is_empty) {
+            return Err(ConfigError::MissingToken);
+        }
+        if self.prefixes.iter().all(|prefix| prefix.is_empty()) {
+            return Err(ConfigError::NoPrefix);
+        }
+        if self.delimiters.iter().any(|delimiter| delimiter.is_empty()) {
+            return Err(ConfigError::EmptyDelimiter);
+        }
+        self.gateway_intents()?;
+        for (index, preset) in self.sporz.presets.iter().enumerate() {
+            if self.sporz.presets[..index]
+                .iter()
+                .any(|other| other.name == preset.name)
+            {
+                return Err(ConfigError::DuplicatePreset(preset.name.clone()));
+            }
+            preset
+                .validate()
+                .map_err(|source| ConfigError::InvalidPreset {
+                    name: preset.name.clone(),
+                    source,
+                })?;
+        }
+        match self.logging.rotation.as_str() {
+            "daily" | "hourly" | "never" => Ok(()),
+            rotation => Err(ConfigError::UnknownRotation(rotation.to_string())),
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        self.token.as_deref().unwrap_or_default()
+    }
+
+    pub fn gateway_intents(&self) -> Result<GatewayIntents, ConfigError> {
+        self.intents
+            .iter()
+            .try_fold(GatewayIntents::empty(), |intents, name| {
+                intent(&name.to_lowercase())
+                    .map(|intent| intents | intent)
+                    .ok_or_else(|| ConfigError::UnknownIntent(name.clone()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Config {
+        Config {
+            token: Some("token".to_string()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn defaults_only_lack_a_token() {
+        assert!(matches!(
+            Config::default().validate(),
+            Err(ConfigError::MissingToken)
+        ));
+        let config = Config {
+            token: Some(String::new()),
+            ..Config::default()
+        };
+        assert!(matches!(config.validate(), Err(ConfigError::MissingToken)));
+        assert!(valid().validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut config = valid();
+        config.prefixes = vec![String::new()];
+        assert!(matches!(config.validate(), Err(ConfigError::NoPrefix)));
+
+        let mut config = valid();
+        config.delimiters.push(String::new());
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::EmptyDelimiter)
+        ));
+
+        let mut config = valid();
+        config.intents = vec!["guilds".to_string(), "typing".to_string()];
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::UnknownIntent(name)) if name == "typing"
+        ));
+
+        let mut config = valid();
+        config.logging.rotation = "weekly".to_string();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::UnknownRotation(_))
+        ));
+    }
+
+    #[test]
+    fn intents_are_combined_case_insensitively() {
+        let mut config = valid();
+        config.intents = vec!["GUILDS".to_string(), "guild_messages".to_string()];
+        assert_eq!(
+            config.gateway_intents().unwrap(),
+            GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES
+        );
+    }
+
+    #[test]
+    fn files_are_parsed_with_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+            token = "abc"
+            prefixes = ["?"]
+            [features]
+            presence = false
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.token(), "abc");
+        assert_eq!(config.prefixes, vec!["?"]);
+        assert!(!config.features.presence);
+        assert!(config.features.stats);
+        assert_eq!(config.logging.rotation, "daily");
+        assert!(toml::from_str::<Config>("unknown = 1").is_err());
+    }
+
+    // The only test touching these variables, so it cannot race with another.
+    #[test]
+    fn environment_overrides_the_file() {
+        env::set_var("DISCORD_TOKEN", "from-env");
+        env::set_var("SPORZ_PREFIXES", "?, ;");
+        env::set_var("SPORZ_LOG_DIR", "");
+        env::set_var("SPORZ_METRICS_ADDRESS", "0.0.0.0:9000");
+        let mut config = valid();
+        config.apply_env().unwrap();
+        assert_eq!(config.token(), "from-env");
+        assert_eq!(config.prefixes, vec!["?", ";"]);
+        assert_eq!(config.logging.dir, None);
+        assert_eq!(config.metrics.address, ([0, 0, 0, 0], 9000).into());
+
+        env::set_var("SPORZ_METRICS_ADDRESS", "nowhere");
+        assert!(matches!(
+            config.apply_env(),
+            Err(ConfigError::InvalidEnv {
+                var: "SPORZ_METRICS_ADDRESS",
+                ..
+            })
+        ));
+        for var in [
+            "DISCORD_TOKEN",
+            "SPORZ_PREFIXES",
+            "SPORZ_LOG_DIR",
+            "SPORZ_METRICS_ADDRESS",
+        ] {
+            env::remove_var(var);
+        }
+    }
+}
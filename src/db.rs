@@ -0,0 +1,102 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serenity::{client::Context, prelude::TypeMapKey};
+use std::{convert::TryInto, path::Path};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DbError {
+    #[error("database error: {0}")]
+    Sled(#[from] sled::Error),
+    #[error("could not (de)serialize a database entry: {0}")]
+    Serde(#[from] serde_json::Error),
+}
+
+pub type DbResult<T> = Result<T, DbError>;
+
+pub struct Database;
+
+impl TypeMapKey for Database {
+    type Value = sled::Db;
+}
+
+pub fn open(path: impl AsRef<Path>) -> DbResult<sled::Db> {
+    Ok(sled::open(path)?)
+}
+
+pub fn insert<V: Serialize>(tree: &sled::Tree, key: impl AsRef<[u8]>, value: &V) -> DbResult<()> {
+    tree.insert(key.as_ref(), serde_json::to_vec(value)?)?;
+    Ok(())
+}
+
+pub fn get<V: DeserializeOwned>(tree: &sled::Tree, key: impl AsRef<[u8]>) -> DbResult<Option<V>> {
+    match tree.get(key.as_ref())? {
+        Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes every entry whose key starts with `prefix`, in key order.
+pub fn scan_prefix<V: DeserializeOwned>(
+    tree: &sled::Tree,
+    prefix: impl AsRef<[u8]>,
+) -> DbResult<Vec<V>> {
+    tree.scan_prefix(prefix.as_ref())
+        .values()
+        .map(|bytes| -> DbResult<V> { Ok(serde_json::from_slice(&bytes?)?) })
+        .collect()
+}
+
+/// Deserializes the first entry whose key starts with `prefix`.
+pub fn first_with_prefix<V: DeserializeOwned>(
+    tree: &sled::Tree,
+    prefix: impl AsRef<[u8]>,
+) -> DbResult<Option<V>> {
+    match tree.scan_prefix(prefix.as_ref()).next() {
+        Some(entry) => Ok(Some(serde_json::from_slice(&entry?.1)?)),
+        None => Ok(None),
+    }
+}
+
+/// Deserializes the last entry whose key starts with `prefix`.
+pub fn last_with_prefix<V: DeserializeOwned>(
+    tree: &sled::Tree,
+    prefix: impl AsRef<[u8]>,
+) -> DbResult<Option<V>> {
+    match tree.scan_prefix(prefix.as_ref()).next_back() {
+        Some(entry) => Ok(Some(serde_json::from_slice(&entry?.1)?)),
+        None => Ok(None),
+    }
+}
+
+pub async fn from_context(ctx: &Context) -> sled::Db {
+    ctx.data
+        .read()
+        .await
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.")
+}
+
+fn decode_counter(bytes: &[u8]) -> u64 {
+    bytes.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
+
+/// Atomically increments the counter stored at `key`.
+pub fn increment(tree: &sled::Tree, key: impl AsRef<[u8]>) -> DbResult<u64> {
+    let value = tree.update_and_fetch(key.as_ref(), |old| {
+        let count = old.map_or(0, decode_counter) + 1;
+        Some(count.to_be_bytes().to_vec())
+    })?;
+    Ok(value.map_or(0, |bytes| decode_counter(&bytes)))
+}
+
+/// Counters whose key starts with `prefix`, keyed by the rest of their key.
+pub fn counters(tree: &sled::Tree, prefix: &str) -> DbResult<Vec<(String, u64)>> {
+    tree.scan_prefix(prefix)
+        .map(|entry| -> DbResult<(String, u64)> {
+            let (key, value) = entry?;
+            let key = String::from_utf8_lossy(&key[prefix.len()..]).into_owned();
+            Ok((key, decode_counter(&value)))
+        })
+        .collect()
+}
@@ -0,0 +1,732 @@
+//! Arithmetic expressions with dice, as used by `add`.
+//!
+//! ```text
+//! expr   := term (('+' | '-') term)*
+//! term   := factor (('*' | '/') factor)*
+//! factor := ('+' | '-') factor | atom
+//! atom   := number | dice | 'adv' | 'dis' | '(' expr ')'
+//! dice   := [count] 'd' sides ('k' ['h' | 'l'] [n] | '!' | 'r' n)*
+//! ```
+//!
+//! `k` keeps the highest (or lowest) dice, `!` rerolls a maximal result and
+//! adds it, `r` rerolls once the results lower or equal to `n`. `adv` and
+//! `dis` are the advantage and disadvantage rolls, `2d20kh1` and `2d20kl1`.
+
+use rand::Rng;
+use std::fmt;
+use thiserror::Error;
+
+const MAX_DICE: u32 = 100;
+const MAX_SIDES: u32 = 1000;
+/// Nested parentheses and signs, bounded so that the recursion cannot
+/// overflow the stack.
+const MAX_DEPTH: usize = 32;
+/// Exploding dice are rerolled at most this many times per roll.
+const MAX_EXPLOSIONS: u32 = 100;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExprError {
+    #[error("expected {expected} at column {column}, found `{found}`")]
+    Unexpected {
+        column: usize,
+        found: char,
+        expected: &'static str,
+    },
+    #[error("expected {expected} at the end of the expression")]
+    UnexpectedEnd { expected: &'static str },
+    #[error("invalid number `{text}` at column {column}")]
+    InvalidNumber { column: usize, text: String },
+    #[error("at most {} dice can be rolled at once (column {column})", MAX_DICE)]
+    TooManyDice { column: usize },
+    #[error("dice must have between 1 and {} sides (column {column})", MAX_SIDES)]
+    InvalidSides { column: usize },
+    #[error("division by zero at column {column}")]
+    DivisionByZero { column: usize },
+    #[error(
+        "expressions cannot be nested more than {} levels deep (column {column})",
+        MAX_DEPTH
+    )]
+    TooDeep { column: usize },
+    #[error("cannot keep {keep} dice out of {count} (column {column})")]
+    InvalidKeep {
+        column: usize,
+        keep: u32,
+        count: u32,
+    },
+    #[error("rerolling results up to {limit} on a d{sides} would never end (column {column})")]
+    InvalidReroll {
+        column: usize,
+        limit: u32,
+        sides: u32,
+    },
+    #[error("a d1 cannot explode (column {column})")]
+    InvalidExplosion { column: usize },
+    #[error("`{modifier}` is given twice (column {column})")]
+    DuplicateModifier { column: usize, modifier: char },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keep {
+    Highest(u32),
+    Lowest(u32),
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Modifiers {
+    pub keep: Option<Keep>,
+    pub explode: bool,
+    /// Results lower or equal to this are rerolled once.
+    pub reroll: Option<u32>,
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.keep {
+            Some(Keep::Highest(n)) => write!(f, "kh{}", n)?,
+            Some(Keep::Lowest(n)) => write!(f, "kl{}", n)?,
+            None => {}
+        }
+        if self.explode {
+            f.write_str("!")?;
+        }
+        if let Some(limit) = self.reroll {
+            write!(f, "r{}", limit)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single die of a roll.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Die {
+    pub value: u32,
+    /// The first result, when it was rerolled.
+    pub rerolled: Option<u32>,
+    /// Whether this die was added by an explosion.
+    pub exploded: bool,
+    /// Whether the value counts in the total.
+    pub kept: bool,
+}
+
+impl fmt::Display for Die {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = match self.rerolled {
+            Some(first) => format!("{}→{}", first, self.value),
+            None => self.value.to_string(),
+        };
+        let marker = if self.exploded { "!" } else { "" };
+        if self.kept {
+            write!(f, "{}{}", value, marker)
+        } else {
+            write!(f, "~~{}{}~~", value, marker)
+        }
+    }
+}
+
+/// The dice rolled while evaluating an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiceRoll {
+    pub count: u32,
+    pub sides: u32,
+    pub modifiers: Modifiers,
+    pub dice: Vec<Die>,
+}
+
+impl DiceRoll {
+    pub fn total(&self) -> u32 {
+        self.dice
+            .iter()
+            .filter(|die| die.kept)
+            .map(|die| die.value)
+            .sum()
+    }
+}
+
+impl fmt::Display for DiceRoll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dice: Vec<String> = self.dice.iter().map(Die::to_string).collect();
+        write!(
+            f,
+            "{}d{}{} [{}]",
+            self.count,
+            self.sides,
+            self.modifiers,
+            dice.join(", ")
+        )
+    }
+}
+
+/// Rolls `count` dice, modifiers being already validated.
+pub fn roll_dice<R: Rng>(count: u32, sides: u32, modifiers: Modifiers, rng: &mut R) -> DiceRoll {
+    let mut dice = Vec::new();
+    let mut pending = count;
+    let mut explosions = 0;
+    while pending > 0 {
+        pending -= 1;
+        let mut value = rng.gen_range(1..=sides);
+        let mut rerolled = None;
+        if modifiers.reroll.is_some_and(|limit| value <= limit) {
+            rerolled = Some(value);
+            value = rng.gen_range(1..=sides);
+        }
+        if modifiers.explode && value == sides && explosions < MAX_EXPLOSIONS {
+            explosions += 1;
+            pending += 1;
+        }
+        dice.push(Die {
+            value,
+            rerolled,
+            exploded: dice.len() as u32 >= count,
+            kept: true,
+        });
+    }
+    if let Some(keep) = modifiers.keep {
+        let mut order: Vec<usize> = (0..dice.len()).collect();
+        let kept = match keep {
+            Keep::Highest(n) => {
+                order.sort_by_key(|&i| std::cmp::Reverse(dice[i].value));
+                n
+            }
+            Keep::Lowest(n) => {
+                order.sort_by_key(|&i| dice[i].value);
+                n
+            }
+        };
+        for &i in order.iter().skip(kept as usize) {
+            dice[i].kept = false;
+        }
+    }
+    DiceRoll {
+        count,
+        sides,
+        modifiers,
+        dice,
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    pub value: f64,
+    pub rolls: Vec<DiceRoll>,
+}
+
+impl Evaluation {
+    /// `input` with its value and the dice rolled, on one line.
+    pub fn summary(&self, input: &str) -> String {
+        let mut summary = format!("`{}` = {}", input, format_value(self.value));
+        for roll in &self.rolls {
+            summary += &format!(" — {}", roll);
+        }
+        summary
+    }
+}
+
+/// Formats a value without a trailing `.0` when it is an integer.
+pub fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", (value * 1e6).round() / 1e6)
+    }
+}
+
+struct Parser<'a, R> {
+    /// Non blank characters with their index in the input.
+    chars: Vec<(usize, char)>,
+    end: usize,
+    position: usize,
+    depth: usize,
+    rng: &'a mut R,
+    rolls: Vec<DiceRoll>,
+}
+
+impl<'a, R: Rng> Parser<'a, R> {
+    fn peek(&self) -> Option<(usize, char)> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Consumes `word` if the input continues with it, ignoring the case.
+    fn keyword(&mut self, word: &str) -> bool {
+        let matches = word.chars().enumerate().all(|(offset, expected)| {
+            self.chars
+                .get(self.position + offset)
+                .is_some_and(|(_, c)| c.eq_ignore_ascii_case(&expected))
+        });
+        if matches {
+            self.position += word.chars().count();
+        }
+        matches
+    }
+
+    fn column(&self) -> usize {
+        self.peek().map_or(self.end, |(column, _)| column) + 1
+    }
+
+    fn expect(&mut self, expected_char: char, expected: &'static str) -> Result<(), ExprError> {
+        match self.peek() {
+            Some((_, c)) if c == expected_char => {
+                self.position += 1;
+                Ok(())
+            }
+            Some((column, found)) => Err(ExprError::Unexpected {
+                column: column + 1,
+                found,
+                expected,
+            }),
+            None => Err(ExprError::UnexpectedEnd { expected }),
+        }
+    }
+
+    /// Runs `parse` one nesting level deeper.
+    fn nested(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<f64, ExprError>,
+    ) -> Result<f64, ExprError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ExprError::TooDeep {
+                column: self.column(),
+            });
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn expr(&mut self) -> Result<f64, ExprError> {
+        let mut value = self.term()?;
+        while let Some((_, c)) = self.peek() {
+            match c {
+                '+' => {
+                    self.position += 1;
+                    value += self.term()?;
+                }
+                '-' => {
+                    self.position += 1;
+                    value -= self.term()?;
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn term(&mut self) -> Result<f64, ExprError> {
+        let mut value = self.factor()?;
+        while let Some((_, c)) = self.peek() {
+            match c {
+                '*' | 'x' | '×' => {
+                    self.position += 1;
+                    value *= self.factor()?;
+                }
+                '/' => {
+                    self.position += 1;
+                    let column = self.column();
+                    let divisor = self.factor()?;
+                    if divisor == 0.0 {
+                        return Err(ExprError::DivisionByZero { column });
+                    }
+                    value /= divisor;
+                }
+                _ => break,
+            }
+        }
+        Ok(value)
+    }
+
+    fn factor(&mut self) -> Result<f64, ExprError> {
+        match self.peek() {
+            Some((_, '-')) => {
+                self.position += 1;
+                Ok(-self.nested(Self::factor)?)
+            }
+            Some((_, '+')) => {
+                self.position += 1;
+                self.nested(Self::factor)
+            }
+            _ => self.atom(),
+        }
+    }
+
+    fn atom(&mut self) -> Result<f64, ExprError> {
+        const EXPECTED: &str = "a number, a dice or `(`";
+        match self.peek() {
+            Some((_, '(')) => {
+                self.position += 1;
+                let value = self.nested(Self::expr)?;
+                self.expect(')', "`)`")?;
+                Ok(value)
+            }
+            Some(_) if self.keyword("adv") => Ok(self.advantage(Keep::Highest(1))),
+            Some(_) if self.keyword("dis") => Ok(self.advantage(Keep::Lowest(1))),
+            Some((_, 'd')) | Some((_, 'D')) => self.dice(1),
+            Some((_, c)) if c.is_ascii_digit() || c == '.' => {
+                let column = self.column();
+                let text = self.number_text();
+                if let Some((_, 'd')) | Some((_, 'D')) = self.peek() {
+                    let count = text
+                        .parse::<u32>()
+                        .map_err(|_| ExprError::InvalidNumber { column, text })?;
+                    if count > MAX_DICE {
+                        return Err(ExprError::TooManyDice { column });
+                    }
+                    return self.dice(count);
+                }
+                text.parse::<f64>()
+                    .map_err(|_| ExprError::InvalidNumber { column, text })
+            }
+            Some((column, found)) => Err(ExprError::Unexpected {
+                column: column + 1,
+                found,
+                expected: EXPECTED,
+            }),
+            None => Err(ExprError::UnexpectedEnd { expected: EXPECTED }),
+        }
+    }
+
+    fn number_text(&mut self) -> String {
+        let mut text = String::new();
+        while let Some((_, c)) = self.peek() {
+            if !c.is_ascii_digit() && c != '.' {
+                break;
+            }
+            text.push(c);
+            self.position += 1;
+        }
+        text
+    }
+
+    fn advantage(&mut self, keep: Keep) -> f64 {
+        let modifiers = Modifiers {
+            keep: Some(keep),
+            ..Modifiers::default()
+        };
+        let roll = roll_dice(2, 20, modifiers, self.rng);
+        let total = roll.total();
+        self.rolls.push(roll);
+        total as f64
+    }
+
+    /// Parses an optional count following a modifier.
+    fn modifier_count(&mut self) -> Result<Option<u32>, ExprError> {
+        let column = self.column();
+        let text = self.number_text();
+        if text.is_empty() {
+            return Ok(None);
+        }
+        text.parse()
+            .map(Some)
+            .map_err(|_| ExprError::InvalidNumber { column, text })
+    }
+
+    fn modifiers(&mut self, count: u32, sides: u32) -> Result<Modifiers, ExprError> {
+        let mut modifiers = Modifiers::default();
+        while let Some((index, c)) = self.peek() {
+            let column = index + 1;
+            let duplicate = ExprError::DuplicateModifier {
+                column,
+                modifier: c,
+            };
+            match c.to_ascii_lowercase() {
+                'k' => {
+                    self.position += 1;
+                    if modifiers.keep.is_some() {
+                        return Err(duplicate);
+                    }
+                    let lowest = self.keyword("l");
+                    if !lowest {
+                        self.keyword("h");
+                    }
+                    let keep = self.modifier_count()?.unwrap_or(1);
+                    if keep == 0 || keep > count {
+                        return Err(ExprError::InvalidKeep {
+                            column,
+                            keep,
+                            count,
+                        });
+                    }
+                    modifiers.keep = Some(if lowest {
+                        Keep::Lowest(keep)
+                    } else {
+                        Keep::Highest(keep)
+                    });
+                }
+                '!' => {
+                    self.position += 1;
+                    if modifiers.explode {
+                        return Err(duplicate);
+                    }
+                    if sides == 1 {
+                        return Err(ExprError::InvalidExplosion { column });
+                    }
+                    modifiers.explode = true;
+                }
+                'r' => {
+                    self.position += 1;
+                    if modifiers.reroll.is_some() {
+                        return Err(duplicate);
+                    }
+                    let limit = self.modifier_count()?.unwrap_or(1);
+                    if limit >= sides {
+                        return Err(ExprError::InvalidReroll {
+                            column,
+                            limit,
+                            sides,
+                        });
+                    }
+                    modifiers.reroll = Some(limit);
+                }
+                _ => break,
+            }
+        }
+        Ok(modifiers)
+    }
+
+    /// Parses the `d<sides>` part of a dice, the count being already read.
+    fn dice(&mut self, count: u32) -> Result<f64, ExprError> {
+        self.position += 1;
+        let column = self.column();
+        let text = self.number_text();
+        if text.is_empty() {
+            return match self.peek() {
+                Some((column, found)) => Err(ExprError::Unexpected {
+                    column: column + 1,
+                    found,
+                    expected: "a number of sides",
+                }),
+                None => Err(ExprError::UnexpectedEnd {
+                    expected: "a number of sides",
+                }),
+            };
+        }
+        let sides = text
+            .parse::<u32>()
+            .map_err(|_| ExprError::InvalidNumber { column, text })?;
+        if sides == 0 || sides > MAX_SIDES {
+            return Err(ExprError::InvalidSides { column });
+        }
+        let modifiers = self.modifiers(count, sides)?;
+        let roll = roll_dice(count, sides, modifiers, self.rng);
+        let total = roll.total();
+        self.rolls.push(roll);
+        Ok(total as f64)
+    }
+}
+
+/// Evaluates `input`, rolling its dice with `rng`.
+pub fn evaluate<R: Rng>(input: &str, rng: &mut R) -> Result<Evaluation, ExprError> {
+    let mut parser = Parser {
+        chars: input
+            .chars()
+            .enumerate()
+            .filter(|(_, c)| !c.is_whitespace())
+            .collect(),
+        end: input.chars().count(),
+        position: 0,
+        depth: 0,
+        rng,
+        rolls: Vec::new(),
+    };
+    let value = parser.expr()?;
+    if let Some((column, found)) = parser.peek() {
+        return Err(ExprError::Unexpected {
+            column: column + 1,
+            found,
+            expected: "an operator",
+        });
+    }
+    Ok(Evaluation {
+        value,
+        rolls: parser.rolls,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::mock::StepRng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    fn value(input: &str) -> f64 {
+        evaluate(input, &mut ChaCha8Rng::seed_from_u64(0))
+            .unwrap()
+            .value
+    }
+
+    fn error(input: &str) -> ExprError {
+        evaluate(input, &mut ChaCha8Rng::seed_from_u64(0)).unwrap_err()
+    }
+
+    fn single_roll(input: &str, seed: u64) -> DiceRoll {
+        let mut evaluation = evaluate(input, &mut ChaCha8Rng::seed_from_u64(seed)).unwrap();
+        assert_eq!(evaluation.rolls.len(), 1);
+        evaluation.rolls.remove(0)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(value("1 + 2 * 3"), 7.0);
+        assert_eq!(value("(1 + 2) * 3"), 9.0);
+        assert_eq!(value("2 - 3 - 4"), -5.0);
+        assert_eq!(value("12 / 3 / 2"), 2.0);
+        assert_eq!(value("-2 * 3 + 10 / 4"), -3.5);
+        assert_eq!(value("2 x 3 × 4"), 24.0);
+        assert_eq!(value("--1"), 1.0);
+    }
+
+    #[test]
+    fn dice_values_are_in_range() {
+        for seed in 0..50 {
+            let roll = single_roll("3d6", seed);
+            assert_eq!(roll.dice.len(), 3);
+            assert!(roll.dice.iter().all(|die| (1..=6).contains(&die.value)));
+            assert!((3..=18).contains(&roll.total()));
+        }
+    }
+
+    #[test]
+    fn keep_highest_and_lowest() {
+        for seed in 0..50 {
+            let roll = single_roll("4d6kh3", seed);
+            let mut values: Vec<u32> = roll.dice.iter().map(|die| die.value).collect();
+            values.sort_unstable();
+            assert_eq!(roll.dice.iter().filter(|die| die.kept).count(), 3);
+            assert_eq!(roll.total(), values[1..].iter().sum::<u32>());
+
+            let roll = single_roll("4d6kl1", seed);
+            let lowest = roll.dice.iter().map(|die| die.value).min().unwrap();
+            assert_eq!(roll.dice.iter().filter(|die| die.kept).count(), 1);
+            assert_eq!(roll.total(), lowest);
+        }
+        assert_eq!(single_roll("adv", 0).dice.len(), 2);
+        assert_eq!(single_roll("dis", 0).modifiers.keep, Some(Keep::Lowest(1)));
+    }
+
+    #[test]
+    fn reroll_only_low_results_once() {
+        for seed in 0..50 {
+            let roll = single_roll("10d6r2", seed);
+            assert_eq!(roll.dice.len(), 10);
+            for die in &roll.dice {
+                match die.rerolled {
+                    Some(first) => assert!(first <= 2),
+                    None => assert!(die.value > 2),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn explosions_are_capped() {
+        // Always draws the highest side of a d2.
+        let mut rng = StepRng::new(1 << 31, 0);
+        let modifiers = Modifiers {
+            explode: true,
+            ..Modifiers::default()
+        };
+        let roll = roll_dice(1, 2, modifiers, &mut rng);
+        assert_eq!(roll.dice.len() as u32, 1 + MAX_EXPLOSIONS);
+        assert!(!roll.dice[0].exploded);
+        assert!(roll.dice[1..].iter().all(|die| die.exploded));
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(error("101d6"), ExprError::TooManyDice { column: 1 });
+        assert_eq!(error("2d1001"), ExprError::InvalidSides { column: 3 });
+        assert_eq!(error("d0"), ExprError::InvalidSides { column: 2 });
+        assert_eq!(
+            error("2d6k3"),
+            ExprError::InvalidKeep {
+                column: 4,
+                keep: 3,
+                count: 2
+            }
+        );
+        assert_eq!(
+            error("d6r6"),
+            ExprError::InvalidReroll {
+                column: 3,
+                limit: 6,
+                sides: 6
+            }
+        );
+        assert_eq!(error("3d1!"), ExprError::InvalidExplosion { column: 4 });
+        assert_eq!(
+            error("d6!!"),
+            ExprError::DuplicateModifier {
+                column: 4,
+                modifier: '!'
+            }
+        );
+    }
+
+    #[test]
+    fn values_are_formatted_without_trailing_zeros() {
+        assert_eq!(format_value(3.0), "3");
+        assert_eq!(format_value(-0.5), "-0.5");
+        assert_eq!(format_value(1.0 / 3.0), "0.333333");
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = format!("{}1{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH));
+        assert_eq!(value(&nested), 1.0);
+        let too_deep = format!("({}", nested);
+        assert_eq!(
+            error(&too_deep),
+            ExprError::TooDeep {
+                column: MAX_DEPTH + 2
+            }
+        );
+        assert!(matches!(
+            error(&"(".repeat(100_000)),
+            ExprError::TooDeep { .. }
+        ));
+        assert!(matches!(
+            error(&"-".repeat(100_000)),
+            ExprError::TooDeep { .. }
+        ));
+    }
+
+    #[test]
+    fn error_columns() {
+        assert_eq!(
+            error("1 + * 2"),
+            ExprError::Unexpected {
+                column: 5,
+                found: '*',
+                expected: "a number, a dice or `(`"
+            }
+        );
+        assert_eq!(
+            error("1 (2)"),
+            ExprError::Unexpected {
+                column: 3,
+                found: '(',
+                expected: "an operator"
+            }
+        );
+        assert_eq!(
+            error("4 / (2 - 2)"),
+            ExprError::DivisionByZero { column: 5 }
+        );
+        assert_eq!(
+            error("(1 + 2"),
+            ExprError::UnexpectedEnd { expected: "`)`" }
+        );
+        assert_eq!(
+            error("2d"),
+            ExprError::UnexpectedEnd {
+                expected: "a number of sides"
+            }
+        );
+        assert_eq!(
+            error("1..2"),
+            ExprError::InvalidNumber {
+                column: 1,
+                text: "1..2".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,4 @@
+//! The Sporz engine, shared by the bot and the balance simulator. It does not
+//! depend on Discord.
+
+pub mod sporz;
@@ -0,0 +1,35 @@
+use tracing_appender::{non_blocking::WorkerGuard, rolling};
+use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Registry};
+
+use crate::config::Logging;
+
+/// Installs the global subscriber. Levels are filtered with `RUST_LOG`, or the
+/// configured level when it is unset, and when a log directory is configured
+/// events are also written there as JSON, in rotated files.
+///
+/// The returned guard flushes the log file when dropped, it must be kept
+/// alive until the end of `main`.
+pub fn init(config: &Logging) -> Option<WorkerGuard> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level));
+    let subscriber = Registry::default().with(filter).with(fmt::layer());
+    match &config.dir {
+        Some(dir) => {
+            let appender = match config.rotation.as_str() {
+                "hourly" => rolling::hourly(dir, "sporz_bot.log"),
+                "never" => rolling::never(dir, "sporz_bot.log"),
+                _ => rolling::daily(dir, "sporz_bot.log"),
+            };
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let subscriber = subscriber.with(fmt::layer().json().with_writer(writer));
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("Could not install the log subscriber");
+            Some(guard)
+        }
+        None => {
+            tracing::subscriber::set_global_default(subscriber)
+                .expect("Could not install the log subscriber");
+            None
+        }
+    }
+}
@@ -1,137 +1,344 @@
 use serenity::{
     async_trait,
     framework::standard::macros::*,
-    model::{event::PresenceUpdateEvent, prelude::Ready},
+    model::{
+        event::PresenceUpdateEvent,
+        guild::{Guild, GuildUnavailable},
+        interactions::Interaction,
+        prelude::Ready,
+    },
 };
 use serenity::{
-    client::bridge::gateway::GatewayIntents,
     client::{Client, Context, EventHandler},
     framework::standard::help_commands,
     framework::standard::{Args, CommandGroup, HelpOptions},
     model::id::UserId,
 };
+use serenity::{constants::MESSAGE_CODE_LIMIT, http::Http, model::channel::Message};
 use serenity::{
     framework::standard::{
         macros::{command, group},
         CommandResult, StandardFramework,
     },
+    model::id::MessageId,
     prelude::TypeMapKey,
 };
-use serenity::{http::Http, model::channel::Message};
 
+use chrono::Utc;
 use std::{
     collections::{HashMap, HashSet},
-    env,
-    time::Instant,
+    fmt::Display,
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use tracing::{debug, error, field, info, info_span, warn, Span};
+
+mod config;
+mod db;
+mod expr;
+mod logging;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod presence;
+mod random;
+mod scheduler;
+mod settings;
+mod slash;
+mod stats;
+mod table;
+mod vote;
+
+use config::Config;
+use db::Database;
+use presence::{OnlineTracker, PRESENCE_GROUP};
+use random::RANDOM_GROUP;
+use scheduler::{Scheduler, REMINDERS_GROUP};
+use settings::SETTINGS_GROUP;
+use sporz_bot::sporz;
+use stats::{CommandCounter, STATS_GROUP};
+use table::{Profiles, Tables, GAMEMASTER_GROUP, SPORZ_GROUP};
+use vote::{Polls, VOTING_GROUP};
 
 #[group]
-#[commands(add, whosonline)]
+#[commands(add)]
 struct General;
-struct CommandCounter;
 
-impl TypeMapKey for CommandCounter {
-    type Value = HashMap<String, u64>;
+struct CommandTiming {
+    start: Instant,
+    span: Span,
 }
 
-struct OnlineTracker;
+/// Commands being processed, keyed by invoking message.
+struct CommandTimings;
 
-impl TypeMapKey for OnlineTracker {
-    type Value = HashMap<UserId, std::time::Instant>;
+impl TypeMapKey for CommandTimings {
+    type Value = HashMap<MessageId, CommandTiming>;
+}
+
+/// Every command group, whether enabled or not.
+pub static GROUPS: &[&CommandGroup] = &[
+    &GENERAL_GROUP,
+    &RANDOM_GROUP,
+    &SPORZ_GROUP,
+    &GAMEMASTER_GROUP,
+    &VOTING_GROUP,
+    &REMINDERS_GROUP,
+    &SETTINGS_GROUP,
+    &PRESENCE_GROUP,
+    &STATS_GROUP,
+];
+
+fn enabled_groups(config: &Config) -> Vec<&'static CommandGroup> {
+    let mut groups = vec![
+        &GENERAL_GROUP,
+        &RANDOM_GROUP,
+        &SPORZ_GROUP,
+        &GAMEMASTER_GROUP,
+        &VOTING_GROUP,
+        &REMINDERS_GROUP,
+        &SETTINGS_GROUP,
+    ];
+    if config.features.presence {
+        groups.push(&PRESENCE_GROUP);
+    }
+    if config.features.stats {
+        groups.push(&STATS_GROUP);
+    }
+    groups
+}
+
+/// Startup failures are reported without a backtrace, as they are almost
+/// always configuration mistakes.
+fn exit_with(context: &str, why: impl Display) -> ! {
+    error!("{}: {}", context, why);
+    eprintln!("{}: {}", context, why);
+    process::exit(1)
+}
+
+struct Handler {
+    track_presences: bool,
 }
-struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    // As the intents set in this example, this event shall never be dispatched.
-    // Try it by changing your status.
     async fn presence_update(&self, ctx: Context, new_data: PresenceUpdateEvent) {
+        let guild_id = match new_data.guild_id {
+            Some(guild_id) if self.track_presences => guild_id,
+            _ => return,
+        };
         let mut data = ctx.data.write().await;
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
         let tracker = data
             .get_mut::<OnlineTracker>()
-            .expect("Expected CommandCounter in TypeMap.");
-        let user_id = new_data.presence.user_id;
-        use serenity::model::prelude::OnlineStatus::*;
-        let online = match new_data.presence.status {
-            DoNotDisturb | Idle | Invisible | Online => true,
-            _ => false,
-        };
-        if online && !tracker.contains_key(&user_id) {
-            tracker.insert(user_id, Instant::now());
-        }
-        if !online {
-            tracker.remove(&user_id);
+            .expect("Expected OnlineTracker in TypeMap.");
+        let presence = &new_data.presence;
+        if let Err(why) = presence::update(
+            &db,
+            tracker.entry(guild_id).or_default(),
+            guild_id,
+            presence.user_id,
+            presence.status,
+            Utc::now(),
+        ) {
+            error!(%guild_id, "Could not record presence session: {}", why);
         }
     }
     async fn ready(&self, ctx: Context, ready: Ready) {
+        info!(
+            user = %ready.user.name,
+            guilds = ready.guilds.len(),
+            "Connected to the gateway"
+        );
+        if let Err(why) = slash::register(&ctx).await {
+            error!("Could not register the application commands: {}", why);
+        }
+        scheduler::start(&ctx).await;
+        if !self.track_presences {
+            return;
+        }
         let mut data = ctx.data.write().await;
-        let now = Instant::now();
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
         let tracker = data
             .get_mut::<OnlineTracker>()
-            .expect("Expected CommandCounter in TypeMap.");
-
-        if let Some(guild) = ready.guilds[0].id().to_guild_cached(&ctx).await {
-            println!("found guild {}", guild.name);
-            *tracker = guild
-                .presences
-                .iter()
-                .filter_map(|(id, presence)| {
-                    use serenity::model::prelude::OnlineStatus::*;
-                    let online = match presence.status {
-                        DoNotDisturb | Idle | Invisible | Online => true,
-                        _ => false,
-                    };
-                    if online {
-                        Some((*id, now.clone()))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+            .expect("Expected OnlineTracker in TypeMap.");
+
+        // Sessions left open by the previous run keep their original start time,
+        // they are reconciled with the actual presences once each guild is received.
+        match presence::load_open_sessions(&db) {
+            Ok(sessions) => *tracker = sessions,
+            Err(why) => error!("Could not load presence sessions: {}", why),
+        }
+        drop(data);
+
+        for guild in &ready.guilds {
+            if let Some(guild) = guild.id().to_guild_cached(&ctx).await {
+                sync_guild(&ctx, &guild).await;
+            }
         }
     }
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        slash::handle(&ctx, interaction).await;
+    }
+    async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: bool) {
+        if self.track_presences {
+            sync_guild(&ctx, &guild).await;
+        }
+    }
+    async fn guild_delete(&self, ctx: Context, incomplete: GuildUnavailable, _full: Option<Guild>) {
+        // An unavailable guild is an outage, not a removal: its sessions are kept.
+        if incomplete.unavailable {
+            return;
+        }
+        let mut data = ctx.data.write().await;
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
+        let tracker = data
+            .get_mut::<OnlineTracker>()
+            .expect("Expected OnlineTracker in TypeMap.");
+        if let Some(sessions) = tracker.remove(&incomplete.id) {
+            if let Err(why) = presence::close_guild(&db, sessions, Utc::now()) {
+                error!(guild_id = %incomplete.id, "Could not close presence sessions: {}", why);
+            }
+        }
+    }
+}
+
+async fn sync_guild(ctx: &Context, guild: &Guild) {
+    info!(guild_id = %guild.id, "Found guild {}", guild.name);
+    let mut data = ctx.data.write().await;
+    let db = data
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.");
+    let tracker = data
+        .get_mut::<OnlineTracker>()
+        .expect("Expected OnlineTracker in TypeMap.");
+    if let Err(why) = presence::sync_guild(
+        &db,
+        tracker.entry(guild.id).or_default(),
+        guild.id,
+        &guild.presences,
+        Utc::now(),
+    ) {
+        error!(guild_id = %guild.id, "Could not record presence sessions: {}", why);
+    }
 }
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    let token = env::var("DISCORD_TOKEN").expect("token");
-    let http = Http::new_with_token(&token);
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(why) => {
+            eprintln!("Invalid configuration: {}", why);
+            process::exit(1)
+        }
+    };
+    let _log_guard = logging::init(&config.logging);
+    let database = db::open(&config.database_path)
+        .unwrap_or_else(|why| exit_with("Could not open the database", why));
+    let http = Http::new_with_token(config.token());
     let bot_id = match http.get_current_user().await {
         Ok(bot_id) => bot_id.id,
-        Err(why) => panic!("Could not access the bot id: {:?}", why),
+        Err(why) => exit_with("Could not access the bot id", why),
     };
+    let (application_id, owners) = match http.get_current_application_info().await {
+        Ok(info) => {
+            let mut owners: HashSet<UserId> = config.owners.iter().copied().collect();
+            owners.insert(info.owner.id);
+            if let Some(team) = info.team {
+                owners.extend(team.members.iter().map(|member| member.user.id));
+            }
+            (info.id, owners)
+        }
+        Err(why) => exit_with("Could not access the application info", why),
+    };
+    let command_counts = stats::load_counts(&database)
+        .unwrap_or_else(|why| exit_with("Could not load command statistics", why));
+    let tables = table::load_tables(&database)
+        .unwrap_or_else(|why| exit_with("Could not load the Sporz tables", why));
+    let profiles = table::load_profiles(&database)
+        .unwrap_or_else(|why| exit_with("Could not load the Sporz profiles", why));
+    let polls = vote::load_polls(&database)
+        .unwrap_or_else(|why| exit_with("Could not load the polls", why));
+    let intents = config
+        .gateway_intents()
+        .expect("Intents are checked when loading the configuration");
 
-    let framework = StandardFramework::new()
+    let mut framework = StandardFramework::new()
         .configure(|c| {
-            c.prefix("!")
+            c.prefixes(&config.prefixes)
                 .with_whitespace(true)
                 .on_mention(Some(bot_id))
-                .prefix("!")
-                .delimiters(vec![", ", ","])
+                .dynamic_prefix(settings::dynamic_prefix)
+                .delimiters(config.delimiters.iter().map(String::as_str))
+                .allowed_channels(config.allowed_channels.iter().copied().collect())
+                .owners(owners)
         })
-        .group(&GENERAL_GROUP)
         .before(before)
         .after(after)
         .unrecognised_command(unknown_command)
         .help(&MY_HELP);
+    for group in enabled_groups(&config) {
+        framework = framework.group(group);
+    }
 
-    // Login with a bot token from the environment
-    let mut client = Client::builder(token)
-        .event_handler(Handler)
-        .intents(GatewayIntents::all())
-        .framework(framework)
+    let client = Client::builder(config.token())
+        .application_id(application_id.0)
+        .event_handler(Handler {
+            track_presences: config.features.presence,
+        })
+        .intents(intents)
+        .framework(framework);
+    #[cfg(feature = "metrics")]
+    let client = if config.metrics.enabled {
+        client.raw_event_handler(metrics::MetricsHandler)
+    } else {
+        client
+    };
+    let mut client = client
         .await
-        .expect("Error creating client");
+        .unwrap_or_else(|why| exit_with("Error creating client", why));
 
     {
         let mut data = client.data.write().await;
-        data.insert::<CommandCounter>(HashMap::default());
+        #[cfg(feature = "metrics")]
+        data.insert::<metrics::Metrics>(
+            metrics::Metrics::new(&command_counts)
+                .unwrap_or_else(|why| exit_with("Could not register the metrics", why)),
+        );
+        data.insert::<CommandCounter>(command_counts);
+        data.insert::<CommandTimings>(HashMap::default());
         data.insert::<OnlineTracker>(HashMap::default());
+        data.insert::<Tables>(tables);
+        data.insert::<Profiles>(profiles);
+        data.insert::<Polls>(polls);
+        data.insert::<Scheduler>(Arc::default());
+        data.insert::<Database>(database);
+        data.insert::<Config>(config.clone());
+    }
+    #[cfg(feature = "metrics")]
+    if config.metrics.enabled {
+        let addr = config.metrics.address;
+        let data = client.data.clone();
+        tokio::spawn(async move {
+            if let Err(why) = metrics::serve(addr, data).await {
+                error!("The metrics server stopped: {}", why);
+            }
+        });
     }
     // start listening for events by starting a single shard
     if let Err(why) = client.start().await {
-        println!("An error occurred while running the client: {:?}", why);
+        error!("An error occurred while running the client: {:?}", why);
     }
 }
 
@@ -154,69 +361,222 @@ async fn my_help(
 }
 #[hook]
 async fn before(ctx: &Context, msg: &Message, command_name: &str) -> bool {
-    println!(
-        "Got command '{}' by user '{}'",
-        command_name, msg.author.name
+    let span = info_span!(
+        "command",
+        command = command_name,
+        user_id = %msg.author.id,
+        guild_id = ?msg.guild_id,
+        channel_id = %msg.channel_id,
+        duration_ms = field::Empty,
+        result = field::Empty,
     );
+    span.in_scope(|| debug!(user = %msg.author.name, "Got command"));
+
+    match settings::allows(ctx, msg, command_name).await {
+        Ok(true) => {}
+        Ok(false) => {
+            span.in_scope(|| debug!("Command refused by the guild settings"));
+            return false;
+        }
+        Err(why) => {
+            span.in_scope(|| warn!("Could not read the guild settings: {}", why));
+        }
+    }
 
     // Increment the number of times this command has been run once. If
     // the command's name does not exist in the counter, add a default
     // value of 0.
     let mut data = ctx.data.write().await;
+    let db = data
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.");
     let counter = data
         .get_mut::<CommandCounter>()
         .expect("Expected CommandCounter in TypeMap.");
     let entry = counter.entry(command_name.to_string()).or_insert(0);
     *entry += 1;
+    data.get_mut::<CommandTimings>()
+        .expect("Expected CommandTimings in TypeMap.")
+        .insert(
+            msg.id,
+            CommandTiming {
+                start: Instant::now(),
+                span,
+            },
+        );
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = data.get::<metrics::Metrics>() {
+        metrics.commands.with_label_values(&[command_name]).inc();
+    }
+    if let Err(why) = stats::record_invocation(&db, msg.author.id, msg.channel_id, command_name) {
+        warn!("Could not record command statistics: {}", why);
+    }
 
     true // if `before` returns false, command processing doesn't happen.
 }
 
 #[hook]
-async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: CommandResult) {
+async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    let timing = ctx
+        .data
+        .write()
+        .await
+        .get_mut::<CommandTimings>()
+        .expect("Expected CommandTimings in TypeMap.")
+        .remove(&msg.id);
+    let (elapsed, span) = match timing {
+        Some(timing) => (timing.start.elapsed(), timing.span),
+        None => (Duration::default(), Span::none()),
+    };
+    span.record("duration_ms", &(elapsed.as_millis() as u64));
+    span.record(
+        "result",
+        &if command_result.is_ok() {
+            "ok"
+        } else {
+            "error"
+        },
+    );
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = ctx.data.read().await.get::<metrics::Metrics>() {
+        metrics
+            .command_latency
+            .with_label_values(&[command_name])
+            .observe(elapsed.as_secs_f64());
+        if command_result.is_err() {
+            metrics
+                .command_errors
+                .with_label_values(&[command_name])
+                .inc();
+        }
+    }
     match command_result {
-        Ok(()) => println!("Processed command '{}'", command_name),
-        Err(why) => println!("Command '{}' returned error {:?}", command_name, why),
+        Ok(()) => span.in_scope(|| info!("Processed command")),
+        Err(why) => {
+            span.in_scope(|| warn!("Command returned error {:?}", why));
+            let db = db::from_context(ctx).await;
+            if let Err(why) = stats::record_error(&db, command_name) {
+                span.in_scope(|| warn!("Could not record command statistics: {}", why));
+            }
+        }
     }
 }
 
 #[hook]
 async fn unknown_command(_ctx: &Context, _msg: &Message, unknown_command_name: &str) {
-    println!("Could not find command named '{}'", unknown_command_name);
+    debug!("Could not find command named '{}'", unknown_command_name);
 }
 
 #[command]
-async fn add(ctx: &Context, msg: &Message) -> CommandResult {
-    msg.reply(ctx, "Pong!").await?;
+#[description = "Add numbers and dice rolls, such as `2d6+3`."]
+#[usage = "expression[, expression...]"]
+#[example = "2d6+3, 1.5, (4-1)*2"]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let terms: Vec<String> = args.iter::<String>().filter_map(Result::ok).collect();
+    let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+    msg.reply(ctx, add_reply(&terms)).await?;
 
     Ok(())
 }
 
-#[command]
-async fn whosonline(ctx: &Context, msg: &Message) -> CommandResult {
-    let mut data = ctx.data.write().await;
-    let tracker = data
-        .get_mut::<OnlineTracker>()
-        .expect("Expected CommandCounter in TypeMap.");
-    let mut reply = "the following users are online:\n".to_string();
-    for (userid, instant) in tracker.iter() {
-        let member = msg
-            .guild_id
-            .ok_or("Must be used in guild")?
-            .member(ctx, userid)
-            .await?;
-        let duration = instant.elapsed();
-        let seconds = duration.as_secs() % 60;
-        let minutes = (duration.as_secs() / 60) % 60;
-        let hours = (duration.as_secs() / 60) / 60;
-        reply += &format!(
-            "{} has been connected for {}h {}m {}s\n",
-            member.display_name(),
-            hours,
-            minutes,
-            seconds
+/// Longest excerpt of a term quoted in the reply of `add`.
+const TERM_EXCERPT: usize = 50;
+/// Room kept in the reply of `add` for the total and the omitted terms.
+const ADD_REPLY_MARGIN: usize = 100;
+
+fn excerpt(term: &str) -> String {
+    if term.chars().count() <= TERM_EXCERPT {
+        return term.to_string();
+    }
+    term.chars()
+        .take(TERM_EXCERPT - 1)
+        .chain(Some('…'))
+        .collect()
+}
+
+/// Shared by the prefix and slash variants of `add`: evaluates each term and
+/// sums them, or reports the first one that cannot be parsed. The dice of the
+/// last terms are left out when the reply would not fit in a message.
+pub fn add_reply(terms: &[&str]) -> String {
+    let terms: Vec<&str> = terms
+        .iter()
+        .map(|term| term.trim())
+        .filter(|term| !term.is_empty())
+        .collect();
+    if terms.is_empty() {
+        return "nothing to add".to_string();
+    }
+    let mut rng = rand::thread_rng();
+    let mut evaluations = Vec::new();
+    for term in &terms {
+        match expr::evaluate(term, &mut rng) {
+            Ok(evaluation) => evaluations.push(evaluation),
+            Err(why) => return format!("could not parse `{}`: {}", excerpt(term), why),
+        }
+    }
+    let budget = MESSAGE_CODE_LIMIT - ADD_REPLY_MARGIN;
+    let mut total = 0.0;
+    let mut omitted = 0;
+    let mut reply = String::new();
+    for (term, evaluation) in terms.iter().zip(&evaluations) {
+        total += evaluation.value;
+        let value = format!(
+            "`{}` = {}",
+            excerpt(term),
+            expr::format_value(evaluation.value)
         );
+        let line = evaluation.summary(&excerpt(term));
+        let length = reply.chars().count();
+        if length + line.chars().count() < budget {
+            reply += &line;
+        } else if length + value.chars().count() + 4 < budget {
+            reply += &value;
+            reply += " — …";
+        } else {
+            omitted += 1;
+            continue;
+        }
+        reply += "\n";
+    }
+    if omitted > 0 {
+        reply += &format!("…and {} more\n", omitted);
+    }
+    if terms.len() > 1 {
+        reply += &format!("**total: {}**", expr::format_value(total));
+    }
+    reply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_sums_every_term() {
+        assert_eq!(add_reply(&[" ", ""]), "nothing to add");
+        assert_eq!(add_reply(&["1 + 2"]), "`1 + 2` = 3\n");
+        assert_eq!(
+            add_reply(&["2 * 3", " 4"]),
+            "`2 * 3` = 6\n`4` = 4\n**total: 10**"
+        );
+        assert!(add_reply(&["1", "2 +"]).starts_with("could not parse `2 +`"));
+    }
+
+    #[test]
+    fn add_replies_fit_in_a_message() {
+        let terms = vec!["100d1000"; 40];
+        let reply = add_reply(&terms);
+        assert!(reply.chars().count() <= MESSAGE_CODE_LIMIT);
+        assert!(reply.contains(" — …"));
+        assert!(reply.contains("**total: "));
+
+        let long = "1+".repeat(2000) + "1";
+        let terms = vec![long.as_str(); 60];
+        let reply = add_reply(&terms);
+        assert!(reply.chars().count() <= MESSAGE_CODE_LIMIT);
+        assert!(reply.contains("more\n**total: 120060**"));
+        let error = add_reply(&[&(long.clone() + "+")]);
+        assert!(error.chars().count() <= MESSAGE_CODE_LIMIT);
     }
-    msg.reply(ctx, reply).await?;
-    Ok(())
 }
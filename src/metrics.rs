@@ -0,0 +1,129 @@
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use serenity::{
+    async_trait,
+    client::{Context, RawEventHandler},
+    model::event::Event,
+    prelude::{RwLock, TypeMap, TypeMapKey},
+};
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, sync::Arc};
+
+use crate::presence::OnlineTracker;
+
+pub struct Metrics {
+    registry: Registry,
+    pub commands: IntCounterVec,
+    pub command_errors: IntCounterVec,
+    pub command_latency: HistogramVec,
+    pub gateway_events: IntCounter,
+    online_users: IntGaugeVec,
+}
+
+impl TypeMapKey for Metrics {
+    type Value = Metrics;
+}
+
+impl Metrics {
+    /// Registers every metric, starting the command counters from the
+    /// persisted totals.
+    pub fn new(command_counts: &HashMap<String, u64>) -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("sporz_bot".to_string()), None)?;
+        let commands = IntCounterVec::new(
+            Opts::new("command_invocations_total", "Commands invoked."),
+            &["command"],
+        )?;
+        let command_errors = IntCounterVec::new(
+            Opts::new("command_errors_total", "Commands that returned an error."),
+            &["command"],
+        )?;
+        let command_latency = HistogramVec::new(
+            HistogramOpts::new("command_duration_seconds", "Time taken to run commands."),
+            &["command"],
+        )?;
+        let gateway_events = IntCounter::new("gateway_events_total", "Gateway events received.")?;
+        let online_users = IntGaugeVec::new(
+            Opts::new("online_users", "Users currently tracked as online."),
+            &["guild"],
+        )?;
+        registry.register(Box::new(commands.clone()))?;
+        registry.register(Box::new(command_errors.clone()))?;
+        registry.register(Box::new(command_latency.clone()))?;
+        registry.register(Box::new(gateway_events.clone()))?;
+        registry.register(Box::new(online_users.clone()))?;
+        for (command, count) in command_counts {
+            commands
+                .with_label_values(&[command.as_str()])
+                .inc_by(*count);
+        }
+        Ok(Metrics {
+            registry,
+            commands,
+            command_errors,
+            command_latency,
+            gateway_events,
+            online_users,
+        })
+    }
+}
+
+/// Counts every event received from the gateway.
+pub struct MetricsHandler;
+
+#[async_trait]
+impl RawEventHandler for MetricsHandler {
+    async fn raw_event(&self, ctx: Context, _ev: Event) {
+        if let Some(metrics) = ctx.data.read().await.get::<Metrics>() {
+            metrics.gateway_events.inc();
+        }
+    }
+}
+
+async fn handle(
+    req: Request<Body>,
+    data: Arc<RwLock<TypeMap>>,
+) -> Result<Response<Body>, Infallible> {
+    if req.uri().path() != "/metrics" {
+        let mut not_found = Response::new(Body::empty());
+        *not_found.status_mut() = StatusCode::NOT_FOUND;
+        return Ok(not_found);
+    }
+    let data = data.read().await;
+    let metrics = data.get::<Metrics>().expect("Expected Metrics in TypeMap.");
+    let tracker = data
+        .get::<OnlineTracker>()
+        .expect("Expected OnlineTracker in TypeMap.");
+    metrics.online_users.reset();
+    for (guild_id, sessions) in tracker {
+        metrics
+            .online_users
+            .with_label_values(&[&guild_id.to_string()])
+            .set(sessions.len() as i64);
+    }
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    let response = match encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        Ok(()) => Response::builder()
+            .header(CONTENT_TYPE, encoder.format_type())
+            .body(Body::from(buffer)),
+        Err(why) => Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(why.to_string())),
+    };
+    Ok(response.expect("Could not build the metrics response"))
+}
+
+/// Serves the metrics on `addr` until the process stops.
+pub async fn serve(addr: SocketAddr, data: Arc<RwLock<TypeMap>>) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let data = data.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, data.clone()))) }
+    });
+    Server::bind(&addr).serve(make_service).await
+}
@@ -0,0 +1,395 @@
+use chrono::{DateTime, Duration, Local, TimeZone, Timelike, Utc};
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::UserId},
+};
+use std::{collections::HashMap, str::FromStr};
+use thiserror::Error;
+
+use super::{
+    display_name, format_duration, format_time, guild_sessions, target_user, PresenceSession,
+    PresenceTransition, StatusKind, StatusTimes,
+};
+use crate::db;
+
+const LEADERBOARD_SIZE: usize = 10;
+const HISTOGRAM_WIDTH: i64 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Event,
+}
+
+impl Period {
+    /// Start of the period ending at `now`, `None` meaning since the beginning.
+    fn start(self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Period::Day => Some(now - Duration::days(1)),
+            Period::Week => Some(now - Duration::weeks(1)),
+            Period::Event => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Period::Day => "last 24 hours",
+            Period::Week => "last 7 days",
+            Period::Event => "whole event",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown period `{0}`, expected one of day, week or event")]
+pub struct UnknownPeriod(String);
+
+impl FromStr for Period {
+    type Err = UnknownPeriod;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week),
+            "event" | "all" => Ok(Period::Event),
+            _ => Err(UnknownPeriod(s.to_string())),
+        }
+    }
+}
+
+/// Presence sessions aggregated over a period.
+#[derive(Debug, Default)]
+pub struct ActivityReport {
+    pub totals: HashMap<UserId, i64>,
+    pub sessions: usize,
+    pub peak: usize,
+    pub peak_at: Option<DateTime<Utc>>,
+    /// Seconds of presence falling in each local hour of the day.
+    pub hourly: [i64; 24],
+}
+
+impl ActivityReport {
+    pub fn total(&self) -> i64 {
+        self.totals.values().sum()
+    }
+
+    /// Users sorted by decreasing online time.
+    pub fn ranking(&self) -> Vec<(UserId, i64)> {
+        let mut ranking: Vec<_> = self.totals.iter().map(|(id, time)| (*id, *time)).collect();
+        ranking.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+        ranking
+    }
+
+    pub fn histogram(&self) -> String {
+        let max = self.hourly.iter().copied().max().unwrap_or(0).max(1);
+        let mut histogram = String::new();
+        for (hour, seconds) in self.hourly.iter().enumerate() {
+            histogram += &format!(
+                "{:02}h {:<width$} {}\n",
+                hour,
+                "█".repeat((seconds * HISTOGRAM_WIDTH / max) as usize),
+                format_duration(*seconds),
+                width = HISTOGRAM_WIDTH as usize
+            );
+        }
+        histogram
+    }
+}
+
+/// Aggregates `sessions`, clipped to the interval between `from` and `now`.
+/// The hourly activity is bucketed on the hours of `tz`.
+pub fn aggregate<Tz: TimeZone>(
+    sessions: &[PresenceSession],
+    from: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    tz: &Tz,
+) -> ActivityReport {
+    let mut report = ActivityReport::default();
+    let mut events = Vec::new();
+    for session in sessions {
+        let start = from.map_or(session.start, |from| from.max(session.start));
+        let end = session.end.unwrap_or(now).min(now);
+        if end <= start {
+            continue;
+        }
+        report.sessions += 1;
+        *report.totals.entry(session.user_id).or_insert(0) += (end - start).num_seconds();
+        events.push((start, 1));
+        events.push((end, -1));
+
+        let mut cursor = start;
+        while cursor < end {
+            let local = cursor.with_timezone(tz);
+            let into_hour = Duration::seconds((local.minute() * 60 + local.second()) as i64)
+                + Duration::nanoseconds(local.nanosecond() as i64);
+            let until = (cursor - into_hour + Duration::hours(1)).min(end);
+            report.hourly[local.hour() as usize] += (until - cursor).num_seconds();
+            cursor = until;
+        }
+    }
+
+    // Ends sort before starts at the same instant, so back to back sessions
+    // are not counted twice.
+    events.sort();
+    let mut current: i64 = 0;
+    for (at, delta) in events {
+        current += delta;
+        if current as usize > report.peak {
+            report.peak = current as usize;
+            report.peak_at = Some(at);
+        }
+    }
+    report
+}
+
+/// Time spent in each status between `from` and `now`, from the transitions of
+/// a single user sorted oldest first.
+pub fn status_times(
+    transitions: &[PresenceTransition],
+    from: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> StatusTimes {
+    let mut times = StatusTimes::default();
+    for (index, transition) in transitions.iter().enumerate() {
+        let kind = match StatusKind::of(transition.status) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let start = from.map_or(transition.at, |from| from.max(transition.at));
+        let end = transitions
+            .get(index + 1)
+            .map_or(now, |next| next.at)
+            .min(now);
+        if end > start {
+            times.add(kind, (end - start).num_seconds());
+        }
+    }
+    times
+}
+
+fn parse_period(args: &mut Args) -> Result<Period, UnknownPeriod> {
+    match args.trimmed().current() {
+        Some(period) => {
+            let period = period.parse();
+            args.advance();
+            period
+        }
+        None => Ok(Period::Event),
+    }
+}
+
+#[command]
+#[description = "Rank the users by time spent online."]
+#[usage = "[day|week|event]"]
+async fn leaderboard(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let period = match parse_period(&mut args) {
+        Ok(period) => period,
+        Err(why) => {
+            msg.reply(ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let now = Utc::now();
+    let db = db::from_context(ctx).await;
+    let report = aggregate(
+        &guild_sessions(&db, guild_id)?,
+        period.start(now),
+        now,
+        &Local,
+    );
+
+    let mut ranking = String::new();
+    for (rank, (user_id, time)) in report
+        .ranking()
+        .into_iter()
+        .take(LEADERBOARD_SIZE)
+        .enumerate()
+    {
+        ranking += &format!(
+            "{}. {} — {}\n",
+            rank + 1,
+            display_name(ctx, guild_id, user_id).await,
+            format_duration(time)
+        );
+    }
+    if ranking.is_empty() {
+        ranking = "nobody was seen online".to_string();
+    }
+    let peak = match report.peak_at {
+        Some(at) => format!("{} users at {}", report.peak, format_time(at)),
+        None => "nobody".to_string(),
+    };
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Leaderboard ({})", period.name()))
+                    .description(ranking)
+                    .field("Peak concurrent users", peak, false)
+                    .field(
+                        "Online time",
+                        format!(
+                            "{} over {} sessions",
+                            format_duration(report.total()),
+                            report.sessions
+                        ),
+                        false,
+                    )
+                    .field(
+                        "Hourly activity",
+                        format!("```\n{}```", report.histogram()),
+                        false,
+                    )
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Show the online activity of a user."]
+#[usage = "[@user][, day|week|event]"]
+async fn activity(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = target_user(msg, &mut args);
+    let period = match parse_period(&mut args) {
+        Ok(period) => period,
+        Err(why) => {
+            msg.reply(ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let now = Utc::now();
+    let db = db::from_context(ctx).await;
+    let sessions = super::user_sessions(&db, guild_id, user_id)?;
+    let report = aggregate(&sessions, period.start(now), now, &Local);
+    let transitions = super::user_transitions(&db, guild_id, user_id)?;
+    let times = status_times(&transitions, period.start(now), now);
+    let name = display_name(ctx, guild_id, user_id).await;
+    let average = report.total() / report.sessions.max(1) as i64;
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title(format!("Activity of {} ({})", name, period.name()))
+                    .field("Online time", format_duration(report.total()), true)
+                    .field("Sessions", report.sessions, true)
+                    .field("Average session", format_duration(average), true)
+                    .field(
+                        "By status",
+                        format!(
+                            "active {}\nidle {}\ndnd {}",
+                            format_duration(times.active),
+                            format_duration(times.idle),
+                            format_duration(times.dnd)
+                        ),
+                        false,
+                    )
+                    .field(
+                        "Hourly activity",
+                        format!("```\n{}```", report.histogram()),
+                        false,
+                    )
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+    use serenity::model::{id::GuildId, user::OnlineStatus};
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 6, 1).and_hms(hour, minute, 0)
+    }
+
+    fn session(user: u64, start: DateTime<Utc>, end: Option<DateTime<Utc>>) -> PresenceSession {
+        PresenceSession {
+            user_id: UserId(user),
+            guild_id: GuildId(1),
+            start,
+            end,
+            status: OnlineStatus::Online,
+            status_since: start,
+            times: StatusTimes::default(),
+        }
+    }
+
+    fn transition(minutes: i64, status: OnlineStatus) -> PresenceTransition {
+        PresenceTransition {
+            user_id: UserId(1),
+            guild_id: GuildId(1),
+            at: at(10, 0) + Duration::minutes(minutes),
+            status,
+        }
+    }
+
+    #[test]
+    fn periods_parse_case_insensitively() {
+        assert_eq!("Day".parse::<Period>().unwrap(), Period::Day);
+        assert_eq!("WEEK".parse::<Period>().unwrap(), Period::Week);
+        assert_eq!("all".parse::<Period>().unwrap(), Period::Event);
+        assert!("month".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn sessions_are_clipped_to_the_period() {
+        let sessions = [
+            session(1, at(8, 0), Some(at(10, 0))),
+            session(1, at(11, 0), None),
+            session(2, at(9, 30), Some(at(11, 30))),
+            session(3, at(6, 0), Some(at(7, 0))),
+        ];
+        let report = aggregate(&sessions, Some(at(9, 0)), at(12, 0), &Utc);
+        assert_eq!(report.sessions, 3);
+        assert_eq!(report.totals[&UserId(1)], 2 * 3600);
+        assert_eq!(report.totals[&UserId(2)], 2 * 3600);
+        assert!(!report.totals.contains_key(&UserId(3)));
+        assert_eq!(report.total(), 4 * 3600);
+        assert_eq!(report.ranking().len(), 2);
+        assert_eq!(report.peak, 2);
+        assert_eq!(report.peak_at, Some(at(9, 30)));
+        assert_eq!(report.hourly.iter().sum::<i64>(), report.total());
+        assert_eq!(report.hourly[9], 3600 + 1800);
+        assert_eq!(report.hourly[11], 3600 + 1800);
+    }
+
+    #[test]
+    fn back_to_back_sessions_do_not_raise_the_peak() {
+        let sessions = [
+            session(1, at(8, 0), Some(at(9, 0))),
+            session(2, at(9, 0), Some(at(10, 0))),
+        ];
+        let report = aggregate(&sessions, None, at(12, 0), &Utc);
+        assert_eq!(report.peak, 1);
+    }
+
+    #[test]
+    fn hours_are_bucketed_on_local_time() {
+        let india = FixedOffset::east(5 * 3600 + 30 * 60);
+        let sessions = [session(1, at(10, 0), Some(at(11, 0)))];
+        let report = aggregate(&sessions, None, at(12, 0), &india);
+        // 10:00 UTC is 15:30 in India, so the hour is split across two buckets.
+        assert_eq!(report.hourly[15], 1800);
+        assert_eq!(report.hourly[16], 1800);
+        assert_eq!(report.hourly.iter().sum::<i64>(), 3600);
+    }
+
+    #[test]
+    fn status_times_are_clipped_to_the_period() {
+        let transitions = [
+            transition(0, OnlineStatus::Online),
+            transition(60, OnlineStatus::Idle),
+            transition(90, OnlineStatus::Offline),
+            transition(120, OnlineStatus::DoNotDisturb),
+        ];
+        let times = status_times(&transitions, Some(at(10, 30)), at(12, 30));
+        assert_eq!((times.active, times.idle, times.dnd), (1800, 1800, 1800));
+        let times = status_times(&transitions, None, at(12, 30));
+        assert_eq!((times.active, times.idle, times.dnd), (3600, 1800, 1800));
+    }
+}
@@ -0,0 +1,695 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+        prelude::OnlineStatus,
+    },
+    prelude::TypeMapKey,
+};
+use std::{collections::HashMap, str::FromStr};
+use thiserror::Error;
+
+use crate::db::{self, DbResult};
+
+mod activity;
+
+use activity::{ACTIVITY_COMMAND, LEADERBOARD_COMMAND};
+
+const SESSIONS_TREE: &str = "presence_sessions";
+const TRANSITIONS_TREE: &str = "presence_transitions";
+
+#[group]
+#[commands(whosonline, lastseen, history, leaderboard, activity)]
+#[only_in(guilds)]
+struct Presence;
+
+/// The online statuses `whosonline` tells apart. `Invisible` is only ever
+/// reported for the bot itself and counts as active.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StatusKind {
+    Active,
+    Idle,
+    DoNotDisturb,
+}
+
+impl StatusKind {
+    pub const ALL: [StatusKind; 3] = [
+        StatusKind::Active,
+        StatusKind::Idle,
+        StatusKind::DoNotDisturb,
+    ];
+
+    pub fn of(status: OnlineStatus) -> Option<Self> {
+        use OnlineStatus::*;
+        match status {
+            Online | Invisible => Some(StatusKind::Active),
+            Idle => Some(StatusKind::Idle),
+            DoNotDisturb => Some(StatusKind::DoNotDisturb),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StatusKind::Active => "active",
+            StatusKind::Idle => "idle",
+            StatusKind::DoNotDisturb => "dnd",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown status `{0}`, expected one of active, idle, dnd or all")]
+pub struct UnknownStatus(String);
+
+impl FromStr for StatusKind {
+    type Err = UnknownStatus;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "active" | "online" => Ok(StatusKind::Active),
+            "idle" | "afk" => Ok(StatusKind::Idle),
+            "dnd" | "busy" => Ok(StatusKind::DoNotDisturb),
+            _ => Err(UnknownStatus(s.to_string())),
+        }
+    }
+}
+
+/// Seconds spent in each status during a session.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StatusTimes {
+    pub active: i64,
+    pub idle: i64,
+    pub dnd: i64,
+}
+
+impl StatusTimes {
+    fn add(&mut self, kind: StatusKind, seconds: i64) {
+        match kind {
+            StatusKind::Active => self.active += seconds,
+            StatusKind::Idle => self.idle += seconds,
+            StatusKind::DoNotDisturb => self.dnd += seconds,
+        }
+    }
+}
+
+/// A span of time during which a user was seen online, stored with wall-clock
+/// timestamps so it survives restarts. `end` is `None` while the session is open.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PresenceSession {
+    pub user_id: UserId,
+    pub guild_id: GuildId,
+    pub start: DateTime<Utc>,
+    pub end: Option<DateTime<Utc>>,
+    pub status: OnlineStatus,
+    /// When `status` was last changed.
+    #[serde(default = "Utc::now")]
+    pub status_since: DateTime<Utc>,
+    /// Time spent in each status, not counting the ongoing one.
+    #[serde(default)]
+    pub times: StatusTimes,
+}
+
+impl PresenceSession {
+    fn key(&self) -> Vec<u8> {
+        let mut key = user_key(self.guild_id, self.user_id);
+        key.extend_from_slice(&self.start.timestamp_millis().to_be_bytes());
+        key
+    }
+
+    fn change_status(&mut self, status: OnlineStatus, now: DateTime<Utc>) {
+        if let Some(kind) = StatusKind::of(self.status) {
+            self.times
+                .add(kind, (now - self.status_since).num_seconds().max(0));
+        }
+        self.status = status;
+        self.status_since = now;
+    }
+
+    /// Time spent in each status, including the ongoing one up to `now`.
+    pub fn times_at(&self, now: DateTime<Utc>) -> StatusTimes {
+        let mut times = self.times.clone();
+        if let (None, Some(kind)) = (self.end, StatusKind::of(self.status)) {
+            times.add(kind, (now - self.status_since).num_seconds().max(0));
+        }
+        times
+    }
+}
+
+/// A change of status of a user, logged every time a presence update differs
+/// from the last known status.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PresenceTransition {
+    pub user_id: UserId,
+    pub guild_id: GuildId,
+    pub at: DateTime<Utc>,
+    pub status: OnlineStatus,
+}
+
+impl PresenceTransition {
+    fn key(&self) -> Vec<u8> {
+        let mut key = user_key(self.guild_id, self.user_id);
+        key.extend_from_slice(&self.at.timestamp_millis().to_be_bytes());
+        key
+    }
+}
+
+/// Prefix shared by all the entries of a user in a guild.
+fn user_key(guild_id: GuildId, user_id: UserId) -> Vec<u8> {
+    let mut key = guild_id.0.to_be_bytes().to_vec();
+    key.extend_from_slice(&user_id.0.to_be_bytes());
+    key
+}
+
+/// Open sessions of a single guild.
+pub type GuildSessions = HashMap<UserId, PresenceSession>;
+
+pub struct OnlineTracker;
+
+impl TypeMapKey for OnlineTracker {
+    type Value = HashMap<GuildId, GuildSessions>;
+}
+
+pub fn is_online(status: OnlineStatus) -> bool {
+    use OnlineStatus::*;
+    matches!(status, DoNotDisturb | Idle | Invisible | Online)
+}
+
+pub fn open_session(
+    db: &sled::Db,
+    user_id: UserId,
+    guild_id: GuildId,
+    status: OnlineStatus,
+    now: DateTime<Utc>,
+) -> DbResult<PresenceSession> {
+    let session = PresenceSession {
+        user_id,
+        guild_id,
+        start: now,
+        end: None,
+        status,
+        status_since: now,
+        times: StatusTimes::default(),
+    };
+    db::insert(&db.open_tree(SESSIONS_TREE)?, session.key(), &session)?;
+    Ok(session)
+}
+
+pub fn close_session(
+    db: &sled::Db,
+    mut session: PresenceSession,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    session.change_status(OnlineStatus::Offline, now);
+    session.end = Some(now);
+    db::insert(&db.open_tree(SESSIONS_TREE)?, session.key(), &session)
+}
+
+fn record_transition(
+    db: &sled::Db,
+    guild_id: GuildId,
+    user_id: UserId,
+    status: OnlineStatus,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    let transition = PresenceTransition {
+        user_id,
+        guild_id,
+        at: now,
+        status,
+    };
+    db::insert(
+        &db.open_tree(TRANSITIONS_TREE)?,
+        transition.key(),
+        &transition,
+    )
+}
+
+pub fn last_transition(
+    db: &sled::Db,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> DbResult<Option<PresenceTransition>> {
+    db::last_with_prefix(
+        &db.open_tree(TRANSITIONS_TREE)?,
+        user_key(guild_id, user_id),
+    )
+}
+
+/// All the recorded sessions of a user in a guild, oldest first.
+pub fn user_sessions(
+    db: &sled::Db,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> DbResult<Vec<PresenceSession>> {
+    db::scan_prefix(&db.open_tree(SESSIONS_TREE)?, user_key(guild_id, user_id))
+}
+
+/// All the logged transitions of a user in a guild, oldest first.
+pub fn user_transitions(
+    db: &sled::Db,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> DbResult<Vec<PresenceTransition>> {
+    db::scan_prefix(
+        &db.open_tree(TRANSITIONS_TREE)?,
+        user_key(guild_id, user_id),
+    )
+}
+
+/// All the recorded sessions of a guild.
+pub fn guild_sessions(db: &sled::Db, guild_id: GuildId) -> DbResult<Vec<PresenceSession>> {
+    db::scan_prefix(&db.open_tree(SESSIONS_TREE)?, guild_id.0.to_be_bytes())
+}
+
+/// Sessions that were still open when the bot last stopped, grouped by guild.
+pub fn load_open_sessions(db: &sled::Db) -> DbResult<HashMap<GuildId, GuildSessions>> {
+    let sessions: Vec<PresenceSession> = db::scan_prefix(&db.open_tree(SESSIONS_TREE)?, b"")?;
+    let mut tracker: HashMap<GuildId, GuildSessions> = HashMap::new();
+    for session in sessions.into_iter().filter(|s| s.end.is_none()) {
+        tracker
+            .entry(session.guild_id)
+            .or_default()
+            .insert(session.user_id, session);
+    }
+    Ok(tracker)
+}
+
+/// Logs the status change of `user_id`, opening or closing its session
+/// accordingly.
+pub fn update(
+    db: &sled::Db,
+    sessions: &mut GuildSessions,
+    guild_id: GuildId,
+    user_id: UserId,
+    status: OnlineStatus,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    let previous = sessions
+        .get(&user_id)
+        .map_or(OnlineStatus::Offline, |session| session.status);
+    if previous == status {
+        return Ok(());
+    }
+    record_transition(db, guild_id, user_id, status, now)?;
+    if !is_online(status) {
+        if let Some(session) = sessions.remove(&user_id) {
+            close_session(db, session, now)?;
+        }
+    } else if let Some(session) = sessions.get_mut(&user_id) {
+        session.change_status(status, now);
+        db::insert(&db.open_tree(SESSIONS_TREE)?, session.key(), &*session)?;
+    } else {
+        let session = open_session(db, user_id, guild_id, status, now)?;
+        sessions.insert(user_id, session);
+    }
+    Ok(())
+}
+
+/// Reconciles the tracked sessions of a guild with a full presence snapshot, as
+/// received on startup. Sessions of users still online keep their start time.
+pub fn sync_guild(
+    db: &sled::Db,
+    sessions: &mut GuildSessions,
+    guild_id: GuildId,
+    presences: &HashMap<UserId, serenity::model::prelude::Presence>,
+    now: DateTime<Utc>,
+) -> DbResult<()> {
+    let offline: Vec<UserId> = sessions
+        .keys()
+        .filter(|id| !presences.get(*id).is_some_and(|p| is_online(p.status)))
+        .cloned()
+        .collect();
+    for user_id in offline {
+        update(db, sessions, guild_id, user_id, OnlineStatus::Offline, now)?;
+    }
+    for (user_id, presence) in presences {
+        update(db, sessions, guild_id, *user_id, presence.status, now)?;
+    }
+    Ok(())
+}
+
+/// Closes every session of a guild the bot is no longer part of.
+pub fn close_guild(db: &sled::Db, sessions: GuildSessions, now: DateTime<Utc>) -> DbResult<()> {
+    for session in sessions.into_values() {
+        close_session(db, session, now)?;
+    }
+    Ok(())
+}
+
+pub fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    format!(
+        "{}h {}m {}s",
+        seconds / 3600,
+        (seconds / 60) % 60,
+        seconds % 60
+    )
+}
+
+/// Discord timestamp markup, rendered in the reader's timezone.
+pub fn format_time(at: DateTime<Utc>) -> String {
+    format!("<t:{}:f>", at.timestamp())
+}
+
+pub async fn display_name(ctx: &Context, guild_id: GuildId, user_id: UserId) -> String {
+    match guild_id.member(ctx, user_id).await {
+        Ok(member) => member.display_name().into_owned(),
+        Err(_) => match user_id.to_user(ctx).await {
+            Ok(user) => user.name,
+            Err(_) => user_id.to_string(),
+        },
+    }
+}
+
+/// The user mentioned in the first argument, or the author of the message.
+pub(crate) fn target_user(msg: &Message, args: &mut Args) -> UserId {
+    args.trimmed().single::<UserId>().unwrap_or(msg.author.id)
+}
+
+async fn open_sessions(ctx: &Context, guild_id: GuildId) -> GuildSessions {
+    ctx.data
+        .read()
+        .await
+        .get::<OnlineTracker>()
+        .expect("Expected OnlineTracker in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+#[command]
+#[description = "List the users currently online, grouped by status."]
+#[usage = "[active|idle|dnd|all]"]
+async fn whosonline(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let reply = match parse_filter(args.trimmed().current()) {
+        Ok(filter) => whosonline_reply(ctx, guild_id, filter).await,
+        Err(why) => why.to_string(),
+    };
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+/// Parses the status filter of `whosonline`, `all` meaning no filter.
+pub fn parse_filter(filter: Option<&str>) -> Result<Option<StatusKind>, UnknownStatus> {
+    match filter {
+        None => Ok(None),
+        Some(filter) if filter.eq_ignore_ascii_case("all") => Ok(None),
+        Some(filter) => filter.parse().map(Some),
+    }
+}
+
+/// Shared by the prefix and slash variants of `whosonline`.
+pub async fn whosonline_reply(
+    ctx: &Context,
+    guild_id: GuildId,
+    filter: Option<StatusKind>,
+) -> String {
+    let now = Utc::now();
+    let sessions = open_sessions(ctx, guild_id).await;
+    let mut reply = String::new();
+    for kind in StatusKind::ALL.iter().copied() {
+        if filter.is_some_and(|filter| filter != kind) {
+            continue;
+        }
+        let mut group: Vec<_> = sessions
+            .values()
+            .filter(|session| StatusKind::of(session.status) == Some(kind))
+            .collect();
+        if group.is_empty() {
+            continue;
+        }
+        group.sort_by_key(|session| session.status_since);
+        reply += &format!("**{}** ({}):\n", kind.name(), group.len());
+        for session in group {
+            let times = session.times_at(now);
+            reply += &format!(
+                "{} has been connected for {}, {} for {} (active {}, idle {}, dnd {})\n",
+                display_name(ctx, guild_id, session.user_id).await,
+                format_duration((now - session.start).num_seconds()),
+                kind.name(),
+                format_duration((now - session.status_since).num_seconds()),
+                format_duration(times.active),
+                format_duration(times.idle),
+                format_duration(times.dnd)
+            );
+        }
+    }
+    if reply.is_empty() {
+        reply = "nobody is online".to_string();
+    }
+    reply
+}
+
+#[command]
+#[description = "Tell when a user was last online."]
+#[usage = "[@user]"]
+async fn lastseen(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = target_user(msg, &mut args);
+    let name = display_name(ctx, guild_id, user_id).await;
+    let now = Utc::now();
+    let reply = if let Some(session) = open_sessions(ctx, guild_id).await.get(&user_id) {
+        format!(
+            "{} is online right now, since {}",
+            name,
+            format_time(session.start)
+        )
+    } else {
+        let db = db::from_context(ctx).await;
+        match last_transition(&db, guild_id, user_id)? {
+            Some(transition) => format!(
+                "{} was last seen {} ({} ago)",
+                name,
+                format_time(transition.at),
+                format_duration((now - transition.at).num_seconds())
+            ),
+            None => format!("{} has never been seen online", name),
+        }
+    };
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Show the most recent online sessions of a user."]
+#[usage = "[@user][, count]"]
+async fn history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = target_user(msg, &mut args);
+    let count = args.trimmed().single::<usize>().unwrap_or(10).clamp(1, 25);
+    let db = db::from_context(ctx).await;
+    let sessions = user_sessions(&db, guild_id, user_id)?;
+    let name = display_name(ctx, guild_id, user_id).await;
+    if sessions.is_empty() {
+        msg.reply(ctx, format!("{} has never been seen online", name))
+            .await?;
+        return Ok(());
+    }
+    let now = Utc::now();
+    let mut reply = format!("last sessions of {}:\n", name);
+    for session in sessions.iter().rev().take(count) {
+        let end = session.end.unwrap_or(now);
+        reply += &format!(
+            "{} → {} ({})\n",
+            format_time(session.start),
+            session.end.map_or("now".to_string(), format_time),
+            format_duration((end - session.start).num_seconds())
+        );
+    }
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn temporary_db() -> sled::Db {
+        sled::Config::new().temporary(true).open().unwrap()
+    }
+
+    #[test]
+    fn open_sessions_are_reloaded_by_guild() {
+        let db = temporary_db();
+        let now = Utc::now();
+        let closed = open_session(&db, UserId(1), GuildId(1), OnlineStatus::Online, now).unwrap();
+        close_session(&db, closed, now + Duration::minutes(5)).unwrap();
+        open_session(&db, UserId(2), GuildId(1), OnlineStatus::Idle, now).unwrap();
+        open_session(&db, UserId(2), GuildId(2), OnlineStatus::Online, now).unwrap();
+
+        let tracker = load_open_sessions(&db).unwrap();
+        assert_eq!(tracker.len(), 2);
+        let first = &tracker[&GuildId(1)];
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[&UserId(2)].status, OnlineStatus::Idle);
+        assert_eq!(first[&UserId(2)].start, now);
+        assert_eq!(
+            tracker[&GuildId(2)][&UserId(2)].status,
+            OnlineStatus::Online
+        );
+    }
+
+    #[test]
+    fn updates_open_and_close_sessions() {
+        let db = temporary_db();
+        let start = Utc::now();
+        let end = start + Duration::hours(1);
+        let mut sessions = GuildSessions::new();
+        let guild_id = GuildId(1);
+        update(
+            &db,
+            &mut sessions,
+            guild_id,
+            UserId(1),
+            OnlineStatus::Online,
+            start,
+        )
+        .unwrap();
+        // A change between online statuses keeps the session.
+        update(
+            &db,
+            &mut sessions,
+            guild_id,
+            UserId(1),
+            OnlineStatus::Idle,
+            end,
+        )
+        .unwrap();
+        assert_eq!(sessions[&UserId(1)].start, start);
+        update(
+            &db,
+            &mut sessions,
+            guild_id,
+            UserId(1),
+            OnlineStatus::Offline,
+            end,
+        )
+        .unwrap();
+        assert!(sessions.is_empty());
+
+        let stored: Vec<PresenceSession> =
+            db::scan_prefix(&db.open_tree(SESSIONS_TREE).unwrap(), b"").unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].start, start);
+        assert_eq!(stored[0].end, Some(end));
+        assert!(load_open_sessions(&db).unwrap().is_empty());
+    }
+
+    #[test]
+    fn transitions_are_logged_on_change_only() {
+        let db = temporary_db();
+        let start = Utc::now();
+        let mut sessions = GuildSessions::new();
+        let (guild_id, user_id) = (GuildId(1), UserId(1));
+        for (minutes, status) in [
+            (0, OnlineStatus::Online),
+            (1, OnlineStatus::Online),
+            (2, OnlineStatus::Idle),
+            (3, OnlineStatus::Offline),
+            (4, OnlineStatus::Offline),
+            (5, OnlineStatus::DoNotDisturb),
+        ] {
+            let now = start + Duration::minutes(minutes);
+            update(&db, &mut sessions, guild_id, user_id, status, now).unwrap();
+        }
+        let transitions: Vec<PresenceTransition> = db::scan_prefix(
+            &db.open_tree(TRANSITIONS_TREE).unwrap(),
+            user_key(guild_id, user_id),
+        )
+        .unwrap();
+        let statuses: Vec<OnlineStatus> = transitions.iter().map(|t| t.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                OnlineStatus::Online,
+                OnlineStatus::Idle,
+                OnlineStatus::Offline,
+                OnlineStatus::DoNotDisturb
+            ]
+        );
+        let last = last_transition(&db, guild_id, user_id).unwrap().unwrap();
+        assert_eq!(last.at, start + Duration::minutes(5));
+        assert!(last_transition(&db, GuildId(2), user_id).unwrap().is_none());
+
+        let history = user_sessions(&db, guild_id, user_id).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].end, Some(start + Duration::minutes(3)));
+        assert_eq!(history[1].start, start + Duration::minutes(5));
+        assert_eq!(history[1].end, None);
+    }
+
+    #[test]
+    fn durations_are_split_in_hours_minutes_and_seconds() {
+        assert_eq!(format_duration(0), "0h 0m 0s");
+        assert_eq!(format_duration(3 * 3600 + 25 * 60 + 7), "3h 25m 7s");
+        assert_eq!(format_duration(-5), "0h 0m 0s");
+    }
+
+    #[test]
+    fn status_kinds_parse_case_insensitively() {
+        assert_eq!("Active".parse::<StatusKind>().unwrap(), StatusKind::Active);
+        assert_eq!("online".parse::<StatusKind>().unwrap(), StatusKind::Active);
+        assert_eq!("AFK".parse::<StatusKind>().unwrap(), StatusKind::Idle);
+        assert_eq!(
+            "dnd".parse::<StatusKind>().unwrap(),
+            StatusKind::DoNotDisturb
+        );
+        assert!("offline".parse::<StatusKind>().is_err());
+        for kind in StatusKind::ALL.iter().copied() {
+            assert_eq!(kind.name().parse::<StatusKind>().unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn time_is_split_between_statuses() {
+        let db = temporary_db();
+        let start = Utc::now();
+        let mut sessions = GuildSessions::new();
+        let (guild_id, user_id) = (GuildId(1), UserId(1));
+        for (minutes, status) in [
+            (0, OnlineStatus::Online),
+            (10, OnlineStatus::Idle),
+            (15, OnlineStatus::DoNotDisturb),
+            (20, OnlineStatus::Online),
+        ] {
+            let now = start + Duration::minutes(minutes);
+            update(&db, &mut sessions, guild_id, user_id, status, now).unwrap();
+        }
+        let session = &sessions[&user_id];
+        assert_eq!(session.status_since, start + Duration::minutes(20));
+        let times = session.times_at(start + Duration::minutes(30));
+        assert_eq!((times.active, times.idle, times.dnd), (1200, 300, 300));
+
+        // A closed session no longer counts the time after its end.
+        let end = start + Duration::minutes(25);
+        update(
+            &db,
+            &mut sessions,
+            guild_id,
+            user_id,
+            OnlineStatus::Offline,
+            end,
+        )
+        .unwrap();
+        let stored = user_sessions(&db, guild_id, user_id).unwrap();
+        let times = stored[0].times_at(end + Duration::hours(1));
+        assert_eq!((times.active, times.idle, times.dnd), (900, 300, 300));
+    }
+
+    #[test]
+    fn filters_accept_all_in_any_case() {
+        assert_eq!(parse_filter(None).unwrap(), None);
+        assert_eq!(parse_filter(Some("ALL")).unwrap(), None);
+        assert_eq!(parse_filter(Some("Idle")).unwrap(), Some(StatusKind::Idle));
+        assert!(parse_filter(Some("away")).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+//! Dice rolls and random draws. Every draw is made from a seed stored with
+//! its outcome, so that `roll audit` can recompute it when it is disputed.
+
+use chrono::{DateTime, Utc};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    constants::MESSAGE_CODE_LIMIT,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, UserId},
+    },
+};
+use thiserror::Error;
+
+use crate::{
+    db::{self, DbResult},
+    expr::{self, ExprError},
+};
+
+const DRAWS_TREE: &str = "random_draws";
+const MAX_COINS: u32 = 100;
+/// The inputs shown by `roll audit`, in characters.
+const AUDIT_INPUTS: usize = 200;
+
+#[group]
+#[commands(roll, pick, shuffle, coin)]
+struct Random;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawKind {
+    Roll,
+    Pick,
+    Shuffle,
+    Coin,
+}
+
+#[derive(Debug, Error)]
+pub enum DrawError {
+    #[error("could not parse `{term}`: {source}")]
+    Expression { term: String, source: ExprError },
+    #[error("nothing to draw from")]
+    Empty,
+    #[error("between 1 and {} coins can be flipped at once", MAX_COINS)]
+    InvalidCoins,
+    #[error("the outcome would not fit in a message, draw fewer items")]
+    TooLong,
+}
+
+/// A draw as recorded in the database.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Draw {
+    pub id: u64,
+    pub kind: DrawKind,
+    pub user_id: UserId,
+    pub channel_id: ChannelId,
+    pub inputs: Vec<String>,
+    pub seed: u64,
+    /// Whether the seed was given by the user rather than drawn.
+    pub chosen_seed: bool,
+    pub outcome: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Computes the outcome of a draw, which only depends on its inputs and seed.
+pub fn outcome(kind: DrawKind, inputs: &[String], seed: u64) -> Result<String, DrawError> {
+    outcome_from(kind, inputs, &mut ChaCha8Rng::seed_from_u64(seed))
+}
+
+fn outcome_from<R: Rng>(
+    kind: DrawKind,
+    inputs: &[String],
+    rng: &mut R,
+) -> Result<String, DrawError> {
+    match kind {
+        DrawKind::Roll => {
+            let mut lines = Vec::new();
+            for term in inputs {
+                let evaluation =
+                    expr::evaluate(term, rng).map_err(|source| DrawError::Expression {
+                        term: term.clone(),
+                        source,
+                    })?;
+                lines.push(evaluation.summary(term));
+            }
+            Ok(lines.join("\n"))
+        }
+        DrawKind::Pick => inputs
+            .choose(rng)
+            .map(|choice| format!("**{}**", choice))
+            .ok_or(DrawError::Empty),
+        DrawKind::Shuffle => {
+            if inputs.is_empty() {
+                return Err(DrawError::Empty);
+            }
+            let mut items = inputs.to_vec();
+            items.shuffle(rng);
+            Ok(items.join(", "))
+        }
+        DrawKind::Coin => {
+            let count = match inputs.first() {
+                Some(count) => count.parse().map_err(|_| DrawError::InvalidCoins)?,
+                None => 1,
+            };
+            if count == 0 || count > MAX_COINS {
+                return Err(DrawError::InvalidCoins);
+            }
+            let flips: Vec<bool> = (0..count).map(|_| rng.gen()).collect();
+            let heads = flips.iter().filter(|heads| **heads).count();
+            let faces: Vec<&str> = flips
+                .iter()
+                .map(|heads| if *heads { "heads" } else { "tails" })
+                .collect();
+            if count == 1 {
+                Ok(format!("**{}**", faces[0]))
+            } else {
+                Ok(format!(
+                    "{} — **{} heads, {} tails**",
+                    faces.join(", "),
+                    heads,
+                    count as usize - heads
+                ))
+            }
+        }
+    }
+}
+
+pub fn record(db: &sled::Db, draw: &Draw) -> DbResult<()> {
+    db::insert(&db.open_tree(DRAWS_TREE)?, draw.id.to_be_bytes(), draw)
+}
+
+pub fn load(db: &sled::Db, id: u64) -> DbResult<Option<Draw>> {
+    db::get(&db.open_tree(DRAWS_TREE)?, id.to_be_bytes())
+}
+
+/// Cuts `text` to at most `length` characters.
+fn clip(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
+    }
+    let mut clipped: String = text.chars().take(length.saturating_sub(1)).collect();
+    clipped.push('…');
+    clipped
+}
+
+/// Draws, records the draw and replies with its outcome, or with the reason
+/// it could not be made. Invalid draws, and those whose outcome would not fit
+/// in a message, are not recorded.
+async fn draw(
+    ctx: &Context,
+    msg: &Message,
+    kind: DrawKind,
+    inputs: Vec<String>,
+    seed: Option<u64>,
+) -> CommandResult {
+    let chosen_seed = seed.is_some();
+    let seed = seed.unwrap_or_else(rand::random);
+    let outcome = match outcome(kind, &inputs, seed) {
+        Ok(outcome) => outcome,
+        Err(why) => {
+            msg.reply(ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+    let db = db::from_context(ctx).await;
+    let draw = Draw {
+        id: db.generate_id().map_err(db::DbError::from)?,
+        kind,
+        user_id: msg.author.id,
+        channel_id: msg.channel_id,
+        inputs,
+        seed,
+        chosen_seed,
+        outcome,
+        at: Utc::now(),
+    };
+    let reply = format!("{}\n*draw #{}, seed {}*", draw.outcome, draw.id, draw.seed);
+    // The reply mentions its author in front of the content.
+    if reply.chars().count() + format!("<@{}>: ", msg.author.id).len() > MESSAGE_CODE_LIMIT {
+        msg.reply(ctx, DrawError::TooLong.to_string()).await?;
+        return Ok(());
+    }
+    record(&db, &draw)?;
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+fn inputs(mut args: Args) -> Vec<String> {
+    args.iter::<String>()
+        .filter_map(Result::ok)
+        .map(|input| input.trim().to_string())
+        .filter(|input| !input.is_empty())
+        .collect()
+}
+
+#[command]
+#[aliases("r")]
+#[sub_commands(seeded, audit)]
+#[description = "Roll dice: `4d6kh3` keeps the 3 highest, `d6!` explodes, `2d10r1` rerolls ones, `adv` and `dis` roll with advantage and disadvantage."]
+#[usage = "[expression, expression...]"]
+#[example = "4d6kh3, adv+5, 3d6!r1"]
+async fn roll(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut inputs = inputs(args);
+    if inputs.is_empty() {
+        inputs.push("d20".to_string());
+    }
+    draw(ctx, msg, DrawKind::Roll, inputs, None).await
+}
+
+#[command]
+#[description = "Roll dice from a chosen seed, giving the same results each time."]
+#[usage = "seed, expression[, expression...]"]
+#[example = "42, 2d6+3"]
+async fn seeded(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let seed = match args.trimmed().single::<u64>() {
+        Ok(seed) => seed,
+        Err(_) => {
+            msg.reply(ctx, "usage: `roll seeded seed, expression`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut inputs = inputs(args);
+    if inputs.is_empty() {
+        inputs.push("d20".to_string());
+    }
+    draw(ctx, msg, DrawKind::Roll, inputs, Some(seed)).await
+}
+
+#[command]
+#[description = "Recompute a recorded draw from its seed to check its outcome."]
+#[usage = "draw id"]
+#[example = "12"]
+async fn audit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let id = match args.trimmed().single::<String>() {
+        Ok(id) => id.trim_start_matches('#').parse::<u64>().ok(),
+        Err(_) => None,
+    };
+    let id = match id {
+        Some(id) => id,
+        None => {
+            msg.reply(ctx, "usage: `roll audit draw id`").await?;
+            return Ok(());
+        }
+    };
+    let db = db::from_context(ctx).await;
+    let draw = match load(&db, id)? {
+        Some(draw) => draw,
+        None => {
+            msg.reply(ctx, format!("no draw #{}", id)).await?;
+            return Ok(());
+        }
+    };
+    let verdict = match outcome(draw.kind, &draw.inputs, draw.seed) {
+        Ok(outcome) if outcome == draw.outcome => "the recorded outcome matches its seed",
+        _ => "**the recorded outcome does not match its seed**",
+    };
+    let header = format!(
+        "<@{}>: draw #{} by <@{}> in <#{}>, {}\n{:?} of `{}` with {} seed {}\n",
+        msg.author.id,
+        draw.id,
+        draw.user_id,
+        draw.channel_id,
+        crate::presence::format_time(draw.at),
+        draw.kind,
+        clip(&draw.inputs.join(", "), AUDIT_INPUTS),
+        if draw.chosen_seed { "chosen" } else { "drawn" },
+        draw.seed,
+    );
+    // Outcomes recorded before their length was checked are cut to fit.
+    let room = MESSAGE_CODE_LIMIT - header.chars().count() - verdict.chars().count() - 1;
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content(format!(
+                "{}{}\n{}",
+                header,
+                clip(&draw.outcome, room),
+                verdict
+            ))
+            .allowed_mentions(|a| a.empty_parse().users(vec![msg.author.id]))
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[aliases("choose")]
+#[description = "Pick one of the given choices at random."]
+#[usage = "choice, choice[, choice...]"]
+#[example = "pizza, sushi, tacos"]
+async fn pick(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    draw(ctx, msg, DrawKind::Pick, inputs(args), None).await
+}
+
+#[command]
+#[description = "Shuffle the given items."]
+#[usage = "item, item[, item...]"]
+#[example = "Alice, Bob, Carol, Dave"]
+async fn shuffle(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    draw(ctx, msg, DrawKind::Shuffle, inputs(args), None).await
+}
+
+#[command]
+#[aliases("flip")]
+#[description = "Flip one or more coins."]
+#[usage = "[count]"]
+#[example = "3"]
+async fn coin(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    draw(ctx, msg, DrawKind::Coin, inputs(args), None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn outcomes_only_depend_on_the_seed() {
+        let names: Vec<String> = (0..1000).map(|name| name.to_string()).collect();
+        let draws = [
+            (
+                DrawKind::Roll,
+                strings(&["4d6kh3", "adv+5", "3d6!r1", "10d100"]),
+            ),
+            (DrawKind::Pick, names.clone()),
+            (DrawKind::Shuffle, names[..20].to_vec()),
+            (DrawKind::Coin, strings(&["64"])),
+        ];
+        for (kind, inputs) in &draws {
+            for seed in 0..20 {
+                let mut first = ChaCha8Rng::seed_from_u64(seed);
+                let mut second = ChaCha8Rng::seed_from_u64(seed);
+                let mut other = ChaCha8Rng::seed_from_u64(seed + 100);
+                let drawn = outcome_from(*kind, inputs, &mut first).unwrap();
+                assert_eq!(drawn, outcome_from(*kind, inputs, &mut second).unwrap());
+                assert_ne!(drawn, outcome_from(*kind, inputs, &mut other).unwrap());
+                assert_eq!(drawn, outcome(*kind, inputs, seed).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn long_texts_are_clipped() {
+        assert_eq!(clip("2d6", 3), "2d6");
+        assert_eq!(clip("2d6+3", 3), "2d…");
+        assert_eq!(clip(&"é".repeat(50), 10).chars().count(), 10);
+    }
+
+    #[test]
+    fn audit_recomputes_a_recorded_draw() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let inputs = strings(&["2d6+3"]);
+        let draw = Draw {
+            id: 1,
+            kind: DrawKind::Roll,
+            user_id: UserId(1),
+            channel_id: ChannelId(1),
+            outcome: outcome(DrawKind::Roll, &inputs, 42).unwrap(),
+            inputs,
+            seed: 42,
+            chosen_seed: true,
+            at: Utc::now(),
+        };
+        record(&db, &draw).unwrap();
+        let loaded = load(&db, 1).unwrap().unwrap();
+        assert_eq!(
+            outcome(loaded.kind, &loaded.inputs, loaded.seed).unwrap(),
+            draw.outcome
+        );
+    }
+
+    #[test]
+    fn invalid_draws() {
+        assert!(matches!(
+            outcome(DrawKind::Pick, &[], 0),
+            Err(DrawError::Empty)
+        ));
+        assert!(matches!(
+            outcome(DrawKind::Coin, &strings(&["0"]), 0),
+            Err(DrawError::InvalidCoins)
+        ));
+        assert!(matches!(
+            outcome(DrawKind::Roll, &strings(&["2d"]), 0),
+            Err(DrawError::Expression { .. })
+        ));
+    }
+}
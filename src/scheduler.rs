@@ -0,0 +1,365 @@
+//! Jobs run at a given time: phase transitions, reminders and timeouts. Jobs
+//! are saved before being scheduled, so those pending when the bot stops run
+//! once it is back, late if their time has passed.
+//!
+//! A job runs at most once and is never cancelled: it checks when it runs that
+//! what it was scheduled for, such as a poll or a night, is still going on.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
+    prelude::TypeMapKey,
+};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    config::Config,
+    db::{self, Database, DbResult},
+    presence::format_time,
+    sporz::Phase,
+    table, vote,
+};
+
+const JOBS_TREE: &str = "scheduled_jobs";
+/// How long to wait before reading the jobs again after a database error.
+const RETRY_SECONDS: u64 = 60;
+/// How long before a deadline its reminder is sent.
+pub const REMINDER_MINUTES: i64 = 2;
+
+#[group]
+#[commands(remind)]
+struct Reminders;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "job", rename_all = "snake_case")]
+pub enum Job {
+    /// Posts `content` in `channel_id`, mentioning `user_id`.
+    Reminder {
+        channel_id: ChannelId,
+        user_id: Option<UserId>,
+        content: String,
+    },
+    /// Reminds the voters of poll `poll` that it is about to close.
+    PollReminder {
+        channel_id: ChannelId,
+        poll: u64,
+    },
+    ClosePoll {
+        channel_id: ChannelId,
+        poll: u64,
+    },
+    /// Reminds the players of the table of `guild_id` that `night` is about
+    /// to end.
+    NightReminder {
+        guild_id: GuildId,
+        night: u32,
+    },
+    /// Resolves `night` of the table of `guild_id`, unless it already is.
+    EndNight {
+        guild_id: GuildId,
+        night: u32,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Task {
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub job: Job,
+}
+
+/// Wakes the scheduler up when a job is added.
+#[derive(Default)]
+pub struct State {
+    wake: Notify,
+    started: AtomicBool,
+}
+
+pub struct Scheduler;
+
+impl TypeMapKey for Scheduler {
+    type Value = Arc<State>;
+}
+
+/// Tasks are keyed by time then id, so the first one is the next to run.
+fn key(task: &Task) -> Vec<u8> {
+    let mut key = (task.at.timestamp_millis().max(0) as u64)
+        .to_be_bytes()
+        .to_vec();
+    key.extend_from_slice(&task.id.to_be_bytes());
+    key
+}
+
+/// Runs `job` at `at`, or as soon as possible if `at` is past.
+pub async fn schedule(ctx: &Context, at: DateTime<Utc>, job: Job) -> DbResult<u64> {
+    let (db, state) = {
+        let data = ctx.data.read().await;
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
+        let state = data
+            .get::<Scheduler>()
+            .cloned()
+            .expect("Expected Scheduler in TypeMap.");
+        (db, state)
+    };
+    let task = Task {
+        id: db.generate_id()?,
+        at,
+        job,
+    };
+    db::insert(&db.open_tree(JOBS_TREE)?, key(&task), &task)?;
+    debug!(task = task.id, at = %task.at, job = ?task.job, "Scheduled a job");
+    state.wake.notify_one();
+    Ok(task.id)
+}
+
+/// Schedules a reminder of `job` a few minutes before `deadline`, if there is
+/// still time for it.
+pub async fn schedule_reminder(ctx: &Context, deadline: DateTime<Utc>, job: Job) -> DbResult<()> {
+    let at = deadline - Duration::minutes(REMINDER_MINUTES);
+    if at > Utc::now() {
+        schedule(ctx, at, job).await?;
+    }
+    Ok(())
+}
+
+fn next(db: &sled::Db) -> DbResult<Option<Task>> {
+    db::first_with_prefix(&db.open_tree(JOBS_TREE)?, b"")
+}
+
+fn remove(db: &sled::Db, task: &Task) -> DbResult<()> {
+    db.open_tree(JOBS_TREE)?.remove(key(task))?;
+    Ok(())
+}
+
+/// Starts running the scheduled jobs, once per process.
+pub async fn start(ctx: &Context) {
+    let state = ctx
+        .data
+        .read()
+        .await
+        .get::<Scheduler>()
+        .cloned()
+        .expect("Expected Scheduler in TypeMap.");
+    if !state.started.swap(true, Ordering::SeqCst) {
+        tokio::spawn(run(ctx.clone(), state));
+    }
+}
+
+async fn run(ctx: Context, state: Arc<State>) {
+    let db = db::from_context(&ctx).await;
+    info!("Running the scheduled jobs");
+    loop {
+        match next(&db) {
+            Ok(Some(task)) if task.at <= Utc::now() => match remove(&db, &task) {
+                Ok(()) => {
+                    tokio::spawn(execute(ctx.clone(), task));
+                }
+                Err(why) => {
+                    error!(task = task.id, "Could not remove a scheduled job: {}", why);
+                    tokio::time::sleep(std::time::Duration::from_secs(RETRY_SECONDS)).await;
+                }
+            },
+            Ok(Some(task)) => {
+                let delay = (task.at - Utc::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = state.wake.notified() => {}
+                }
+            }
+            Ok(None) => state.wake.notified().await,
+            Err(why) => {
+                error!("Could not read the scheduled jobs: {}", why);
+                tokio::time::sleep(std::time::Duration::from_secs(RETRY_SECONDS)).await;
+            }
+        }
+    }
+}
+
+async fn execute(ctx: Context, task: Task) {
+    debug!(task = task.id, job = ?task.job, "Running a scheduled job");
+    match task.job {
+        Job::Reminder {
+            channel_id,
+            user_id,
+            content,
+        } => {
+            let content = match user_id {
+                Some(user_id) => format!("⏰ <@{}> {}", user_id, content),
+                None => format!("⏰ {}", content),
+            };
+            // The content is typed by users: only the reminded user is pinged.
+            let result = channel_id
+                .send_message(&ctx.http, |m| {
+                    m.content(content)
+                        .allowed_mentions(|a| a.empty_parse().users(user_id))
+                })
+                .await;
+            if let Err(why) = result {
+                warn!(task = task.id, "Could not send a reminder: {}", why);
+            }
+        }
+        Job::PollReminder { channel_id, poll } => vote::remind(&ctx, channel_id, poll).await,
+        Job::ClosePoll { channel_id, poll } => {
+            if let Err(why) = vote::close_if_open(&ctx, channel_id, poll).await {
+                warn!(poll, "Could not close the poll: {}", why);
+            }
+        }
+        Job::NightReminder { guild_id, night } => table::remind_night(&ctx, guild_id, night).await,
+        Job::EndNight { guild_id, night } => {
+            // The night usually ends before, once every prompt is answered.
+            if let Err(why) = table::advance(&ctx, guild_id, Phase::Night(night)).await {
+                debug!(%guild_id, night, "Night not ended by the scheduler: {}", why);
+            }
+        }
+    }
+}
+
+/// Splits the duration off the start of `input`, ending at the first blank or
+/// delimiter. The message may contain the delimiters, it is not split further.
+fn split_duration<'a>(input: &'a str, delimiters: &[String]) -> (&'a str, &'a str) {
+    let input = input.trim();
+    let delimiters: Vec<&str> = delimiters
+        .iter()
+        .map(|delimiter| delimiter.trim())
+        .filter(|delimiter| !delimiter.is_empty())
+        .collect();
+    let end = input
+        .char_indices()
+        .find(|(index, c)| {
+            c.is_whitespace()
+                || delimiters
+                    .iter()
+                    .any(|delimiter| input[*index..].starts_with(delimiter))
+        })
+        .map_or(input.len(), |(index, _)| index);
+    let (duration, rest) = input.split_at(end);
+    let rest = rest.trim_start();
+    let rest = delimiters
+        .iter()
+        .find_map(|delimiter| rest.strip_prefix(delimiter))
+        .unwrap_or(rest);
+    (duration, rest.trim_start())
+}
+
+#[command]
+#[description = "Remind you of something in this channel after a while."]
+#[usage = "duration message"]
+#[example = "10m take the pizza out"]
+async fn remind(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let delimiters = {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("Expected Config in TypeMap.");
+        config.delimiters.clone()
+    };
+    let (duration, content) = split_duration(args.rest(), &delimiters);
+    let duration = match vote::parse_duration(duration) {
+        Some(duration) => duration,
+        None => {
+            msg.reply(ctx, "give a duration such as `30s`, `10m` or `2h`")
+                .await?;
+            return Ok(());
+        }
+    };
+    let at = Utc::now() + duration;
+    let job = Job::Reminder {
+        channel_id: msg.channel_id,
+        user_id: Some(msg.author.id),
+        content: content.to_string(),
+    };
+    schedule(ctx, at, job).await?;
+    msg.reply(ctx, format!("I will remind you {}.", format_time(at)))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: u64, at: DateTime<Utc>) -> Task {
+        Task {
+            id,
+            at,
+            job: Job::ClosePoll {
+                channel_id: ChannelId(1),
+                poll: id,
+            },
+        }
+    }
+
+    #[test]
+    fn the_next_task_is_the_earliest() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let now = Utc::now();
+        let tasks = [
+            task(1, now + Duration::minutes(10)),
+            task(2, now - Duration::minutes(5)),
+            task(3, now + Duration::seconds(1)),
+            task(4, now - Duration::minutes(5)),
+        ];
+        let tree = db.open_tree(JOBS_TREE).unwrap();
+        for task in &tasks {
+            db::insert(&tree, key(task), task).unwrap();
+        }
+        let mut order = Vec::new();
+        while let Some(task) = next(&db).unwrap() {
+            remove(&db, &task).unwrap();
+            order.push(task.id);
+        }
+        assert_eq!(order, vec![2, 4, 3, 1]);
+    }
+
+    #[test]
+    fn tasks_survive_a_round_trip() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let task = Task {
+            id: 7,
+            at: Utc::now(),
+            job: Job::Reminder {
+                channel_id: ChannelId(1),
+                user_id: Some(UserId(2)),
+                content: "bins".to_string(),
+            },
+        };
+        db::insert(&db.open_tree(JOBS_TREE).unwrap(), key(&task), &task).unwrap();
+        let loaded = next(&db).unwrap().unwrap();
+        assert_eq!(loaded.id, 7);
+        assert!(matches!(loaded.job, Job::Reminder { content, .. } if content == "bins"));
+    }
+
+    #[test]
+    fn durations_end_at_a_blank_or_a_delimiter() {
+        let delimiters = vec![", ".to_string(), ",".to_string()];
+        assert_eq!(
+            split_duration("10m take out the bins", &delimiters),
+            ("10m", "take out the bins")
+        );
+        assert_eq!(
+            split_duration("10m,take out the bins, now", &delimiters),
+            ("10m", "take out the bins, now")
+        );
+        assert_eq!(split_duration(" 10m , bins", &delimiters), ("10m", "bins"));
+        assert_eq!(split_duration("10m", &delimiters), ("10m", ""));
+        assert_eq!(
+            split_duration("10m;bins", &[";".to_string()]),
+            ("10m", "bins")
+        );
+    }
+}
@@ -0,0 +1,531 @@
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group, hook},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+    },
+};
+use std::{collections::BTreeSet, fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::db::{self, DbResult};
+
+const SETTINGS_TREE: &str = "guild_settings";
+
+#[group]
+#[commands(config)]
+#[only_in(guilds)]
+struct Settings;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    En,
+    Fr,
+}
+
+/// Fixed replies of the settings commands.
+struct Messages {
+    default_prefix: &'static str,
+    any_channel: &'static str,
+    no_group: &'static str,
+    set_to: &'static str,
+    reset: &'static str,
+    set_usage: &'static str,
+}
+
+const ENGLISH: Messages = Messages {
+    default_prefix: "default",
+    any_channel: "any channel",
+    no_group: "none",
+    set_to: "set to",
+    reset: "settings reset",
+    set_usage: "usage: `config set setting, value`",
+};
+
+const FRENCH: Messages = Messages {
+    default_prefix: "par défaut",
+    any_channel: "tous les salons",
+    no_group: "aucun",
+    set_to: "vaut maintenant",
+    reset: "paramètres réinitialisés",
+    set_usage: "utilisation : `config set paramètre, valeur`",
+};
+
+impl Language {
+    fn messages(self) -> &'static Messages {
+        match self {
+            Language::En => &ENGLISH,
+            Language::Fr => &FRENCH,
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Language::En => "en",
+            Language::Fr => "fr",
+        })
+    }
+}
+
+/// Settings an administrator can change at runtime, stored per guild.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Replaces the configured prefixes in this guild.
+    pub prefix: Option<String>,
+    /// When set, commands are only accepted in this channel.
+    pub bot_channel: Option<ChannelId>,
+    pub language: Language,
+    /// Lowercase names of the command groups disabled in this guild.
+    pub disabled_groups: BTreeSet<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum SettingError {
+    #[error(
+        "unknown setting `{0}`, expected one of prefix, bot_channel, language or disabled_groups"
+    )]
+    UnknownKey(String),
+    #[error("a value is required for `{0}`")]
+    MissingValue(&'static str),
+    #[error("the prefix cannot contain spaces")]
+    InvalidPrefix,
+    #[error("`{0}` is not a channel")]
+    InvalidChannel(String),
+    #[error("unknown language `{0}`, expected en or fr")]
+    InvalidLanguage(String),
+    #[error("unknown command group `{0}`")]
+    UnknownGroup(String),
+    #[error("the settings group cannot be disabled")]
+    SettingsGroup,
+}
+
+impl SettingError {
+    /// The error in `language`, English being the `Display` implementation.
+    fn localized(&self, language: Language) -> String {
+        if language == Language::En {
+            return self.to_string();
+        }
+        match self {
+            SettingError::UnknownKey(key) => format!(
+                "paramètre `{}` inconnu, valeurs possibles : prefix, bot_channel, language ou disabled_groups",
+                key
+            ),
+            SettingError::MissingValue(key) => format!("une valeur est requise pour `{}`", key),
+            SettingError::InvalidPrefix => "le préfixe ne peut pas contenir d'espace".to_string(),
+            SettingError::InvalidChannel(channel) => {
+                format!("`{}` n'est pas un salon", channel)
+            }
+            SettingError::InvalidLanguage(language) => {
+                format!("langue `{}` inconnue, valeurs possibles : en ou fr", language)
+            }
+            SettingError::UnknownGroup(group) => {
+                format!("groupe de commandes `{}` inconnu", group)
+            }
+            SettingError::SettingsGroup => {
+                "le groupe settings ne peut pas être désactivé".to_string()
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingKey {
+    Prefix,
+    BotChannel,
+    Language,
+    DisabledGroups,
+}
+
+impl SettingKey {
+    const ALL: [SettingKey; 4] = [
+        SettingKey::Prefix,
+        SettingKey::BotChannel,
+        SettingKey::Language,
+        SettingKey::DisabledGroups,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            SettingKey::Prefix => "prefix",
+            SettingKey::BotChannel => "bot_channel",
+            SettingKey::Language => "language",
+            SettingKey::DisabledGroups => "disabled_groups",
+        }
+    }
+}
+
+impl FromStr for SettingKey {
+    type Err = SettingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SettingKey::ALL
+            .iter()
+            .copied()
+            .find(|key| key.name() == s.to_lowercase())
+            .ok_or_else(|| SettingError::UnknownKey(s.to_string()))
+    }
+}
+
+impl GuildSettings {
+    fn get(&self, key: SettingKey) -> String {
+        let messages = self.language.messages();
+        match key {
+            SettingKey::Prefix => self
+                .prefix
+                .clone()
+                .unwrap_or_else(|| messages.default_prefix.to_string()),
+            SettingKey::BotChannel => self
+                .bot_channel
+                .map_or(messages.any_channel.to_string(), |channel| {
+                    format!("<#{}>", channel)
+                }),
+            SettingKey::Language => self.language.to_string(),
+            SettingKey::DisabledGroups if self.disabled_groups.is_empty() => {
+                messages.no_group.to_string()
+            }
+            SettingKey::DisabledGroups => self
+                .disabled_groups
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", "),
+        }
+    }
+
+    fn set(&mut self, key: SettingKey, values: &[String]) -> Result<(), SettingError> {
+        let value = values.first().map(|value| value.trim());
+        match key {
+            SettingKey::Prefix => {
+                let prefix = value.ok_or(SettingError::MissingValue(key.name()))?;
+                if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+                    return Err(SettingError::InvalidPrefix);
+                }
+                self.prefix = Some(prefix.to_string());
+            }
+            SettingKey::BotChannel => {
+                let channel = value.ok_or(SettingError::MissingValue(key.name()))?;
+                self.bot_channel = Some(
+                    channel
+                        .parse()
+                        .map_err(|_| SettingError::InvalidChannel(channel.to_string()))?,
+                );
+            }
+            SettingKey::Language => {
+                self.language = match value.ok_or(SettingError::MissingValue(key.name()))? {
+                    "en" => Language::En,
+                    "fr" => Language::Fr,
+                    language => return Err(SettingError::InvalidLanguage(language.to_string())),
+                };
+            }
+            SettingKey::DisabledGroups => {
+                let known = group_names();
+                let mut disabled = BTreeSet::new();
+                for group in values.iter().map(|group| group.trim().to_lowercase()) {
+                    if group == "settings" {
+                        return Err(SettingError::SettingsGroup);
+                    }
+                    if !known.contains(&group) {
+                        return Err(SettingError::UnknownGroup(group));
+                    }
+                    disabled.insert(group);
+                }
+                self.disabled_groups = disabled;
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self, key: SettingKey) {
+        let default = GuildSettings::default();
+        match key {
+            SettingKey::Prefix => self.prefix = default.prefix,
+            SettingKey::BotChannel => self.bot_channel = default.bot_channel,
+            SettingKey::Language => self.language = default.language,
+            SettingKey::DisabledGroups => self.disabled_groups = default.disabled_groups,
+        }
+    }
+}
+
+fn group_names() -> Vec<String> {
+    crate::GROUPS
+        .iter()
+        .map(|group| group.name.to_lowercase())
+        .collect()
+}
+
+/// Lowercase name of the group a command belongs to.
+fn group_of(command_name: &str) -> Option<String> {
+    crate::GROUPS
+        .iter()
+        .find(|group| crate::stats::command_names(group).contains(&command_name))
+        .map(|group| group.name.to_lowercase())
+}
+
+pub fn load(db: &sled::Db, guild_id: GuildId) -> DbResult<GuildSettings> {
+    Ok(db::get(&db.open_tree(SETTINGS_TREE)?, guild_id.0.to_be_bytes())?.unwrap_or_default())
+}
+
+pub fn save(db: &sled::Db, guild_id: GuildId, settings: &GuildSettings) -> DbResult<()> {
+    db::insert(
+        &db.open_tree(SETTINGS_TREE)?,
+        guild_id.0.to_be_bytes(),
+        settings,
+    )
+}
+
+/// Makes the prefix of the guild the message was sent in recognised.
+#[hook]
+pub async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    let guild_id = msg.guild_id?;
+    let db = db::from_context(ctx).await;
+    load(&db, guild_id).ok()?.prefix
+}
+
+/// Whether the guild settings allow `command_name` to run for this message.
+/// Settings commands are always allowed so that a mistake can be undone.
+pub async fn allows(ctx: &Context, msg: &Message, command_name: &str) -> DbResult<bool> {
+    let guild_id = match msg.guild_id {
+        Some(guild_id) => guild_id,
+        None => return Ok(true),
+    };
+    let db = db::from_context(ctx).await;
+    let settings = load(&db, guild_id)?;
+    if let Some(prefix) = &settings.prefix {
+        // The configured prefixes stay recognised by the framework, they are
+        // rejected here in guilds with their own prefix.
+        let mentioned = msg.mentions_me(ctx).await.unwrap_or(false);
+        if !msg.content.starts_with(prefix.as_str())
+            && !mentioned
+            && group_of(command_name).as_deref() != Some("settings")
+        {
+            return Ok(false);
+        }
+    }
+    Ok(settings.allows_command(msg.channel_id, command_name))
+}
+
+impl GuildSettings {
+    /// Whether `command_name` may run in `channel_id`, whichever way it was
+    /// invoked.
+    pub fn allows_command(&self, channel_id: ChannelId, command_name: &str) -> bool {
+        let group = group_of(command_name);
+        if group.as_deref() == Some("settings") {
+            return true;
+        }
+        if self
+            .bot_channel
+            .is_some_and(|channel| channel != channel_id)
+        {
+            return false;
+        }
+        group.map_or(true, |group| !self.disabled_groups.contains(&group))
+    }
+}
+
+#[command]
+#[required_permissions("ADMINISTRATOR")]
+#[sub_commands(get, set, reset)]
+#[description = "Show or change the settings of this server."]
+#[usage = "[get|set|reset]"]
+async fn config(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let db = db::from_context(ctx).await;
+    let settings = load(&db, guild_id)?;
+    let reply = SettingKey::ALL
+        .iter()
+        .map(|key| format!("{}: {}", key.name(), settings.get(*key)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[required_permissions("ADMINISTRATOR")]
+#[description = "Show a setting, or all of them."]
+#[usage = "[setting]"]
+async fn get(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let db = db::from_context(ctx).await;
+    let settings = load(&db, guild_id)?;
+    let keys = match args.trimmed().current() {
+        Some(key) => match key.parse::<SettingKey>() {
+            Ok(key) => vec![key],
+            Err(why) => {
+                msg.reply(ctx, why.localized(settings.language)).await?;
+                return Ok(());
+            }
+        },
+        None => SettingKey::ALL.to_vec(),
+    };
+    let reply = keys
+        .iter()
+        .map(|key| format!("{}: {}", key.name(), settings.get(*key)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[required_permissions("ADMINISTRATOR")]
+#[description = "Change a setting."]
+#[usage = "setting, value[, value...]"]
+#[example = "disabled_groups, presence, stats"]
+async fn set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let db = db::from_context(ctx).await;
+    let mut settings = load(&db, guild_id)?;
+    let key = match args.trimmed().single::<String>() {
+        Ok(key) => key,
+        Err(_) => {
+            msg.reply(ctx, settings.language.messages().set_usage)
+                .await?;
+            return Ok(());
+        }
+    };
+    let values: Vec<String> = args.iter::<String>().filter_map(Result::ok).collect();
+    let result = key
+        .parse::<SettingKey>()
+        .and_then(|key| settings.set(key, &values).map(|_| key));
+    match result {
+        Ok(key) => {
+            save(&db, guild_id, &settings)?;
+            // Replies in the new language when the language was just changed.
+            let reply = format!(
+                "{} {} {}",
+                key.name(),
+                settings.language.messages().set_to,
+                settings.get(key)
+            );
+            msg.reply(ctx, reply).await?;
+        }
+        Err(why) => {
+            msg.reply(ctx, why.localized(settings.language)).await?;
+        }
+    }
+    Ok(())
+}
+
+#[command]
+#[required_permissions("ADMINISTRATOR")]
+#[description = "Reset a setting, or all of them, to the default."]
+#[usage = "[setting]"]
+async fn reset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let db = db::from_context(ctx).await;
+    let mut settings = load(&db, guild_id)?;
+    match args.trimmed().current() {
+        Some(key) => match key.parse::<SettingKey>() {
+            Ok(key) => settings.reset(key),
+            Err(why) => {
+                msg.reply(ctx, why.localized(settings.language)).await?;
+                return Ok(());
+            }
+        },
+        None => settings = GuildSettings::default(),
+    }
+    save(&db, guild_id, &settings)?;
+    msg.reply(ctx, settings.language.messages().reset).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn keys_parse_case_insensitively() {
+        assert_eq!(
+            "Bot_Channel".parse::<SettingKey>().unwrap(),
+            SettingKey::BotChannel
+        );
+        for key in SettingKey::ALL.iter().copied() {
+            assert_eq!(key.name().parse::<SettingKey>().unwrap(), key);
+        }
+        assert!(matches!(
+            "colour".parse::<SettingKey>(),
+            Err(SettingError::UnknownKey(key)) if key == "colour"
+        ));
+    }
+
+    #[test]
+    fn settings_are_set_and_reset() {
+        let mut settings = GuildSettings::default();
+        assert_eq!(settings.get(SettingKey::Prefix), "default");
+        settings.set(SettingKey::Prefix, &values(&[" ? "])).unwrap();
+        assert_eq!(settings.prefix.as_deref(), Some("?"));
+        settings
+            .set(SettingKey::BotChannel, &values(&["<#42>"]))
+            .unwrap();
+        assert_eq!(settings.get(SettingKey::BotChannel), "<#42>");
+        settings
+            .set(SettingKey::DisabledGroups, &values(&["Presence", "stats"]))
+            .unwrap();
+        assert_eq!(settings.get(SettingKey::DisabledGroups), "presence, stats");
+
+        settings.reset(SettingKey::Prefix);
+        assert_eq!(settings.prefix, None);
+        settings.set(SettingKey::DisabledGroups, &[]).unwrap();
+        assert_eq!(settings.get(SettingKey::DisabledGroups), "none");
+        assert_eq!(settings.bot_channel, Some(ChannelId(42)));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let mut settings = GuildSettings::default();
+        assert!(matches!(
+            settings.set(SettingKey::Prefix, &[]),
+            Err(SettingError::MissingValue("prefix"))
+        ));
+        assert!(matches!(
+            settings.set(SettingKey::Prefix, &values(&["a b"])),
+            Err(SettingError::InvalidPrefix)
+        ));
+        assert!(matches!(
+            settings.set(SettingKey::BotChannel, &values(&["general"])),
+            Err(SettingError::InvalidChannel(_))
+        ));
+        assert!(matches!(
+            settings.set(SettingKey::Language, &values(&["de"])),
+            Err(SettingError::InvalidLanguage(_))
+        ));
+        assert!(matches!(
+            settings.set(SettingKey::DisabledGroups, &values(&["settings"])),
+            Err(SettingError::SettingsGroup)
+        ));
+        assert!(matches!(
+            settings.set(SettingKey::DisabledGroups, &values(&["nope"])),
+            Err(SettingError::UnknownGroup(_))
+        ));
+        assert!(settings.prefix.is_none() && settings.disabled_groups.is_empty());
+    }
+
+    #[test]
+    fn replies_follow_the_language() {
+        let mut settings = GuildSettings::default();
+        settings
+            .set(SettingKey::Language, &values(&["fr"]))
+            .unwrap();
+        assert_eq!(settings.language, Language::Fr);
+        assert_eq!(settings.get(SettingKey::Prefix), "par défaut");
+        assert_eq!(settings.get(SettingKey::DisabledGroups), "aucun");
+        let error = SettingError::InvalidPrefix;
+        assert_eq!(error.localized(Language::En), error.to_string());
+        assert_ne!(error.localized(Language::Fr), error.to_string());
+    }
+}
@@ -0,0 +1,378 @@
+use serenity::{
+    builder::CreateApplicationCommands,
+    client::Context,
+    framework::standard::{CommandError, CommandGroup, OnlyIn},
+    model::interactions::{
+        application_command::{
+            ApplicationCommand, ApplicationCommandInteraction,
+            ApplicationCommandInteractionDataOption, ApplicationCommandOptionType,
+        },
+        autocomplete::AutocompleteInteraction,
+        Interaction, InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+    },
+};
+use std::time::Instant;
+use tracing::{debug, field, info, info_span, warn};
+
+#[cfg(feature = "metrics")]
+use crate::metrics;
+use crate::{config::Config, db, presence, settings, stats, stats::CommandCounter};
+
+/// Discord refuses more autocomplete choices than this.
+const MAX_CHOICES: usize = 25;
+
+const STATUSES: [&str; 4] = ["all", "active", "idle", "dnd"];
+
+/// The enabled group providing `name`, `help` not belonging to any.
+fn group_of(groups: &[&'static CommandGroup], name: &str) -> Option<&'static CommandGroup> {
+    groups
+        .iter()
+        .copied()
+        .find(|group| stats::command_names(group).contains(&name))
+}
+
+/// Defines the slash variants of the commands of the enabled `groups`.
+fn define<'a>(
+    commands: &'a mut CreateApplicationCommands,
+    groups: &[&'static CommandGroup],
+) -> &'a mut CreateApplicationCommands {
+    if group_of(groups, "add").is_some() {
+        commands.create_application_command(|command| {
+            command
+                .name("add")
+                .description("Add numbers and dice rolls, such as `2d6+3`.")
+                .create_option(|option| {
+                    option
+                        .name("expression")
+                        .description("Comma separated expressions to add.")
+                        .kind(ApplicationCommandOptionType::String)
+                        .required(true)
+                })
+        });
+    }
+    if group_of(groups, "whosonline").is_some() {
+        commands.create_application_command(|command| {
+            command
+                .name("whosonline")
+                .description("List the users currently online, grouped by status.")
+                .create_option(|option| {
+                    option
+                        .name("status")
+                        .description("Only list the users with this status.")
+                        .kind(ApplicationCommandOptionType::String)
+                        .set_autocomplete(true)
+                })
+        });
+    }
+    commands.create_application_command(|command| {
+        command
+            .name("help")
+            .description("List the available commands.")
+            .create_option(|option| {
+                option
+                    .name("command")
+                    .description("Only describe this command.")
+                    .kind(ApplicationCommandOptionType::String)
+                    .set_autocomplete(true)
+            })
+    })
+}
+
+/// Replaces the global application commands with the ones of this version.
+pub async fn register(ctx: &Context) -> serenity::Result<()> {
+    let groups = enabled_groups(ctx).await;
+    ApplicationCommand::set_global_application_commands(&ctx.http, |commands| {
+        define(commands, &groups)
+    })
+    .await?;
+    Ok(())
+}
+
+pub async fn handle(ctx: &Context, interaction: Interaction) {
+    match interaction {
+        Interaction::ApplicationCommand(command) => run(ctx, command).await,
+        Interaction::Autocomplete(autocomplete) => complete(ctx, autocomplete).await,
+        _ => {}
+    }
+}
+
+fn string_option<'a>(
+    options: &'a [ApplicationCommandInteractionDataOption],
+    name: &str,
+) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|option| option.name == name)?
+        .value
+        .as_ref()?
+        .as_str()
+}
+
+async fn enabled_groups(ctx: &Context) -> Vec<&'static CommandGroup> {
+    let data = ctx.data.read().await;
+    let config = data.get::<Config>().expect("Expected Config in TypeMap.");
+    crate::enabled_groups(config)
+}
+
+/// Same content as the prefix help, built from the command groups.
+async fn help_reply(ctx: &Context, command_name: Option<&str>) -> String {
+    let mut reply = String::new();
+    for group in enabled_groups(ctx).await {
+        let mut lines = String::new();
+        for command in group.options.commands {
+            let name = command.options.names[0];
+            if command_name.is_some_and(|wanted| wanted != name) {
+                continue;
+            }
+            lines += &format!(
+                "`{}` — {}\n",
+                name,
+                command.options.desc.unwrap_or("no description")
+            );
+        }
+        if !lines.is_empty() {
+            reply += &format!("**{}**\n{}", group.name, lines);
+        }
+    }
+    if reply.is_empty() {
+        reply = format!("Could not find: `{}`.", command_name.unwrap_or_default());
+    }
+    reply
+}
+
+/// Why a slash command may not run, applying the same restrictions as the
+/// framework and the `before` hook apply to prefix commands.
+async fn refusal(ctx: &Context, command: &ApplicationCommandInteraction) -> Option<&'static str> {
+    let name = command.data.name.as_str();
+    let groups = enabled_groups(ctx).await;
+    let group = group_of(&groups, name);
+    if group.is_none() && name != "help" {
+        return Some("This command is disabled.");
+    }
+    {
+        let data = ctx.data.read().await;
+        let config = data.get::<Config>().expect("Expected Config in TypeMap.");
+        if !config.allowed_channels.is_empty()
+            && !config.allowed_channels.contains(&command.channel_id)
+        {
+            return Some("Commands are not accepted in this channel.");
+        }
+    }
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id,
+        None if group.is_some_and(|group| group.options.only_in == OnlyIn::Guild) => {
+            return Some("This command can only be used in a server.");
+        }
+        None => return None,
+    };
+    let db = db::from_context(ctx).await;
+    match settings::load(&db, guild_id) {
+        Ok(settings) if !settings.allows_command(command.channel_id, name) => {
+            Some("This command is disabled here.")
+        }
+        Ok(_) => None,
+        Err(why) => {
+            warn!("Could not read the guild settings: {}", why);
+            None
+        }
+    }
+}
+
+/// Runs the shared implementation of a slash command.
+async fn reply(
+    ctx: &Context,
+    command: &ApplicationCommandInteraction,
+) -> Result<String, CommandError> {
+    let name = command.data.name.as_str();
+    let options = &command.data.options;
+    Ok(match name {
+        "add" => {
+            let expression = string_option(options, "expression").unwrap_or_default();
+            crate::add_reply(&expression.split(',').collect::<Vec<_>>())
+        }
+        "whosonline" => {
+            let guild_id = command.guild_id.ok_or("Must be used in guild")?;
+            match presence::parse_filter(string_option(options, "status")) {
+                Ok(filter) => presence::whosonline_reply(ctx, guild_id, filter).await,
+                Err(why) => why.to_string(),
+            }
+        }
+        "help" => help_reply(ctx, string_option(options, "command")).await,
+        _ => return Err(format!("unknown application command `{}`", name).into()),
+    })
+}
+
+async fn run(ctx: &Context, command: ApplicationCommandInteraction) {
+    let name = command.data.name.clone();
+    let span = info_span!(
+        "slash_command",
+        command = %name,
+        user_id = %command.user.id,
+        guild_id = ?command.guild_id,
+        channel_id = %command.channel_id,
+        duration_ms = field::Empty,
+    );
+    if let Some(refusal) = refusal(ctx, &command).await {
+        span.in_scope(|| debug!("Command refused: {}", refusal));
+        let result = command
+            .create_interaction_response(&ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|data| {
+                        data.content(refusal)
+                            .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                    })
+            })
+            .await;
+        if let Err(why) = result {
+            span.in_scope(|| warn!("Could not send the command reply: {}", why));
+        }
+        return;
+    }
+    let start = Instant::now();
+    {
+        let mut data = ctx.data.write().await;
+        let counter = data
+            .get_mut::<CommandCounter>()
+            .expect("Expected CommandCounter in TypeMap.");
+        *counter.entry(name.clone()).or_insert(0) += 1;
+        #[cfg(feature = "metrics")]
+        if let Some(metrics) = data.get::<metrics::Metrics>() {
+            metrics.commands.with_label_values(&[name.as_str()]).inc();
+        }
+    }
+    let db = db::from_context(ctx).await;
+    if let Err(why) = stats::record_invocation(&db, command.user.id, command.channel_id, &name) {
+        span.in_scope(|| warn!("Could not record command statistics: {}", why));
+    }
+
+    // Replies may take longer than the three seconds Discord waits for.
+    if let Err(why) = command
+        .create_interaction_response(&ctx.http, |response| {
+            response.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+        })
+        .await
+    {
+        span.in_scope(|| warn!("Could not acknowledge the command: {}", why));
+        return;
+    }
+    let result = reply(ctx, &command).await;
+    #[cfg(feature = "metrics")]
+    if let Some(metrics) = ctx.data.read().await.get::<metrics::Metrics>() {
+        metrics
+            .command_latency
+            .with_label_values(&[name.as_str()])
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            metrics
+                .command_errors
+                .with_label_values(&[name.as_str()])
+                .inc();
+        }
+    }
+    span.record("duration_ms", &(start.elapsed().as_millis() as u64));
+    let content = match result {
+        Ok(content) => {
+            span.in_scope(|| info!("Processed command"));
+            content
+        }
+        Err(why) => {
+            span.in_scope(|| warn!("Command returned error {:?}", why));
+            if let Err(why) = stats::record_error(&db, &name) {
+                span.in_scope(|| warn!("Could not record command statistics: {}", why));
+            }
+            "Something went wrong.".to_string()
+        }
+    };
+    if let Err(why) = command
+        .edit_original_interaction_response(&ctx.http, |response| response.content(content))
+        .await
+    {
+        span.in_scope(|| warn!("Could not send the command reply: {}", why));
+    }
+}
+
+async fn complete(ctx: &Context, autocomplete: AutocompleteInteraction) {
+    let focused = match autocomplete
+        .data
+        .options
+        .iter()
+        .find(|option| option.focused)
+    {
+        Some(option) => option,
+        None => return,
+    };
+    let typed = focused
+        .value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let candidates: Vec<&str> = match (autocomplete.data.name.as_str(), focused.name.as_str()) {
+        ("whosonline", "status") => STATUSES.to_vec(),
+        ("help", "command") => enabled_groups(ctx)
+            .await
+            .into_iter()
+            .flat_map(|group| group.options.commands.iter())
+            .map(|command| command.options.names[0])
+            .collect(),
+        _ => Vec::new(),
+    };
+    let result = autocomplete
+        .create_autocomplete_response(&ctx.http, |response| {
+            for candidate in candidates
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(&typed))
+                .take(MAX_CHOICES)
+            {
+                response.add_string_choice(candidate, candidate);
+            }
+            response
+        })
+        .await;
+    if let Err(why) = result {
+        warn!("Could not send autocomplete choices: {}", why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Features, presence::PRESENCE_GROUP, GENERAL_GROUP};
+
+    fn names(groups: &[&'static CommandGroup]) -> Vec<String> {
+        let mut commands = CreateApplicationCommands::default();
+        define(&mut commands, groups);
+        commands
+            .0
+            .iter()
+            .map(|command| command["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn only_enabled_groups_are_registered() {
+        let all = crate::enabled_groups(&Config::default());
+        assert_eq!(names(&all), vec!["add", "whosonline", "help"]);
+
+        let config = Config {
+            features: Features {
+                presence: false,
+                stats: true,
+            },
+            ..Config::default()
+        };
+        let groups = crate::enabled_groups(&config);
+        assert_eq!(names(&groups), vec!["add", "help"]);
+        assert!(group_of(&groups, "whosonline").is_none());
+    }
+
+    #[test]
+    fn commands_are_found_in_their_group() {
+        let groups = [&GENERAL_GROUP, &PRESENCE_GROUP];
+        assert_eq!(group_of(&groups, "add").unwrap().name, "General");
+        let presence = group_of(&groups, "whosonline").unwrap();
+        assert_eq!(presence.options.only_in, OnlyIn::Guild);
+        assert!(group_of(&groups, "help").is_none());
+    }
+}
@@ -0,0 +1,786 @@
+//! Scripted players, to simulate whole games without anyone at the table. A
+//! strategy should only use what its player knows: their own role and
+//! information, the public events, what the others claim during the day, and
+//! the other mutants once mutated.
+
+use rand::{seq::SliceRandom, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use super::{
+    Action, ActionKind, Composition, Election, Event, Game, Genome, HackTarget, Info, Observation,
+    Phase, PlayerId, Role, Side, SporzError, SporzResult, Variants,
+};
+
+/// Games still going after this many nights are given up.
+pub const MAX_NIGHTS: u32 = 50;
+/// How much a claim weighs against the other hints of being a mutant.
+const CLAIM_WEIGHT: i32 = 100;
+
+pub trait Strategy: Send {
+    /// Learns about `event`, which `me` witnessed, once it happened.
+    fn observe(&mut self, _game: &Game, _me: PlayerId, _event: &Event) {}
+
+    /// What `me` tells everyone at the start of the day: whether players are
+    /// mutants, truthfully or not.
+    fn claims(&mut self, _game: &Game, _me: PlayerId) -> Vec<Claim> {
+        Vec::new()
+    }
+
+    /// Hears `speaker` make `claims`.
+    fn hear(&mut self, _game: &Game, _me: PlayerId, _speaker: PlayerId, _claims: &[Claim]) {}
+
+    /// The actions of `me` for the night.
+    fn act(&mut self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Vec<Action>;
+
+    /// Whom `me` votes for, if anyone.
+    fn vote(
+        &mut self,
+        game: &Game,
+        me: PlayerId,
+        election: Election,
+        rng: &mut dyn RngCore,
+    ) -> Option<PlayerId>;
+}
+
+/// A statement made during the day about the side of a player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Claim {
+    pub target: PlayerId,
+    pub mutant: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    Random,
+    Suspicion,
+}
+
+impl StrategyKind {
+    pub const ALL: [StrategyKind; 2] = [StrategyKind::Random, StrategyKind::Suspicion];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StrategyKind::Random => "random",
+            StrategyKind::Suspicion => "suspicion",
+        }
+    }
+
+    pub fn build(self) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::Random => Box::new(Random),
+            StrategyKind::Suspicion => Box::new(Suspicion::default()),
+        }
+    }
+}
+
+impl fmt::Display for StrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for StrategyKind {
+    type Err = SporzError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        StrategyKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| kind.name() == s)
+            .ok_or(SporzError::UnknownStrategy(s))
+    }
+}
+
+/// The strategy of the players of each side, as dealt.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strategies {
+    pub astronauts: StrategyKind,
+    pub mutants: StrategyKind,
+}
+
+impl Default for Strategies {
+    fn default() -> Self {
+        Strategies {
+            astronauts: StrategyKind::Suspicion,
+            mutants: StrategyKind::Suspicion,
+        }
+    }
+}
+
+fn alive_others(game: &Game, me: PlayerId) -> Vec<PlayerId> {
+    game.alive()
+        .map(|player| player.id)
+        .filter(|id| *id != me)
+        .collect()
+}
+
+/// The action of `kind` on `target`, hacking one of the roles the variants
+/// allow.
+fn action_on(game: &Game, kind: ActionKind, target: PlayerId) -> Option<Action> {
+    match kind {
+        ActionKind::Hack => {
+            let hack_targets = &game.variants.hack_targets;
+            let role = if hack_targets.contains(&HackTarget::Psychologist) {
+                HackTarget::Psychologist
+            } else {
+                *hack_targets.first()?
+            };
+            Some(Action::Hack { role, target })
+        }
+        _ => Some(Action::new(kind, target)),
+    }
+}
+
+/// Picks any action and target allowed.
+#[derive(Debug, Default)]
+pub struct Random;
+
+impl Strategy for Random {
+    fn act(&mut self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Vec<Action> {
+        let mut kinds = game.available_actions(me);
+        kinds.shuffle(rng);
+        let mut actions: Vec<Action> = Vec::new();
+        for kind in kinds {
+            if actions.iter().any(|action| kind.replaces(action.kind())) {
+                continue;
+            }
+            let mut targets = alive_others(game, me);
+            if kind == ActionKind::Heal {
+                targets.push(me);
+            }
+            if let Some(action) = targets
+                .choose(rng)
+                .and_then(|target| action_on(game, kind, *target))
+            {
+                actions.push(action);
+            }
+        }
+        actions
+    }
+
+    fn vote(
+        &mut self,
+        game: &Game,
+        me: PlayerId,
+        _election: Election,
+        rng: &mut dyn RngCore,
+    ) -> Option<PlayerId> {
+        alive_others(game, me).choose(rng).copied()
+    }
+}
+
+/// Keeps track of who is likely a mutant: the astronauts tell what they
+/// learnt, then heal, inspect and eliminate the suspects, the mutants kill
+/// whoever resisted them.
+#[derive(Debug, Default)]
+pub struct Suspicion {
+    /// Whether players were mutants when last learnt.
+    mutants: BTreeMap<PlayerId, bool>,
+    /// What the others last claimed of each player.
+    claimed: BTreeMap<PlayerId, bool>,
+    genomes: BTreeMap<PlayerId, Genome>,
+    /// Hints of being a mutant, positive or negative.
+    hints: BTreeMap<PlayerId, i32>,
+    /// The last mutation of the mutants, to check whether it worked.
+    mutation: Option<PlayerId>,
+    /// Players the mutants could not mutate.
+    immune: Vec<PlayerId>,
+}
+
+impl Suspicion {
+    fn suspicion(&self, id: PlayerId) -> i32 {
+        let hints = self.hints.get(&id).copied().unwrap_or(0);
+        match (self.mutants.get(&id), self.claimed.get(&id)) {
+            (Some(true), _) => i32::MAX,
+            (Some(false), _) => i32::MIN,
+            (None, Some(true)) => CLAIM_WEIGHT + hints,
+            (None, Some(false)) => hints - CLAIM_WEIGHT,
+            (None, None) => hints,
+        }
+    }
+
+    /// `ids` from the most to the least suspected, in random order among
+    /// equals.
+    fn ranked(&self, mut ids: Vec<PlayerId>, rng: &mut dyn RngCore) -> Vec<PlayerId> {
+        ids.shuffle(rng);
+        ids.sort_by_key(|id| -(self.suspicion(*id) as i64));
+        ids
+    }
+
+    /// The most suspected player among those whose side is unknown.
+    fn unknown(&self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Option<PlayerId> {
+        let ids = alive_others(game, me)
+            .into_iter()
+            .filter(|id| !self.mutants.contains_key(id))
+            .collect();
+        self.ranked(ids, rng).first().copied()
+    }
+
+    fn mutant_actions(&self, game: &Game, rng: &mut dyn RngCore) -> Vec<Action> {
+        let prey: Vec<PlayerId> = game
+            .alive()
+            .filter(|player| !player.mutant)
+            .map(|player| player.id)
+            .collect();
+        let mut actions = Vec::new();
+        match prey.iter().find(|id| self.immune.contains(id)) {
+            Some(target) => actions.push(Action::MutantKill { target: *target }),
+            None => {
+                if let Some(target) = prey.choose(rng) {
+                    actions.push(Action::Mutate { target: *target });
+                }
+            }
+        }
+        if let Some(target) = prey.choose(rng) {
+            actions.push(Action::Paralyse { target: *target });
+        }
+        actions
+    }
+
+    /// The most suspected player not known to be healthy.
+    fn suspect(&self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Option<PlayerId> {
+        let ids = alive_others(game, me)
+            .into_iter()
+            .filter(|id| self.mutants.get(id) != Some(&false))
+            .collect();
+        self.ranked(ids, rng).first().copied()
+    }
+
+    fn doctor_action(&self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Option<Action> {
+        let suspects = self.ranked(alive_others(game, me), rng);
+        let target = *suspects.first()?;
+        if self.suspicion(target) > 0 {
+            // Hosts cannot be healed, only killed.
+            return Some(if self.genomes.get(&target) == Some(&Genome::Host) {
+                Action::DoctorKill { target }
+            } else {
+                Action::Heal { target }
+            });
+        }
+        // Without suspects, healing anyone not known to be healthy may help.
+        let unknown: Vec<PlayerId> = suspects
+            .into_iter()
+            .filter(|id| !self.mutants.contains_key(id))
+            .collect();
+        unknown
+            .choose(rng)
+            .map(|target| Action::Heal { target: *target })
+    }
+}
+
+impl Strategy for Suspicion {
+    fn observe(&mut self, game: &Game, _me: PlayerId, event: &Event) {
+        match event {
+            Event::Informed { info, .. } => match info {
+                Info::IsMutant { target, mutant } => {
+                    self.mutants.insert(*target, *mutant);
+                }
+                Info::Genome { target, genome } => {
+                    self.genomes.insert(*target, *genome);
+                }
+                Info::Spied {
+                    target,
+                    observations,
+                } => {
+                    for observation in observations {
+                        match observation {
+                            Observation::Mutated => {
+                                self.mutants.insert(*target, true);
+                            }
+                            Observation::Healed => {
+                                self.mutants.insert(*target, false);
+                            }
+                            // The mutants do not target each other.
+                            Observation::Targeted(kind) if kind.is_collective() => {
+                                *self.hints.entry(*target).or_default() -= 1
+                            }
+                            // Neither do the doctors heal the healthy.
+                            Observation::Targeted(ActionKind::Heal) => {
+                                *self.hints.entry(*target).or_default() += 1
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            },
+            Event::ActionSubmitted {
+                action: Action::Mutate { target },
+                ..
+            } => self.mutation = Some(*target),
+            Event::PhaseStarted {
+                phase: Phase::Day(_),
+            } => {
+                if let Some(target) = self.mutation.take() {
+                    if game
+                        .player(target)
+                        .is_ok_and(|player| player.alive && !player.mutant)
+                    {
+                        self.immune.push(target);
+                    }
+                }
+            }
+            Event::Healed { player } => {
+                self.mutants.insert(*player, false);
+            }
+            _ => {}
+        }
+    }
+
+    fn claims(&mut self, game: &Game, me: PlayerId) -> Vec<Claim> {
+        if game.player(me).map_or(true, |player| player.mutant) {
+            return Vec::new();
+        }
+        self.mutants
+            .iter()
+            .filter(|(id, _)| game.alive_player(**id).is_ok())
+            .map(|(id, mutant)| Claim {
+                target: *id,
+                mutant: *mutant,
+            })
+            .collect()
+    }
+
+    fn hear(&mut self, _game: &Game, _me: PlayerId, speaker: PlayerId, claims: &[Claim]) {
+        if self.mutants.get(&speaker) == Some(&true) {
+            return;
+        }
+        for claim in claims {
+            self.claimed.insert(claim.target, claim.mutant);
+        }
+    }
+
+    fn act(&mut self, game: &Game, me: PlayerId, rng: &mut dyn RngCore) -> Vec<Action> {
+        let kinds = game.available_actions(me);
+        let mut actions = Vec::new();
+        if kinds.contains(&ActionKind::Mutate) {
+            actions.extend(self.mutant_actions(game, rng));
+        }
+        for kind in kinds {
+            let chosen = match kind {
+                ActionKind::Heal => self.doctor_action(game, me, rng),
+                ActionKind::Inspect | ActionKind::Hack | ActionKind::Spy => self
+                    .unknown(game, me, rng)
+                    .and_then(|target| action_on(game, kind, target)),
+                ActionKind::Sequence => {
+                    let ids = alive_others(game, me)
+                        .into_iter()
+                        .filter(|id| !self.genomes.contains_key(id))
+                        .collect();
+                    self.ranked(ids, rng)
+                        .first()
+                        .map(|target| Action::Sequence { target: *target })
+                }
+                ActionKind::Paint => alive_others(game, me)
+                    .choose(rng)
+                    .map(|target| Action::Paint { target: *target }),
+                _ => None,
+            };
+            actions.extend(chosen);
+        }
+        actions
+    }
+
+    fn vote(
+        &mut self,
+        game: &Game,
+        me: PlayerId,
+        election: Election,
+        rng: &mut dyn RngCore,
+    ) -> Option<PlayerId> {
+        let player = game.player(me).ok()?;
+        let others = alive_others(game, me);
+        match (player.mutant, election) {
+            (true, Election::Captain) => game
+                .alive()
+                .filter(|player| player.mutant)
+                .map(|player| player.id)
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .copied(),
+            (true, Election::Elimination) => others
+                .into_iter()
+                .filter(|id| game.player(*id).is_ok_and(|player| !player.mutant))
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .copied(),
+            // Whoever is trusted the most, or themselves.
+            (false, Election::Captain) => self
+                .ranked(others, rng)
+                .last()
+                .copied()
+                .filter(|id| self.suspicion(*id) < 0)
+                .or(Some(me)),
+            (false, Election::Elimination) => self.suspect(game, me, rng),
+        }
+    }
+}
+
+/// A player played by a strategy.
+pub struct Bot {
+    pub player: PlayerId,
+    pub strategy: Box<dyn Strategy>,
+}
+
+impl Bot {
+    pub fn new(player: PlayerId, kind: StrategyKind) -> Self {
+        Bot {
+            player,
+            strategy: kind.build(),
+        }
+    }
+}
+
+/// Whether `me` learns about `event` when it happens.
+fn witnesses(game: &Game, me: PlayerId, event: &Event) -> bool {
+    match event {
+        _ if event.is_public() => true,
+        Event::Assigned { player, .. }
+        | Event::Paralysed { player }
+        | Event::Mutated { player }
+        | Event::Healed { player }
+        | Event::Informed { player, .. } => *player == me,
+        Event::ActionSubmitted { player, action } => {
+            *player == me
+                || (action.kind().is_collective()
+                    && game.player(me).is_ok_and(|player| player.mutant))
+        }
+        _ => false,
+    }
+}
+
+/// Tells each bot the events it witnessed.
+pub fn tell(game: &Game, bots: &mut [Bot], events: &[Event]) {
+    for bot in bots.iter_mut() {
+        for event in events {
+            if witnesses(game, bot.player, event) {
+                bot.strategy.observe(game, bot.player, event);
+            }
+        }
+    }
+}
+
+/// Submits the actions of the bots, then resolves the night.
+pub fn play_night(
+    game: &mut Game,
+    bots: &mut [Bot],
+    rng: &mut dyn RngCore,
+) -> SporzResult<Vec<Event>> {
+    let mut events = Vec::new();
+    for bot in bots.iter_mut() {
+        for action in bot.strategy.act(game, bot.player, rng) {
+            events.extend(game.submit(bot.player, action)?);
+        }
+    }
+    events.extend(game.resolve_night()?);
+    tell(game, bots, &events);
+    Ok(events)
+}
+
+/// The votes of the living bots in `election`.
+fn poll(
+    game: &Game,
+    bots: &mut [Bot],
+    election: Election,
+    rng: &mut dyn RngCore,
+) -> Vec<(PlayerId, PlayerId)> {
+    let mut votes = Vec::new();
+    for bot in bots.iter_mut() {
+        if game.alive_player(bot.player).is_err() {
+            continue;
+        }
+        if let Some(target) = bot.strategy.vote(game, bot.player, election, rng) {
+            if game.alive_player(target).is_ok() {
+                votes.push((bot.player, target));
+            }
+        }
+    }
+    votes
+}
+
+/// The player with the most votes, the captain's counting double. Ties go
+/// to the choice of the captain, or to nobody.
+fn elect(game: &Game, votes: &[(PlayerId, PlayerId)]) -> Option<PlayerId> {
+    let mut tally: BTreeMap<PlayerId, u32> = BTreeMap::new();
+    for (voter, target) in votes {
+        *tally.entry(*target).or_default() += if game.captain == Some(*voter) { 2 } else { 1 };
+    }
+    let best = tally.values().copied().max()?;
+    let leaders: Vec<PlayerId> = tally
+        .into_iter()
+        .filter(|(_, count)| *count == best)
+        .map(|(target, _)| target)
+        .collect();
+    match leaders.as_slice() {
+        [winner] => Some(*winner),
+        _ => votes
+            .iter()
+            .find(|(voter, _)| game.captain == Some(*voter))
+            .map(|(_, target)| *target)
+            .filter(|target| leaders.contains(target)),
+    }
+}
+
+/// Elects a captain if there is none, then eliminates a player, or nobody.
+pub fn play_day(
+    game: &mut Game,
+    bots: &mut [Bot],
+    rng: &mut dyn RngCore,
+) -> SporzResult<Vec<Event>> {
+    let speakers: Vec<(PlayerId, Vec<Claim>)> = bots
+        .iter_mut()
+        .filter(|bot| game.alive_player(bot.player).is_ok())
+        .map(|bot| (bot.player, bot.strategy.claims(game, bot.player)))
+        .collect();
+    for bot in bots.iter_mut() {
+        for (speaker, claims) in &speakers {
+            if *speaker != bot.player && !claims.is_empty() {
+                bot.strategy.hear(game, bot.player, *speaker, claims);
+            }
+        }
+    }
+    let mut events = Vec::new();
+    if game.captain.is_none() {
+        let votes = poll(game, bots, Election::Captain, rng);
+        if let Some(captain) = elect(game, &votes) {
+            events.extend(game.elect_captain(captain)?);
+        }
+    }
+    let votes = poll(game, bots, Election::Elimination, rng);
+    let target = elect(game, &votes);
+    events.extend(game.eliminate(target)?);
+    tell(game, bots, &events);
+    Ok(events)
+}
+
+/// How a simulated game went.
+#[derive(Clone, Debug)]
+pub struct Simulation {
+    /// The game as it ended.
+    pub game: Game,
+    /// Unset if the game was given up after `MAX_NIGHTS` nights.
+    pub winner: Option<Side>,
+    pub nights: u32,
+    pub events: Vec<Event>,
+}
+
+/// Plays a whole game between `players` bots, each playing the strategy of
+/// the side they are dealt.
+pub fn simulate(
+    players: usize,
+    composition: &Composition,
+    variants: &Variants,
+    strategies: Strategies,
+    mut rng: &mut dyn RngCore,
+) -> SporzResult<Simulation> {
+    let ids: Vec<PlayerId> = (1..=players as u64).map(PlayerId).collect();
+    let (mut game, mut events) = Game::deal(&ids, composition, variants, &mut rng)?;
+    let mut bots: Vec<Bot> = game
+        .players
+        .iter()
+        .map(|player| {
+            let kind = match game.side(player) {
+                Side::Astronauts => strategies.astronauts,
+                Side::Mutants => strategies.mutants,
+            };
+            Bot::new(player.id, kind)
+        })
+        .collect();
+    tell(&game, &mut bots, &events);
+    loop {
+        let more = match game.phase {
+            Phase::Night(night) if night > MAX_NIGHTS => break,
+            Phase::Night(_) => play_night(&mut game, &mut bots, rng)?,
+            Phase::Day(_) => play_day(&mut game, &mut bots, rng)?,
+            Phase::Ended(_) => break,
+        };
+        events.extend(more);
+    }
+    let (winner, nights) = match game.phase {
+        Phase::Ended(winner) => (Some(winner), night_count(&events)),
+        _ => (None, MAX_NIGHTS),
+    };
+    Ok(Simulation {
+        game,
+        winner,
+        nights,
+        events,
+    })
+}
+
+fn night_count(events: &[Event]) -> u32 {
+    events
+        .iter()
+        .filter(|event| {
+            matches!(
+                event,
+                Event::PhaseStarted {
+                    phase: Phase::Night(_)
+                }
+            )
+        })
+        .count() as u32
+}
+
+/// The results of many simulated games.
+#[derive(Clone, Debug, Default)]
+pub struct Statistics {
+    pub games: usize,
+    pub astronauts: usize,
+    pub mutants: usize,
+    /// Nights played in all the games.
+    pub nights: u32,
+    /// How the players of each role fared.
+    pub roles: BTreeMap<Role, RoleStatistics>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RoleStatistics {
+    /// Players dealt the role, in all the games.
+    pub players: usize,
+    /// Those on the winning side at the end.
+    pub winners: usize,
+    pub survivors: usize,
+    /// Those mutated at the end, base mutants aside.
+    pub mutated: usize,
+}
+
+impl RoleStatistics {
+    /// The share of the players with a property, in percent.
+    pub fn rate(&self, count: usize) -> f64 {
+        if self.players == 0 {
+            return 0.0;
+        }
+        count as f64 * 100.0 / self.players as f64
+    }
+}
+
+impl Statistics {
+    pub fn add(&mut self, simulation: &Simulation) {
+        self.games += 1;
+        self.nights += simulation.nights;
+        match simulation.winner {
+            Some(Side::Astronauts) => self.astronauts += 1,
+            Some(Side::Mutants) => self.mutants += 1,
+            None => {}
+        }
+        let game = &simulation.game;
+        for player in &game.players {
+            let role = self.roles.entry(player.role).or_default();
+            role.players += 1;
+            if simulation.winner == Some(game.side(player)) {
+                role.winners += 1;
+            }
+            if player.alive {
+                role.survivors += 1;
+            }
+            if player.mutant && player.role != Role::BaseMutant {
+                role.mutated += 1;
+            }
+        }
+    }
+
+    pub fn wins(&self, side: Side) -> usize {
+        match side {
+            Side::Astronauts => self.astronauts,
+            Side::Mutants => self.mutants,
+        }
+    }
+
+    /// The share of the games won by `side`, in percent.
+    pub fn win_rate(&self, side: Side) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.wins(side) as f64 * 100.0 / self.games as f64
+    }
+
+    pub fn given_up(&self) -> usize {
+        self.games - self.astronauts - self.mutants
+    }
+
+    pub fn average_nights(&self) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.nights as f64 / self.games as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn play(players: usize, strategies: Strategies, seed: u64) -> Simulation {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        simulate(
+            players,
+            &Composition::standard(players),
+            &Variants::default(),
+            strategies,
+            &mut rng,
+        )
+        .unwrap()
+    }
+
+    /// The share of `games` games of 8 players won by `side`, in percent.
+    fn win_rate(strategies: Strategies, side: Side, games: u64) -> f64 {
+        let mut statistics = Statistics::default();
+        for seed in 0..games {
+            statistics.add(&play(8, strategies, seed));
+        }
+        statistics.win_rate(side)
+    }
+
+    #[test]
+    fn games_end_with_a_winner() {
+        for astronauts in StrategyKind::ALL.iter().copied() {
+            for mutants in StrategyKind::ALL.iter().copied() {
+                let strategies = Strategies {
+                    astronauts,
+                    mutants,
+                };
+                for players in [5, 8, 11, 15] {
+                    for seed in 0..10 {
+                        let simulation = play(players, strategies, seed);
+                        let winner = simulation.winner.expect("game given up");
+                        assert_eq!(simulation.game.phase, Phase::Ended(winner));
+                        assert_eq!(simulation.game.winner(), Some(winner));
+                        assert!(simulation.nights <= MAX_NIGHTS);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn simulations_are_reproducible() {
+        let first = play(10, Strategies::default(), 7);
+        let second = play(10, Strategies::default(), 7);
+        assert_eq!(first.events, second.events);
+    }
+
+    #[test]
+    fn suspicion_is_not_beaten_by_random() {
+        let random = Strategies {
+            astronauts: StrategyKind::Random,
+            mutants: StrategyKind::Random,
+        };
+        let baseline = win_rate(random, Side::Astronauts, 300);
+        let astronauts = Strategies {
+            astronauts: StrategyKind::Suspicion,
+            ..random
+        };
+        assert!(win_rate(astronauts, Side::Astronauts, 300) > baseline);
+        let mutants = Strategies {
+            mutants: StrategyKind::Suspicion,
+            ..random
+        };
+        assert!(win_rate(mutants, Side::Astronauts, 300) < baseline);
+    }
+}
@@ -0,0 +1,448 @@
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::{
+    night::{Action, ActionKind},
+    rules::Variants,
+    Cause, Event, Genome, PlayerId, Role, Side, SporzError, SporzResult,
+};
+
+pub const MIN_PLAYERS: usize = 5;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Player {
+    pub id: PlayerId,
+    pub role: Role,
+    pub genome: Genome,
+    pub mutant: bool,
+    pub alive: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "phase", content = "value", rename_all = "snake_case")]
+pub enum Phase {
+    Night(u32),
+    Day(u32),
+    Ended(Side),
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Night(night) => write!(f, "night {}", night),
+            Phase::Day(day) => write!(f, "day {}", day),
+            Phase::Ended(winner) => write!(f, "victory of the {}", winner),
+        }
+    }
+}
+
+/// The special roles and genomes of a game, the other players being
+/// astronauts with a normal genome.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Composition {
+    pub roles: Vec<Role>,
+    /// Hosts besides the base mutants.
+    #[serde(default)]
+    pub hosts: usize,
+    #[serde(default)]
+    pub resistants: usize,
+}
+
+impl Composition {
+    /// The usual composition for `players` players, adding roles as the
+    /// table grows.
+    pub fn standard(players: usize) -> Self {
+        use Role::*;
+        let mut roles = vec![BaseMutant, Doctor, Doctor, Psychologist];
+        if players >= 7 {
+            roles.extend([Geneticist, ComputerScientist].iter());
+        }
+        if players >= 9 {
+            roles.extend([Hacker, Spy].iter());
+        }
+        if players >= 11 {
+            roles.extend([Painter, Traitor].iter());
+        }
+        Composition {
+            roles,
+            hosts: 1,
+            resistants: if players >= 7 { 1 } else { 0 },
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Game {
+    pub players: Vec<Player>,
+    pub phase: Phase,
+    pub captain: Option<PlayerId>,
+    /// Actions submitted during the current night.
+    pub actions: Vec<(PlayerId, Action)>,
+    #[serde(default)]
+    pub variants: Variants,
+}
+
+impl Game {
+    /// Deals the roles and genomes of `composition` to `players` at random,
+    /// then starts the first night.
+    pub fn deal<R: Rng>(
+        players: &[PlayerId],
+        composition: &Composition,
+        variants: &Variants,
+        rng: &mut R,
+    ) -> SporzResult<(Game, Vec<Event>)> {
+        composition.validate(players.len(), variants)?;
+        let base_mutants = composition
+            .roles
+            .iter()
+            .filter(|role| **role == Role::BaseMutant)
+            .count();
+        let mut roles = composition.roles.clone();
+        roles.resize(players.len(), Role::Astronaut);
+        roles.shuffle(rng);
+
+        let mut genomes: Vec<Genome> = Vec::new();
+        genomes.resize(composition.hosts, Genome::Host);
+        genomes.resize(
+            composition.hosts + composition.resistants,
+            Genome::Resistant,
+        );
+        genomes.resize(players.len() - base_mutants, Genome::Normal);
+        genomes.shuffle(rng);
+        let mut genomes = genomes.into_iter();
+
+        let assignments = players
+            .iter()
+            .zip(roles)
+            .map(|(id, role)| {
+                let genome = match role {
+                    Role::BaseMutant => Genome::Host,
+                    _ => genomes.next().unwrap_or(Genome::Normal),
+                };
+                (*id, role, genome)
+            })
+            .collect::<Vec<_>>();
+        Game::new(&assignments, variants.clone())
+    }
+
+    /// Starts the first night with the given roles and genomes.
+    pub fn new(
+        assignments: &[(PlayerId, Role, Genome)],
+        variants: Variants,
+    ) -> SporzResult<(Game, Vec<Event>)> {
+        let mut players: Vec<Player> = Vec::new();
+        let mut events = Vec::new();
+        for (id, role, genome) in assignments {
+            if players.iter().any(|player| player.id == *id) {
+                return Err(SporzError::DuplicatePlayer(*id));
+            }
+            players.push(Player {
+                id: *id,
+                role: *role,
+                genome: *genome,
+                mutant: *role == Role::BaseMutant,
+                alive: true,
+            });
+            events.push(Event::Assigned {
+                player: *id,
+                role: *role,
+                genome: *genome,
+            });
+        }
+        let game = Game {
+            players,
+            phase: Phase::Night(1),
+            captain: None,
+            actions: Vec::new(),
+            variants,
+        };
+        events.push(Event::PhaseStarted { phase: game.phase });
+        Ok((game, events))
+    }
+
+    pub fn player(&self, id: PlayerId) -> SporzResult<&Player> {
+        self.players
+            .iter()
+            .find(|player| player.id == id)
+            .ok_or(SporzError::UnknownPlayer(id))
+    }
+
+    pub(super) fn player_mut(&mut self, id: PlayerId) -> SporzResult<&mut Player> {
+        self.players
+            .iter_mut()
+            .find(|player| player.id == id)
+            .ok_or(SporzError::UnknownPlayer(id))
+    }
+
+    pub(super) fn alive_player(&self, id: PlayerId) -> SporzResult<&Player> {
+        let player = self.player(id)?;
+        if !player.alive {
+            return Err(SporzError::Dead(id));
+        }
+        Ok(player)
+    }
+
+    pub fn alive(&self) -> impl Iterator<Item = &Player> {
+        self.players.iter().filter(|player| player.alive)
+    }
+
+    /// The side `player` wins with.
+    pub fn side(&self, player: &Player) -> Side {
+        if player.mutant {
+            Side::Mutants
+        } else if player.role == Role::Traitor {
+            self.variants.traitor_side
+        } else {
+            player.role.side()
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        matches!(self.phase, Phase::Ended(_))
+    }
+
+    /// The astronauts win once every mutant is dead, the mutants once nobody
+    /// is left to stop them.
+    pub fn winner(&self) -> Option<Side> {
+        if !self.alive().any(|player| player.mutant) {
+            Some(Side::Astronauts)
+        } else if self
+            .alive()
+            .all(|player| self.side(player) == Side::Mutants)
+        {
+            Some(Side::Mutants)
+        } else {
+            None
+        }
+    }
+
+    /// Ends the game if a side won.
+    pub(super) fn check_winner(&mut self, events: &mut Vec<Event>) -> bool {
+        match self.winner() {
+            Some(winner) => {
+                self.phase = Phase::Ended(winner);
+                self.actions.clear();
+                events.push(Event::Ended { winner });
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(super) fn kill(&mut self, id: PlayerId, cause: Cause, events: &mut Vec<Event>) {
+        if let Ok(player) = self.player_mut(id) {
+            if player.alive {
+                player.alive = false;
+                events.push(Event::Killed { player: id, cause });
+            }
+        }
+        if self.captain == Some(id) {
+            self.captain = None;
+        }
+    }
+
+    fn day(&self) -> SporzResult<u32> {
+        match self.phase {
+            Phase::Day(day) => Ok(day),
+            Phase::Ended(_) => Err(SporzError::Ended),
+            Phase::Night(_) => Err(SporzError::WrongPhase { expected: "day" }),
+        }
+    }
+
+    /// The captain's vote counts double.
+    pub fn elect_captain(&mut self, id: PlayerId) -> SporzResult<Vec<Event>> {
+        self.day()?;
+        self.alive_player(id)?;
+        self.captain = Some(id);
+        Ok(vec![Event::CaptainElected { player: id }])
+    }
+
+    /// Closes the day with the outcome of the vote, which may spare everyone,
+    /// and starts the next night unless the game is over.
+    pub fn eliminate(&mut self, target: Option<PlayerId>) -> SporzResult<Vec<Event>> {
+        let day = self.day()?;
+        let mut events = Vec::new();
+        match target {
+            Some(target) => {
+                self.alive_player(target)?;
+                self.kill(target, Cause::Vote, &mut events);
+            }
+            None => events.push(Event::Spared),
+        }
+        if !self.check_winner(&mut events) {
+            self.phase = Phase::Night(day + 1);
+            events.push(Event::PhaseStarted { phase: self.phase });
+        }
+        Ok(events)
+    }
+
+    /// Kills `id` by decision of the game master.
+    pub fn strike(&mut self, id: PlayerId) -> SporzResult<Vec<Event>> {
+        if self.is_over() {
+            return Err(SporzError::Ended);
+        }
+        self.alive_player(id)?;
+        let mut events = Vec::new();
+        self.kill(id, Cause::GameMaster, &mut events);
+        self.check_winner(&mut events);
+        Ok(events)
+    }
+
+    /// Brings `id` back to life, by decision of the game master.
+    pub fn revive(&mut self, id: PlayerId) -> SporzResult<Vec<Event>> {
+        if self.is_over() {
+            return Err(SporzError::Ended);
+        }
+        let player = self.player_mut(id)?;
+        if player.alive {
+            return Err(SporzError::Alive(id));
+        }
+        player.alive = true;
+        Ok(vec![Event::Revived { player: id }])
+    }
+
+    /// The actions `id` may still submit this night.
+    pub fn available_actions(&self, id: PlayerId) -> Vec<ActionKind> {
+        if !matches!(self.phase, Phase::Night(_)) {
+            return Vec::new();
+        }
+        match self.alive_player(id) {
+            Ok(player) => ActionKind::ALL
+                .iter()
+                .copied()
+                .filter(|kind| kind.allowed(player))
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn ids(count: u64) -> Vec<PlayerId> {
+        (1..=count).map(PlayerId).collect()
+    }
+
+    fn game(assignments: &[(Role, Genome)]) -> Game {
+        let assignments: Vec<_> = assignments
+            .iter()
+            .zip(1..)
+            .map(|((role, genome), id)| (PlayerId(id), *role, *genome))
+            .collect();
+        Game::new(&assignments, Variants::default()).unwrap().0
+    }
+
+    #[test]
+    fn deal_follows_the_composition() {
+        let composition = Composition::standard(9);
+        for seed in 0..20 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let (game, events) =
+                Game::deal(&ids(9), &composition, &Variants::default(), &mut rng).unwrap();
+            assert_eq!(game.players.len(), 9);
+            assert_eq!(game.phase, Phase::Night(1));
+            for role in Role::ALL.iter().copied().filter(|r| *r != Role::Astronaut) {
+                let expected = composition.roles.iter().filter(|r| **r == role).count();
+                let dealt = game.players.iter().filter(|p| p.role == role).count();
+                assert_eq!(dealt, expected, "{:?}", role);
+            }
+            let genomes = |genome| game.players.iter().filter(|p| p.genome == genome).count();
+            assert_eq!(genomes(Genome::Host), 1 + composition.hosts);
+            assert_eq!(genomes(Genome::Resistant), composition.resistants);
+            for player in &game.players {
+                assert_eq!(player.mutant, player.role == Role::BaseMutant);
+                if player.mutant {
+                    assert_eq!(player.genome, Genome::Host);
+                }
+            }
+            let assigned = events
+                .iter()
+                .filter(|event| matches!(event, Event::Assigned { .. }))
+                .count();
+            assert_eq!(assigned, 9);
+        }
+    }
+
+    #[test]
+    fn deal_rejects_invalid_tables() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let variants = Variants::default();
+        assert_eq!(
+            Game::deal(&ids(4), &Composition::standard(4), &variants, &mut rng).unwrap_err(),
+            SporzError::NotEnoughPlayers
+        );
+        let mut players = ids(5);
+        players[4] = PlayerId(1);
+        assert_eq!(
+            Game::deal(&players, &Composition::standard(5), &variants, &mut rng).unwrap_err(),
+            SporzError::DuplicatePlayer(PlayerId(1))
+        );
+    }
+
+    #[test]
+    fn astronauts_win_once_every_mutant_is_dead() {
+        let mut game = game(&[
+            (Role::BaseMutant, Genome::Host),
+            (Role::Doctor, Genome::Normal),
+            (Role::Astronaut, Genome::Normal),
+        ]);
+        assert_eq!(game.winner(), None);
+        game.resolve_night().unwrap();
+        let events = game.eliminate(Some(PlayerId(1))).unwrap();
+        assert_eq!(game.phase, Phase::Ended(Side::Astronauts));
+        assert!(events.contains(&Event::Ended {
+            winner: Side::Astronauts
+        }));
+    }
+
+    #[test]
+    fn mutants_win_once_alone() {
+        let mut game = game(&[
+            (Role::BaseMutant, Genome::Host),
+            (Role::Traitor, Genome::Normal),
+            (Role::Doctor, Genome::Normal),
+            (Role::Astronaut, Genome::Normal),
+        ]);
+        game.players[3].mutant = true;
+        assert_eq!(game.winner(), None);
+        game.resolve_night().unwrap();
+        game.eliminate(Some(PlayerId(3))).unwrap();
+        assert_eq!(game.phase, Phase::Ended(Side::Mutants));
+    }
+
+    #[test]
+    fn traitor_may_side_with_the_astronauts() {
+        let mut game = game(&[
+            (Role::BaseMutant, Genome::Host),
+            (Role::Traitor, Genome::Normal),
+        ]);
+        assert_eq!(game.winner(), Some(Side::Mutants));
+        game.variants.traitor_side = Side::Astronauts;
+        assert_eq!(game.winner(), None);
+    }
+
+    #[test]
+    fn vote_closes_the_day() {
+        let mut game = game(&[
+            (Role::BaseMutant, Genome::Host),
+            (Role::Doctor, Genome::Normal),
+            (Role::Astronaut, Genome::Normal),
+            (Role::Astronaut, Genome::Normal),
+        ]);
+        assert_eq!(
+            game.eliminate(None).unwrap_err(),
+            SporzError::WrongPhase { expected: "day" }
+        );
+        game.resolve_night().unwrap();
+        assert_eq!(game.eliminate(None).unwrap()[0], Event::Spared);
+        assert_eq!(game.phase, Phase::Night(2));
+        game.resolve_night().unwrap();
+        game.eliminate(Some(PlayerId(1))).unwrap();
+        assert_eq!(game.phase, Phase::Ended(Side::Astronauts));
+    }
+}
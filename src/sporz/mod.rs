@@ -0,0 +1,151 @@
+//! The Sporz game engine: players, roles and genomes, the night and day
+//! phases and the win conditions. It knows nothing of Discord, players are
+//! only identified by a number.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use thiserror::Error;
+
+pub mod bot;
+mod game;
+mod night;
+mod role;
+mod rules;
+
+pub use game::{Composition, Game, Phase, Player, MIN_PLAYERS};
+pub use night::{Action, ActionKind, HackTarget, Info, Observation};
+pub use role::{Genome, Role, Side};
+pub use rules::{Preset, SizedComposition, SpyVisibility, Variants};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PlayerId(pub u64);
+
+impl fmt::Display for PlayerId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "player {}", self.0)
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SporzError {
+    #[error("unknown role `{0}`")]
+    UnknownRole(String),
+    #[error("{0} is not in the game")]
+    UnknownPlayer(PlayerId),
+    #[error("{0} joined twice")]
+    DuplicatePlayer(PlayerId),
+    #[error("{0} is dead")]
+    Dead(PlayerId),
+    #[error("{0} is alive")]
+    Alive(PlayerId),
+    #[error("{players} players cannot play a composition of {roles} roles")]
+    InvalidComposition { players: usize, roles: usize },
+    #[error("at least {} players are needed", MIN_PLAYERS)]
+    NotEnoughPlayers,
+    #[error("unbalanced composition: {0}")]
+    Unbalanced(&'static str),
+    #[error("unknown strategy `{0}`")]
+    UnknownStrategy(String),
+    #[error("no composition for {players} players")]
+    NoComposition { players: usize },
+    #[error("{player} cannot {action} now")]
+    NotAllowed {
+        player: PlayerId,
+        action: ActionKind,
+    },
+    #[error("{player} cannot target themselves with {action}")]
+    SelfTarget {
+        player: PlayerId,
+        action: ActionKind,
+    },
+    #[error("this can only be done during the {expected}")]
+    WrongPhase { expected: &'static str },
+    #[error("the game is over")]
+    Ended,
+}
+
+pub type SporzResult<T> = Result<T, SporzError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Election {
+    Captain,
+    Elimination,
+}
+
+/// How a player died.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cause {
+    Mutants,
+    Doctors,
+    Vote,
+    GameMaster,
+}
+
+/// Everything that happens in a game, in order. Only some events are public,
+/// the others must only be told to the players concerned.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Assigned {
+        player: PlayerId,
+        role: Role,
+        genome: Genome,
+    },
+    PhaseStarted {
+        phase: Phase,
+    },
+    ActionSubmitted {
+        player: PlayerId,
+        action: Action,
+    },
+    Paralysed {
+        player: PlayerId,
+    },
+    Mutated {
+        player: PlayerId,
+    },
+    MutationResisted {
+        player: PlayerId,
+    },
+    Healed {
+        player: PlayerId,
+    },
+    HealFailed {
+        player: PlayerId,
+    },
+    Killed {
+        player: PlayerId,
+        cause: Cause,
+    },
+    Revived {
+        player: PlayerId,
+    },
+    Informed {
+        player: PlayerId,
+        info: Info,
+    },
+    CaptainElected {
+        player: PlayerId,
+    },
+    Spared,
+    Ended {
+        winner: Side,
+    },
+}
+
+impl Event {
+    /// Whether every player may learn about this event when it happens.
+    pub fn is_public(&self) -> bool {
+        matches!(
+            self,
+            Event::PhaseStarted { .. }
+                | Event::Killed { .. }
+                | Event::Revived { .. }
+                | Event::CaptainElected { .. }
+                | Event::Spared
+                | Event::Ended { .. }
+        )
+    }
+}
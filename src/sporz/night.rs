@@ -0,0 +1,645 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, mem};
+
+use super::{
+    game::{Game, Phase, Player},
+    rules::SpyVisibility,
+    Cause, Event, Genome, PlayerId, Role, SporzError, SporzResult,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionKind {
+    Mutate,
+    MutantKill,
+    Paralyse,
+    Heal,
+    DoctorKill,
+    Inspect,
+    Sequence,
+    Hack,
+    Spy,
+    Paint,
+}
+
+impl ActionKind {
+    pub const ALL: [ActionKind; 10] = [
+        ActionKind::Mutate,
+        ActionKind::MutantKill,
+        ActionKind::Paralyse,
+        ActionKind::Heal,
+        ActionKind::DoctorKill,
+        ActionKind::Inspect,
+        ActionKind::Sequence,
+        ActionKind::Hack,
+        ActionKind::Spy,
+        ActionKind::Paint,
+    ];
+
+    pub fn allowed(self, player: &Player) -> bool {
+        match self {
+            ActionKind::Mutate | ActionKind::MutantKill | ActionKind::Paralyse => player.mutant,
+            ActionKind::Heal | ActionKind::DoctorKill => {
+                player.role == Role::Doctor && !player.mutant
+            }
+            ActionKind::Inspect => player.role == Role::Psychologist,
+            ActionKind::Sequence => player.role == Role::Geneticist,
+            ActionKind::Hack => player.role == Role::Hacker,
+            ActionKind::Spy => player.role == Role::Spy,
+            ActionKind::Paint => player.role == Role::Painter,
+        }
+    }
+
+    /// Actions made by the mutants as a team rather than by a single player.
+    pub fn is_collective(self) -> bool {
+        matches!(
+            self,
+            ActionKind::Mutate | ActionKind::MutantKill | ActionKind::Paralyse
+        )
+    }
+
+    /// Whether submitting `self` replaces an action of kind `other`.
+    pub(super) fn replaces(self, other: ActionKind) -> bool {
+        use ActionKind::*;
+        self == other
+            || matches!(
+                (self, other),
+                (Mutate, MutantKill)
+                    | (MutantKill, Mutate)
+                    | (Heal, DoctorKill)
+                    | (DoctorKill, Heal)
+            )
+    }
+}
+
+impl fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ActionKind::Mutate => "mutate",
+            ActionKind::MutantKill => "kill as a mutant",
+            ActionKind::Paralyse => "paralyse",
+            ActionKind::Heal => "heal",
+            ActionKind::DoctorKill => "kill as a doctor",
+            ActionKind::Inspect => "inspect",
+            ActionKind::Sequence => "sequence",
+            ActionKind::Hack => "hack",
+            ActionKind::Spy => "spy on",
+            ActionKind::Paint => "paint",
+        })
+    }
+}
+
+/// The role whose information the hacker steals.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HackTarget {
+    Psychologist,
+    Geneticist,
+    /// The target is then ignored.
+    ComputerScientist,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Mutate { target: PlayerId },
+    MutantKill { target: PlayerId },
+    Paralyse { target: PlayerId },
+    Heal { target: PlayerId },
+    DoctorKill { target: PlayerId },
+    Inspect { target: PlayerId },
+    Sequence { target: PlayerId },
+    Hack { role: HackTarget, target: PlayerId },
+    Spy { target: PlayerId },
+    Paint { target: PlayerId },
+}
+
+impl Action {
+    pub fn new(kind: ActionKind, target: PlayerId) -> Self {
+        match kind {
+            ActionKind::Mutate => Action::Mutate { target },
+            ActionKind::MutantKill => Action::MutantKill { target },
+            ActionKind::Paralyse => Action::Paralyse { target },
+            ActionKind::Heal => Action::Heal { target },
+            ActionKind::DoctorKill => Action::DoctorKill { target },
+            ActionKind::Inspect => Action::Inspect { target },
+            ActionKind::Sequence => Action::Sequence { target },
+            ActionKind::Hack => Action::Hack {
+                role: HackTarget::Psychologist,
+                target,
+            },
+            ActionKind::Spy => Action::Spy { target },
+            ActionKind::Paint => Action::Paint { target },
+        }
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Mutate { .. } => ActionKind::Mutate,
+            Action::MutantKill { .. } => ActionKind::MutantKill,
+            Action::Paralyse { .. } => ActionKind::Paralyse,
+            Action::Heal { .. } => ActionKind::Heal,
+            Action::DoctorKill { .. } => ActionKind::DoctorKill,
+            Action::Inspect { .. } => ActionKind::Inspect,
+            Action::Sequence { .. } => ActionKind::Sequence,
+            Action::Hack { .. } => ActionKind::Hack,
+            Action::Spy { .. } => ActionKind::Spy,
+            Action::Paint { .. } => ActionKind::Paint,
+        }
+    }
+
+    pub fn target(&self) -> PlayerId {
+        match *self {
+            Action::Mutate { target }
+            | Action::MutantKill { target }
+            | Action::Paralyse { target }
+            | Action::Heal { target }
+            | Action::DoctorKill { target }
+            | Action::Inspect { target }
+            | Action::Sequence { target }
+            | Action::Hack { target, .. }
+            | Action::Spy { target }
+            | Action::Paint { target } => target,
+        }
+    }
+}
+
+/// What the spy learns about their target.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "observation", content = "action", rename_all = "snake_case")]
+pub enum Observation {
+    Targeted(ActionKind),
+    Mutated,
+    Healed,
+    Paralysed,
+}
+
+/// Private information given to a player at the end of the night.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "info", rename_all = "snake_case")]
+pub enum Info {
+    MutantCount {
+        count: usize,
+    },
+    IsMutant {
+        target: PlayerId,
+        mutant: bool,
+    },
+    Genome {
+        target: PlayerId,
+        genome: Genome,
+    },
+    Spied {
+        target: PlayerId,
+        observations: Vec<Observation>,
+    },
+    Painted {
+        target: PlayerId,
+        visited: bool,
+    },
+}
+
+impl Game {
+    fn night(&self) -> SporzResult<u32> {
+        match self.phase {
+            Phase::Night(night) => Ok(night),
+            Phase::Ended(_) => Err(SporzError::Ended),
+            Phase::Day(_) => Err(SporzError::WrongPhase { expected: "night" }),
+        }
+    }
+
+    /// Records the action of `actor` for this night, replacing their previous
+    /// choice, or the one of their team for the mutants.
+    pub fn submit(&mut self, actor: PlayerId, action: Action) -> SporzResult<Vec<Event>> {
+        self.night()?;
+        let kind = action.kind();
+        let hack_forbidden = matches!(action, Action::Hack { role, .. }
+            if !self.variants.hack_targets.contains(&role));
+        if !kind.allowed(self.alive_player(actor)?) || hack_forbidden {
+            return Err(SporzError::NotAllowed {
+                player: actor,
+                action: kind,
+            });
+        }
+        let target = action.target();
+        self.alive_player(target)?;
+        if target == actor && kind != ActionKind::Heal {
+            return Err(SporzError::SelfTarget {
+                player: actor,
+                action: kind,
+            });
+        }
+        self.actions.retain(|(player, submitted)| {
+            !(kind.replaces(submitted.kind()) && (kind.is_collective() || *player == actor))
+        });
+        self.actions.push((actor, action));
+        Ok(vec![Event::ActionSubmitted {
+            player: actor,
+            action,
+        }])
+    }
+
+    /// Resolves the submitted actions and starts the day, unless the game is
+    /// over. Mutants act first, then doctors, then the information roles.
+    pub fn resolve_night(&mut self) -> SporzResult<Vec<Event>> {
+        let night = self.night()?;
+        let actions = mem::take(&mut self.actions);
+        let mut events = Vec::new();
+        // Performed actions, as (actor, kind, target).
+        let mut visits: Vec<(PlayerId, ActionKind, PlayerId)> = Vec::new();
+
+        let paralysed = actions.iter().find_map(|(actor, action)| match action {
+            Action::Paralyse { target } => Some((*actor, *target)),
+            _ => None,
+        });
+        if let Some((actor, target)) = paralysed {
+            visits.push((actor, ActionKind::Paralyse, target));
+            events.push(Event::Paralysed { player: target });
+        }
+        let paralysed = paralysed.map(|(_, target)| target);
+
+        for (actor, action) in &actions {
+            match *action {
+                Action::Mutate { target } => {
+                    visits.push((*actor, ActionKind::Mutate, target));
+                    let player = self.player_mut(target)?;
+                    if player.genome == Genome::Resistant {
+                        events.push(Event::MutationResisted { player: target });
+                    } else if !player.mutant {
+                        player.mutant = true;
+                        events.push(Event::Mutated { player: target });
+                    }
+                }
+                Action::MutantKill { target } => {
+                    visits.push((*actor, ActionKind::MutantKill, target));
+                    self.kill(target, Cause::Mutants, &mut events);
+                }
+                _ => {}
+            }
+        }
+
+        // Doctors mutated or paralysed tonight cannot act.
+        let doctors: Vec<PlayerId> = self
+            .alive()
+            .filter(|player| player.role == Role::Doctor && !player.mutant)
+            .map(|player| player.id)
+            .filter(|id| Some(*id) != paralysed)
+            .collect();
+        let doctor_actions: Vec<(PlayerId, Action)> = actions
+            .iter()
+            .filter(|(actor, _)| doctors.contains(actor))
+            .copied()
+            .collect();
+        for (actor, action) in &doctor_actions {
+            if let Action::Heal { target } = *action {
+                visits.push((*actor, ActionKind::Heal, target));
+                let player = self.player_mut(target)?;
+                if player.alive && player.mutant {
+                    if player.genome == Genome::Host {
+                        events.push(Event::HealFailed { player: target });
+                    } else {
+                        player.mutant = false;
+                        events.push(Event::Healed { player: target });
+                    }
+                }
+            }
+        }
+        let kills: Vec<PlayerId> = doctor_actions
+            .iter()
+            .filter_map(|(_, action)| match action {
+                Action::DoctorKill { target } => Some(*target),
+                _ => None,
+            })
+            .collect();
+        if !kills.is_empty() && kills.len() == doctors.len() && kills.iter().all(|t| *t == kills[0])
+        {
+            for (actor, _) in &doctor_actions {
+                visits.push((*actor, ActionKind::DoctorKill, kills[0]));
+            }
+            self.kill(kills[0], Cause::Doctors, &mut events);
+        }
+
+        // Information roles act on the state left by the mutants and doctors.
+        let active = |game: &Game, id: PlayerId| {
+            Some(id) != paralysed && game.player(id).is_ok_and(|player| player.alive)
+        };
+        let mutants = self.alive().filter(|player| player.mutant).count();
+        let scientists: Vec<PlayerId> = self
+            .alive()
+            .filter(|player| player.role == Role::ComputerScientist)
+            .map(|player| player.id)
+            .collect();
+        for id in scientists {
+            if active(self, id) {
+                events.push(Event::Informed {
+                    player: id,
+                    info: Info::MutantCount { count: mutants },
+                });
+            }
+        }
+        for (actor, action) in &actions {
+            if !active(self, *actor) {
+                continue;
+            }
+            let info = match *action {
+                Action::Inspect { target }
+                | Action::Hack {
+                    role: HackTarget::Psychologist,
+                    target,
+                } => Info::IsMutant {
+                    target,
+                    mutant: self.player(target)?.mutant,
+                },
+                Action::Sequence { target }
+                | Action::Hack {
+                    role: HackTarget::Geneticist,
+                    target,
+                } => Info::Genome {
+                    target,
+                    genome: self.player(target)?.genome,
+                },
+                Action::Hack {
+                    role: HackTarget::ComputerScientist,
+                    ..
+                } => Info::MutantCount { count: mutants },
+                _ => continue,
+            };
+            if !matches!(info, Info::MutantCount { .. }) {
+                visits.push((*actor, action.kind(), action.target()));
+            }
+            events.push(Event::Informed {
+                player: *actor,
+                info,
+            });
+        }
+
+        for (actor, action) in &actions {
+            if let Action::Spy { target } = *action {
+                if !active(self, *actor) {
+                    continue;
+                }
+                let spy = self.variants.spy;
+                let mut observations: Vec<Observation> = visits
+                    .iter()
+                    .filter(|(_, _, visited)| *visited == target)
+                    .filter(|_| spy != SpyVisibility::Effects)
+                    .map(|(_, kind, _)| Observation::Targeted(*kind))
+                    .collect();
+                observations.extend(events.iter().filter_map(|event| match event {
+                    _ if spy == SpyVisibility::Actions => None,
+                    Event::Mutated { player } if *player == target => Some(Observation::Mutated),
+                    Event::Healed { player } if *player == target => Some(Observation::Healed),
+                    Event::Paralysed { player } if *player == target => {
+                        Some(Observation::Paralysed)
+                    }
+                    _ => None,
+                }));
+                visits.push((*actor, ActionKind::Spy, target));
+                events.push(Event::Informed {
+                    player: *actor,
+                    info: Info::Spied {
+                        target,
+                        observations,
+                    },
+                });
+            }
+        }
+
+        for (actor, action) in &actions {
+            if let Action::Paint { target } = *action {
+                if !active(self, *actor) {
+                    continue;
+                }
+                let visited = visits
+                    .iter()
+                    .any(|(visitor, _, visited)| *visited == target && visitor != actor);
+                events.push(Event::Informed {
+                    player: *actor,
+                    info: Info::Painted { target, visited },
+                });
+            }
+        }
+
+        if !self.check_winner(&mut events) {
+            self.phase = Phase::Day(night);
+            events.push(Event::PhaseStarted { phase: self.phase });
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sporz::{rules::Variants, Side};
+
+    const MUTANT: PlayerId = PlayerId(1);
+    const DOCTOR: PlayerId = PlayerId(2);
+    const OTHER_DOCTOR: PlayerId = PlayerId(3);
+    const PSYCHOLOGIST: PlayerId = PlayerId(4);
+    const RESISTANT: PlayerId = PlayerId(5);
+    const HOST: PlayerId = PlayerId(6);
+    const NORMAL: PlayerId = PlayerId(7);
+
+    fn game() -> Game {
+        let assignments = [
+            (MUTANT, Role::BaseMutant, Genome::Host),
+            (DOCTOR, Role::Doctor, Genome::Normal),
+            (OTHER_DOCTOR, Role::Doctor, Genome::Normal),
+            (PSYCHOLOGIST, Role::Psychologist, Genome::Normal),
+            (RESISTANT, Role::Astronaut, Genome::Resistant),
+            (HOST, Role::Astronaut, Genome::Host),
+            (NORMAL, Role::Astronaut, Genome::Normal),
+        ];
+        Game::new(&assignments, Variants::default()).unwrap().0
+    }
+
+    fn resolve(game: &mut Game, actions: &[(PlayerId, Action)]) -> Vec<Event> {
+        for (actor, action) in actions {
+            game.submit(*actor, *action).unwrap();
+        }
+        game.resolve_night().unwrap()
+    }
+
+    #[test]
+    fn submit_checks_the_phase() {
+        let mut game = game();
+        game.resolve_night().unwrap();
+        assert_eq!(
+            game.submit(DOCTOR, Action::Heal { target: NORMAL }),
+            Err(SporzError::WrongPhase { expected: "night" })
+        );
+    }
+
+    #[test]
+    fn submit_checks_the_actor() {
+        let mut game = game();
+        assert_eq!(
+            game.submit(NORMAL, Action::Heal { target: MUTANT }),
+            Err(SporzError::NotAllowed {
+                player: NORMAL,
+                action: ActionKind::Heal
+            })
+        );
+        assert_eq!(
+            game.submit(MUTANT, Action::Mutate { target: MUTANT }),
+            Err(SporzError::SelfTarget {
+                player: MUTANT,
+                action: ActionKind::Mutate
+            })
+        );
+        game.players[1].alive = false;
+        assert_eq!(
+            game.submit(DOCTOR, Action::Heal { target: NORMAL }),
+            Err(SporzError::Dead(DOCTOR))
+        );
+        assert_eq!(
+            game.submit(PSYCHOLOGIST, Action::Inspect { target: DOCTOR }),
+            Err(SporzError::Dead(DOCTOR))
+        );
+    }
+
+    #[test]
+    fn mutants_replace_their_collective_action() {
+        let mut game = game();
+        game.submit(MUTANT, Action::Mutate { target: NORMAL })
+            .unwrap();
+        game.submit(MUTANT, Action::MutantKill { target: HOST })
+            .unwrap();
+        assert_eq!(
+            game.actions,
+            vec![(MUTANT, Action::MutantKill { target: HOST })]
+        );
+    }
+
+    #[test]
+    fn paralysed_players_do_not_act() {
+        let mut game = game();
+        let events = resolve(
+            &mut game,
+            &[
+                (MUTANT, Action::Paralyse { target: DOCTOR }),
+                (MUTANT, Action::Mutate { target: NORMAL }),
+                (DOCTOR, Action::Heal { target: NORMAL }),
+            ],
+        );
+        assert!(events.contains(&Event::Paralysed { player: DOCTOR }));
+        assert!(game.player(NORMAL).unwrap().mutant);
+
+        game.eliminate(None).unwrap();
+        let events = resolve(
+            &mut game,
+            &[
+                (
+                    MUTANT,
+                    Action::Paralyse {
+                        target: PSYCHOLOGIST,
+                    },
+                ),
+                (PSYCHOLOGIST, Action::Inspect { target: MUTANT }),
+            ],
+        );
+        assert!(!events.iter().any(
+            |event| matches!(event, Event::Informed { player, .. } if *player == PSYCHOLOGIST)
+        ));
+    }
+
+    #[test]
+    fn resistants_cannot_be_mutated() {
+        let mut game = game();
+        let events = resolve(&mut game, &[(MUTANT, Action::Mutate { target: RESISTANT })]);
+        assert!(events.contains(&Event::MutationResisted { player: RESISTANT }));
+        assert!(!game.player(RESISTANT).unwrap().mutant);
+    }
+
+    #[test]
+    fn hosts_cannot_be_healed() {
+        let mut game = game();
+        let events = resolve(
+            &mut game,
+            &[
+                (MUTANT, Action::Mutate { target: HOST }),
+                (DOCTOR, Action::Heal { target: HOST }),
+                (OTHER_DOCTOR, Action::Heal { target: MUTANT }),
+            ],
+        );
+        assert!(events.contains(&Event::HealFailed { player: HOST }));
+        assert!(events.contains(&Event::HealFailed { player: MUTANT }));
+        assert!(game.player(HOST).unwrap().mutant);
+        assert!(game.player(MUTANT).unwrap().mutant);
+    }
+
+    #[test]
+    fn heal_undoes_a_mutation_of_the_same_night() {
+        let mut game = game();
+        let events = resolve(
+            &mut game,
+            &[
+                (DOCTOR, Action::Heal { target: NORMAL }),
+                (MUTANT, Action::Mutate { target: NORMAL }),
+            ],
+        );
+        let mutated = events
+            .iter()
+            .position(|event| *event == Event::Mutated { player: NORMAL });
+        let healed = events
+            .iter()
+            .position(|event| *event == Event::Healed { player: NORMAL });
+        assert!(mutated.unwrap() < healed.unwrap());
+        assert!(!game.player(NORMAL).unwrap().mutant);
+    }
+
+    #[test]
+    fn doctor_kill_needs_every_doctor() {
+        let mut game = game();
+        resolve(
+            &mut game,
+            &[(DOCTOR, Action::DoctorKill { target: MUTANT })],
+        );
+        assert!(game.player(MUTANT).unwrap().alive);
+
+        game.eliminate(None).unwrap();
+        resolve(
+            &mut game,
+            &[
+                (DOCTOR, Action::DoctorKill { target: MUTANT }),
+                (OTHER_DOCTOR, Action::DoctorKill { target: HOST }),
+            ],
+        );
+        assert!(game.player(MUTANT).unwrap().alive);
+        assert!(game.player(HOST).unwrap().alive);
+
+        game.eliminate(None).unwrap();
+        let events = resolve(
+            &mut game,
+            &[
+                (DOCTOR, Action::DoctorKill { target: MUTANT }),
+                (OTHER_DOCTOR, Action::DoctorKill { target: MUTANT }),
+            ],
+        );
+        assert!(events.contains(&Event::Killed {
+            player: MUTANT,
+            cause: Cause::Doctors
+        }));
+        assert_eq!(game.phase, Phase::Ended(Side::Astronauts));
+    }
+
+    #[test]
+    fn information_reflects_the_night() {
+        let mut game = game();
+        let events = resolve(
+            &mut game,
+            &[
+                (MUTANT, Action::Mutate { target: NORMAL }),
+                (PSYCHOLOGIST, Action::Inspect { target: NORMAL }),
+            ],
+        );
+        assert!(events.contains(&Event::Informed {
+            player: PSYCHOLOGIST,
+            info: Info::IsMutant {
+                target: NORMAL,
+                mutant: true
+            }
+        }));
+    }
+}
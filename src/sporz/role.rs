@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+use super::SporzError;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// No power.
+    Astronaut,
+    /// Starts mutated, with a host genome.
+    BaseMutant,
+    /// Heals a player each night, or kills one together with the other doctors.
+    Doctor,
+    /// Learns whether a player is a mutant.
+    Psychologist,
+    /// Learns the genome of a player.
+    Geneticist,
+    /// Learns the number of mutants.
+    ComputerScientist,
+    /// Gets the information of the psychologist, geneticist or computer
+    /// scientist, on a target of their choice.
+    Hacker,
+    /// Learns everything that happened to a player during the night.
+    Spy,
+    /// Paints the door of a player and learns whether anyone visited them.
+    Painter,
+    /// Looks like an astronaut but wins with the mutants.
+    Traitor,
+}
+
+impl Role {
+    pub const ALL: [Role; 10] = [
+        Role::Astronaut,
+        Role::BaseMutant,
+        Role::Doctor,
+        Role::Psychologist,
+        Role::Geneticist,
+        Role::ComputerScientist,
+        Role::Hacker,
+        Role::Spy,
+        Role::Painter,
+        Role::Traitor,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Astronaut => "astronaut",
+            Role::BaseMutant => "base_mutant",
+            Role::Doctor => "doctor",
+            Role::Psychologist => "psychologist",
+            Role::Geneticist => "geneticist",
+            Role::ComputerScientist => "computer_scientist",
+            Role::Hacker => "hacker",
+            Role::Spy => "spy",
+            Role::Painter => "painter",
+            Role::Traitor => "traitor",
+        }
+    }
+
+    /// The side the role starts on; mutated players join the mutants whatever
+    /// their role.
+    pub fn side(self) -> Side {
+        match self {
+            Role::BaseMutant | Role::Traitor => Side::Mutants,
+            _ => Side::Astronauts,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Role {
+    type Err = SporzError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase().replace([' ', '-'], "_");
+        Role::ALL
+            .iter()
+            .copied()
+            .find(|role| role.name() == s)
+            .ok_or(SporzError::UnknownRole(s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Genome {
+    Normal,
+    /// Cannot be healed once mutated.
+    Host,
+    /// Cannot be mutated.
+    Resistant,
+}
+
+impl fmt::Display for Genome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Genome::Normal => "normal",
+            Genome::Host => "host",
+            Genome::Resistant => "resistant",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Astronauts,
+    Mutants,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Side::Astronauts => "astronauts",
+            Side::Mutants => "mutants",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_parse_from_their_name() {
+        for role in Role::ALL.iter().copied() {
+            assert_eq!(role.name().parse::<Role>(), Ok(role));
+        }
+        assert_eq!(" Computer Scientist".parse(), Ok(Role::ComputerScientist));
+        assert_eq!("base-mutant".parse(), Ok(Role::BaseMutant));
+        assert_eq!(
+            "captain".parse::<Role>(),
+            Err(SporzError::UnknownRole("captain".to_string()))
+        );
+    }
+}
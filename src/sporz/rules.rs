@@ -0,0 +1,192 @@
+//! What tables may change: the variants of the rules, and presets giving a
+//! composition for each table size. Compositions are checked for balance
+//! before a game is dealt.
+
+use serde::{Deserialize, Serialize};
+
+use super::{Composition, HackTarget, Role, Side, SporzError, SporzResult, MIN_PLAYERS};
+
+/// At most one player in this many may start with the mutants.
+const MUTANT_SHARE: usize = 3;
+
+/// What the spy learns about their target.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpyVisibility {
+    /// The actions targeting the player and what happened to them.
+    Full,
+    /// Only the actions targeting the player.
+    Actions,
+    /// Only whether the player was mutated, healed or paralysed.
+    Effects,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Variants {
+    /// The roles whose information the hacker may steal.
+    pub hack_targets: Vec<HackTarget>,
+    pub spy: SpyVisibility,
+    /// The side the traitor wins with.
+    pub traitor_side: Side,
+}
+
+impl Default for Variants {
+    fn default() -> Self {
+        Variants {
+            hack_targets: vec![
+                HackTarget::Psychologist,
+                HackTarget::Geneticist,
+                HackTarget::ComputerScientist,
+            ],
+            spy: SpyVisibility::Full,
+            traitor_side: Side::Mutants,
+        }
+    }
+}
+
+/// The composition of tables of `players` players or more, until the next
+/// one of the preset.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SizedComposition {
+    pub players: usize,
+    #[serde(flatten)]
+    pub composition: Composition,
+}
+
+/// A named rule set.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Preset {
+    pub name: String,
+    #[serde(default)]
+    pub variants: Variants,
+    pub compositions: Vec<SizedComposition>,
+}
+
+impl Composition {
+    /// Checks that `players` players can play this composition, and that
+    /// neither side starts with the game already won.
+    pub fn validate(&self, players: usize, variants: &Variants) -> SporzResult<()> {
+        if players < MIN_PLAYERS {
+            return Err(SporzError::NotEnoughPlayers);
+        }
+        let count = |role: Role| self.roles.iter().filter(|r| **r == role).count();
+        let base_mutants = count(Role::BaseMutant);
+        if self.roles.len() > players
+            || base_mutants + self.hosts + self.resistants > players
+            || base_mutants == 0
+        {
+            return Err(SporzError::InvalidComposition {
+                players,
+                roles: self.roles.len(),
+            });
+        }
+        if count(Role::Doctor) == 0 {
+            return Err(SporzError::Unbalanced(
+                "without a doctor, nobody can heal the mutants",
+            ));
+        }
+        let traitors = match variants.traitor_side {
+            Side::Mutants => count(Role::Traitor),
+            Side::Astronauts => 0,
+        };
+        if (base_mutants + traitors) * MUTANT_SHARE > players {
+            return Err(SporzError::Unbalanced(
+                "more than a third of the players start with the mutants",
+            ));
+        }
+        if count(Role::Hacker) > 0 && variants.hack_targets.is_empty() {
+            return Err(SporzError::Unbalanced("the hacker has nothing to hack"));
+        }
+        Ok(())
+    }
+}
+
+impl Preset {
+    /// The composition for `players` players: the one of the largest table
+    /// they fill, the players left being astronauts.
+    pub fn composition(&self, players: usize) -> SporzResult<&Composition> {
+        self.compositions
+            .iter()
+            .filter(|sized| sized.players <= players)
+            .max_by_key(|sized| sized.players)
+            .map(|sized| &sized.composition)
+            .ok_or(SporzError::NoComposition { players })
+    }
+
+    /// Validates each composition for the table size it is meant for.
+    pub fn validate(&self) -> SporzResult<()> {
+        if self.compositions.is_empty() {
+            return Err(SporzError::NoComposition {
+                players: MIN_PLAYERS,
+            });
+        }
+        for sized in &self.compositions {
+            sized.composition.validate(sized.players, &self.variants)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_compositions_are_balanced() {
+        for players in MIN_PLAYERS..=20 {
+            assert_eq!(
+                Composition::standard(players).validate(players, &Variants::default()),
+                Ok(())
+            );
+        }
+    }
+
+    #[test]
+    fn unbalanced_compositions() {
+        let variants = Variants::default();
+        let composition = |roles: Vec<Role>| Composition {
+            roles,
+            hosts: 0,
+            resistants: 0,
+        };
+        assert!(matches!(
+            composition(vec![Role::BaseMutant]).validate(6, &variants),
+            Err(SporzError::Unbalanced(_))
+        ));
+        let traitors = composition(vec![Role::BaseMutant, Role::Traitor, Role::Doctor]);
+        assert!(matches!(
+            traitors.validate(5, &variants),
+            Err(SporzError::Unbalanced(_))
+        ));
+        let astronaut_traitor = Variants {
+            traitor_side: Side::Astronauts,
+            ..Variants::default()
+        };
+        assert_eq!(traitors.validate(5, &astronaut_traitor), Ok(()));
+        assert!(matches!(
+            composition(vec![Role::Doctor]).validate(5, &variants),
+            Err(SporzError::InvalidComposition { .. })
+        ));
+    }
+
+    #[test]
+    fn presets_pick_the_largest_filled_table() {
+        let sized = |players| SizedComposition {
+            players,
+            composition: Composition::standard(players),
+        };
+        let preset = Preset {
+            name: "test".to_string(),
+            variants: Variants::default(),
+            compositions: vec![sized(5), sized(9)],
+        };
+        assert_eq!(preset.validate(), Ok(()));
+        assert_eq!(preset.composition(8), Ok(&Composition::standard(5)));
+        assert_eq!(preset.composition(12), Ok(&Composition::standard(9)));
+        assert_eq!(
+            preset.composition(4),
+            Err(SporzError::NoComposition { players: 4 })
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use chrono::{Duration, Utc};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        CommandGroup, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, UserId},
+    },
+    prelude::TypeMapKey,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::db::{self, DbResult};
+
+const STATS_TREE: &str = "command_stats";
+const TOP_SIZE: usize = 10;
+
+#[group]
+#[commands(stats)]
+struct Stats;
+
+/// Total invocations per command, loaded from the database on startup.
+pub struct CommandCounter;
+
+impl TypeMapKey for CommandCounter {
+    type Value = HashMap<String, u64>;
+}
+
+pub fn load_counts(db: &sled::Db) -> DbResult<HashMap<String, u64>> {
+    Ok(db::counters(&db.open_tree(STATS_TREE)?, "total/")?
+        .into_iter()
+        .collect())
+}
+
+/// Persists an invocation of `command_name`, broken down by user, channel and day.
+pub fn record_invocation(
+    db: &sled::Db,
+    user_id: UserId,
+    channel_id: ChannelId,
+    command_name: &str,
+) -> DbResult<()> {
+    let tree = db.open_tree(STATS_TREE)?;
+    let day = Utc::now().format("%Y-%m-%d");
+    db::increment(&tree, format!("total/{}", command_name))?;
+    db::increment(&tree, format!("user/{}/{}", user_id, command_name))?;
+    db::increment(&tree, format!("channel/{}/{}", channel_id, command_name))?;
+    db::increment(&tree, format!("day/{}/{}", day, command_name))?;
+    Ok(())
+}
+
+pub fn record_error(db: &sled::Db, command_name: &str) -> DbResult<()> {
+    db::increment(
+        &db.open_tree(STATS_TREE)?,
+        format!("error/{}", command_name),
+    )?;
+    Ok(())
+}
+
+/// Names of every command of `group`, including sub groups and sub commands.
+pub fn command_names(group: &CommandGroup) -> Vec<&'static str> {
+    fn push_commands(
+        commands: &[&'static serenity::framework::standard::Command],
+        names: &mut Vec<&'static str>,
+    ) {
+        for command in commands {
+            names.extend(command.options.names.first().copied());
+            push_commands(command.options.sub_commands, names);
+        }
+    }
+    let mut names = Vec::new();
+    push_commands(group.options.commands, &mut names);
+    for sub_group in group.options.sub_groups {
+        names.extend(command_names(sub_group));
+    }
+    names
+}
+
+/// Sums counters keyed by `<id>/<command>` per id.
+fn totals_by_id(counters: Vec<(String, u64)>) -> Vec<(String, u64)> {
+    let mut totals: HashMap<String, u64> = HashMap::new();
+    for (key, count) in counters {
+        let id = key.split('/').next().unwrap_or_default().to_string();
+        *totals.entry(id).or_insert(0) += count;
+    }
+    sorted(totals.into_iter().collect())
+}
+
+fn sorted(mut counters: Vec<(String, u64)>) -> Vec<(String, u64)> {
+    counters.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counters
+}
+
+fn lines(counters: &[(String, u64)], label: impl Fn(&str) -> String) -> String {
+    if counters.is_empty() {
+        return "none".to_string();
+    }
+    counters
+        .iter()
+        .take(TOP_SIZE)
+        .map(|(key, count)| format!("{} — {}", label(key), count))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[command]
+#[owners_only]
+#[sub_commands(commands)]
+#[description = "Bot usage statistics."]
+async fn stats(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx, "usage: `!stats commands`").await?;
+    Ok(())
+}
+
+#[command]
+#[owners_only]
+#[description = "Show the most used and never used commands, with their error counts."]
+async fn commands(ctx: &Context, msg: &Message) -> CommandResult {
+    let db = db::from_context(ctx).await;
+    let tree = db.open_tree(STATS_TREE)?;
+    let totals = sorted(db::counters(&tree, "total/")?);
+    let errors = sorted(db::counters(&tree, "error/")?);
+    let users = totals_by_id(db::counters(&tree, "user/")?);
+    let channels = totals_by_id(db::counters(&tree, "channel/")?);
+
+    let today = Utc::now().date();
+    let mut days = Vec::new();
+    for offset in 0..7 {
+        let day = (today - Duration::days(offset))
+            .format("%Y-%m-%d")
+            .to_string();
+        let count = db::counters(&tree, &format!("day/{}/", day))?
+            .into_iter()
+            .map(|(_, count)| count)
+            .sum::<u64>();
+        days.push((day, count));
+    }
+
+    let used: HashSet<&str> = totals.iter().map(|(name, _)| name.as_str()).collect();
+    let mut never_used: Vec<&str> = crate::GROUPS
+        .iter()
+        .flat_map(|group| command_names(group))
+        .filter(|name| !used.contains(name))
+        .collect();
+    never_used.sort_unstable();
+    never_used.dedup();
+    let never_used = if never_used.is_empty() {
+        "none".to_string()
+    } else {
+        never_used.join(", ")
+    };
+
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Command statistics")
+                    .field("Most used", lines(&totals, |name| name.to_string()), true)
+                    .field("Errors", lines(&errors, |name| name.to_string()), true)
+                    .field("Never used", never_used, false)
+                    .field("Top users", lines(&users, |id| format!("<@{}>", id)), true)
+                    .field(
+                        "Top channels",
+                        lines(&channels, |id| format!("<#{}>", id)),
+                        true,
+                    )
+                    .field("Last 7 days", lines(&days, |day| day.to_string()), false)
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counters(entries: &[(&str, u64)]) -> Vec<(String, u64)> {
+        entries
+            .iter()
+            .map(|(key, count)| (key.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn counters_are_ranked_by_count_then_name() {
+        let ranked = sorted(counters(&[("roll", 2), ("add", 5), ("help", 2)]));
+        assert_eq!(ranked, counters(&[("add", 5), ("help", 2), ("roll", 2)]));
+    }
+
+    #[test]
+    fn totals_are_summed_per_id() {
+        let totals = totals_by_id(counters(&[
+            ("1/add", 1),
+            ("2/add", 4),
+            ("1/help", 2),
+            ("1/roll", 2),
+        ]));
+        assert_eq!(totals, counters(&[("1", 5), ("2", 4)]));
+    }
+
+    #[test]
+    fn lines_are_limited_to_the_top() {
+        assert_eq!(lines(&[], |key| key.to_string()), "none");
+        let many: Vec<_> = (0..TOP_SIZE as u64 + 5)
+            .map(|i| (i.to_string(), i))
+            .collect();
+        let text = lines(&sorted(many), |key| format!("<@{}>", key));
+        assert_eq!(text.lines().count(), TOP_SIZE);
+        assert!(text.starts_with(&format!("<@{0}> — {0}", TOP_SIZE + 4)));
+    }
+
+    #[test]
+    fn totals_are_reloaded_from_the_database() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        record_error(&db, "add").unwrap();
+        let tree = db.open_tree(STATS_TREE).unwrap();
+        db::increment(&tree, "total/add").unwrap();
+        db::increment(&tree, "total/add").unwrap();
+        db::increment(&tree, "total/help").unwrap();
+        let counts = load_counts(&db).unwrap();
+        assert_eq!(counts.len(), 2);
+        assert_eq!(counts["add"], 2);
+        assert_eq!(counts["help"], 1);
+        assert_eq!(
+            db::counters(&tree, "error/").unwrap(),
+            counters(&[("add", 1)])
+        );
+    }
+
+    #[test]
+    fn sub_commands_are_listed() {
+        let names = command_names(&STATS_GROUP);
+        assert_eq!(names, vec!["stats", "commands"]);
+    }
+}
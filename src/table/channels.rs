@@ -0,0 +1,350 @@
+//! The Discord channels of a table: the category holding the night channels,
+//! private channels for players who refuse direct messages, the lock of the
+//! public channel during the night and the spectators, who read the night
+//! channels but may not write in the public one.
+
+use serenity::{
+    client::Context,
+    model::{
+        channel::{ChannelType, PermissionOverwrite, PermissionOverwriteType},
+        id::{ChannelId, RoleId, UserId},
+        Permissions,
+    },
+};
+use tracing::warn;
+
+use super::Table;
+use crate::sporz::Game;
+
+fn member(user_id: UserId, allow: Permissions) -> PermissionOverwrite {
+    PermissionOverwrite {
+        allow,
+        deny: Permissions::empty(),
+        kind: PermissionOverwriteType::Member(user_id),
+    }
+}
+
+fn everyone(table: &Table) -> PermissionOverwriteType {
+    PermissionOverwriteType::Role(RoleId(table.guild_id.0))
+}
+
+fn hidden(table: &Table) -> PermissionOverwrite {
+    PermissionOverwrite {
+        allow: Permissions::empty(),
+        deny: Permissions::READ_MESSAGES,
+        kind: everyone(table),
+    }
+}
+
+fn writer() -> Permissions {
+    Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES | Permissions::READ_MESSAGE_HISTORY
+}
+
+fn reader(user_id: UserId) -> PermissionOverwrite {
+    PermissionOverwrite {
+        allow: Permissions::READ_MESSAGES | Permissions::READ_MESSAGE_HISTORY,
+        deny: Permissions::SEND_MESSAGES,
+        kind: PermissionOverwriteType::Member(user_id),
+    }
+}
+
+/// Creates a channel only the bot and `members` can see, in the category of
+/// the table, which is created on first use.
+pub async fn create_private(
+    ctx: &Context,
+    table: &mut Table,
+    name: &str,
+    members: &[UserId],
+) -> serenity::Result<ChannelId> {
+    let bot_id = ctx.cache.current_user_id().await;
+    let category = match table.category {
+        Some(category) => category,
+        None => {
+            let overwrites = vec![
+                hidden(table),
+                member(bot_id, writer() | Permissions::MANAGE_CHANNELS),
+            ];
+            let category = table
+                .guild_id
+                .create_channel(&ctx.http, |c| {
+                    c.name(format!("sporz-{}", table.id))
+                        .kind(ChannelType::Category)
+                        .permissions(overwrites)
+                })
+                .await?;
+            table.category = Some(category.id);
+            category.id
+        }
+    };
+    let mut overwrites = vec![hidden(table), member(bot_id, writer())];
+    overwrites.extend(members.iter().map(|user_id| member(*user_id, writer())));
+    let channel = table
+        .guild_id
+        .create_channel(&ctx.http, |c| {
+            c.name(name)
+                .kind(ChannelType::Text)
+                .category(category)
+                .permissions(overwrites)
+        })
+        .await?;
+    table.channels.push(channel.id);
+    Ok(channel.id)
+}
+
+/// Gives the mutants channel to the living mutants only, the dead ones being
+/// spectators.
+pub async fn sync_mutants(ctx: &Context, table: &Table, game: &Game) {
+    let channel_id = match table.mutants_channel {
+        Some(channel_id) => channel_id,
+        None => return,
+    };
+    for player in game.alive() {
+        let user_id = UserId(player.id.0);
+        let result = if player.mutant {
+            channel_id
+                .create_permission(&ctx.http, &member(user_id, writer()))
+                .await
+        } else {
+            channel_id
+                .delete_permission(&ctx.http, PermissionOverwriteType::Member(user_id))
+                .await
+        };
+        if let Err(why) = result {
+            warn!(
+                table = table.id,
+                "Could not update the mutants channel: {}", why
+            );
+        }
+    }
+}
+
+/// Saves the overwrite of `@everyone` on the public channel, to restore it
+/// when the game ends.
+pub async fn save_public(ctx: &Context, table: &mut Table) -> serenity::Result<()> {
+    let channel = table.channel_id.to_channel(ctx).await?;
+    table.public_overwrite = channel.guild().and_then(|channel| {
+        channel
+            .permission_overwrites
+            .iter()
+            .find(|overwrite| overwrite.kind == everyone(table))
+            .map(|overwrite| (overwrite.allow.bits(), overwrite.deny.bits()))
+    });
+    Ok(())
+}
+
+/// Forbids everybody but the bot to write in the public channel.
+pub async fn lock_public(ctx: &Context, table: &Table) -> serenity::Result<()> {
+    let (allow, deny) = table.public_overwrite.unwrap_or((0, 0));
+    let overwrite = PermissionOverwrite {
+        allow: Permissions::from_bits_truncate(allow) - Permissions::SEND_MESSAGES,
+        deny: Permissions::from_bits_truncate(deny) | Permissions::SEND_MESSAGES,
+        kind: everyone(table),
+    };
+    let bot_id = ctx.cache.current_user_id().await;
+    table
+        .channel_id
+        .create_permission(&ctx.http, &member(bot_id, writer()))
+        .await?;
+    table
+        .channel_id
+        .create_permission(&ctx.http, &overwrite)
+        .await
+}
+
+/// Restores the overwrite of `@everyone` on the public channel.
+pub async fn unlock_public(ctx: &Context, table: &Table) -> serenity::Result<()> {
+    match table.public_overwrite {
+        Some((allow, deny)) => {
+            let overwrite = PermissionOverwrite {
+                allow: Permissions::from_bits_truncate(allow),
+                deny: Permissions::from_bits_truncate(deny),
+                kind: everyone(table),
+            };
+            table
+                .channel_id
+                .create_permission(&ctx.http, &overwrite)
+                .await
+        }
+        None => {
+            table
+                .channel_id
+                .delete_permission(&ctx.http, everyone(table))
+                .await
+        }
+    }
+}
+
+/// The channels of the night: the mutants channel and the private channels
+/// of the players.
+fn night_channels(table: &Table) -> impl Iterator<Item = &ChannelId> {
+    table.channels.iter().filter(move |channel_id| {
+        Some(**channel_id) != table.spectators_channel && Some(**channel_id) != table.gm_channel
+    })
+}
+
+fn inbox(table: &Table, user_id: UserId) -> Option<ChannelId> {
+    table
+        .seats
+        .iter()
+        .find(|seat| seat.user_id == user_id)
+        .and_then(|seat| seat.inbox)
+}
+
+/// Lets `user_id` talk with the other spectators and read the night
+/// channels, and forbids them to write in the public channel. Returns their
+/// previous overwrite of the public channel.
+async fn grant(
+    ctx: &Context,
+    table: &Table,
+    user_id: UserId,
+) -> serenity::Result<Option<(u64, u64)>> {
+    if let Some(channel_id) = table.spectators_channel {
+        channel_id
+            .create_permission(&ctx.http, &member(user_id, writer()))
+            .await?;
+    }
+    let inbox = inbox(table, user_id);
+    for channel_id in night_channels(table).filter(|channel_id| Some(**channel_id) != inbox) {
+        channel_id
+            .create_permission(&ctx.http, &reader(user_id))
+            .await?;
+    }
+    let kind = PermissionOverwriteType::Member(user_id);
+    let channel = table.channel_id.to_channel(ctx).await?;
+    let saved = channel.guild().and_then(|channel| {
+        channel
+            .permission_overwrites
+            .iter()
+            .find(|overwrite| overwrite.kind == kind)
+            .map(|overwrite| (overwrite.allow.bits(), overwrite.deny.bits()))
+    });
+    let (allow, deny) = saved.unwrap_or((0, 0));
+    let overwrite = PermissionOverwrite {
+        allow: Permissions::from_bits_truncate(allow) - Permissions::SEND_MESSAGES,
+        deny: Permissions::from_bits_truncate(deny) | Permissions::SEND_MESSAGES,
+        kind,
+    };
+    table
+        .channel_id
+        .create_permission(&ctx.http, &overwrite)
+        .await?;
+    Ok(saved)
+}
+
+/// Restores the overwrite of `user_id` on the public channel.
+async fn restore(
+    ctx: &Context,
+    table: &Table,
+    user_id: UserId,
+    saved: Option<(u64, u64)>,
+) -> serenity::Result<()> {
+    match saved {
+        Some((allow, deny)) => {
+            let overwrite = PermissionOverwrite {
+                allow: Permissions::from_bits_truncate(allow),
+                deny: Permissions::from_bits_truncate(deny),
+                kind: PermissionOverwriteType::Member(user_id),
+            };
+            table
+                .channel_id
+                .create_permission(&ctx.http, &overwrite)
+                .await
+        }
+        None => {
+            table
+                .channel_id
+                .delete_permission(&ctx.http, PermissionOverwriteType::Member(user_id))
+                .await
+        }
+    }
+}
+
+/// Takes back what `grant` gave, except the mutants channel which follows
+/// the mutations.
+async fn revoke(
+    ctx: &Context,
+    table: &Table,
+    user_id: UserId,
+    saved: Option<(u64, u64)>,
+) -> serenity::Result<()> {
+    let kind = PermissionOverwriteType::Member(user_id);
+    if let Some(channel_id) = table.spectators_channel {
+        channel_id.delete_permission(&ctx.http, kind).await?;
+    }
+    let inbox = inbox(table, user_id);
+    for channel_id in night_channels(table) {
+        if Some(*channel_id) != inbox && Some(*channel_id) != table.mutants_channel {
+            channel_id.delete_permission(&ctx.http, kind).await?;
+        }
+    }
+    restore(ctx, table, user_id, saved).await
+}
+
+/// Makes spectators of the dead players and the onlookers, and players again
+/// of the revived ones.
+pub async fn sync_spectators(ctx: &Context, table: &mut Table) {
+    let spectators: Vec<UserId> = match &table.game {
+        Some(game) => game
+            .players
+            .iter()
+            .filter(|player| !player.alive)
+            .map(|player| UserId(player.id.0))
+            .chain(table.spectators.iter().copied())
+            .collect(),
+        None => return,
+    };
+    for user_id in &spectators {
+        if table.muted.iter().any(|(muted, _)| muted == user_id) {
+            continue;
+        }
+        match grant(ctx, table, *user_id).await {
+            Ok(saved) => table.muted.push((*user_id, saved)),
+            Err(why) => {
+                warn!(table = table.id, %user_id, "Could not make a spectator: {}", why)
+            }
+        }
+    }
+    let revived: Vec<(UserId, Option<(u64, u64)>)> = table
+        .muted
+        .iter()
+        .filter(|(user_id, _)| !spectators.contains(user_id))
+        .copied()
+        .collect();
+    for (user_id, saved) in revived {
+        match revoke(ctx, table, user_id, saved).await {
+            Ok(()) => table.muted.retain(|(muted, _)| *muted != user_id),
+            Err(why) => warn!(table = table.id, %user_id, "Could not restore a player: {}", why),
+        }
+    }
+}
+
+/// Deletes every channel created for the table and unlocks the public one.
+pub async fn cleanup(ctx: &Context, table: &Table) {
+    for (user_id, saved) in &table.muted {
+        if let Err(why) = restore(ctx, table, *user_id, *saved).await {
+            warn!(table = table.id, %user_id, "Could not restore the public channel: {}", why);
+        }
+    }
+    if let Err(why) = unlock_public(ctx, table).await {
+        warn!(
+            table = table.id,
+            "Could not unlock the public channel: {}", why
+        );
+    }
+    let bot_id = ctx.cache.current_user_id().await;
+    if let Err(why) = table
+        .channel_id
+        .delete_permission(&ctx.http, PermissionOverwriteType::Member(bot_id))
+        .await
+    {
+        warn!(
+            table = table.id,
+            "Could not restore the public channel: {}", why
+        );
+    }
+    for channel_id in table.channels.iter().chain(table.category.iter()) {
+        if let Err(why) = channel_id.delete(&ctx.http).await {
+            warn!(table = table.id, %channel_id, "Could not delete a channel: {}", why);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! Game master mode: the host runs the game instead of playing it. The game
+//! master sees every event in a private channel, and the resolution of each
+//! night is only proposed to them: they confirm it, maybe after killing or
+//! reviving players and adding narration.
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{GuildId, UserId},
+    },
+};
+use tracing::info;
+
+use super::{
+    announce, announce_lines, check_host, conclude, end_phase, history, player_id, reply_with,
+    update, Seat, Table, TableError,
+};
+use crate::sporz::{Event, Game, Phase, PlayerId, SporzError, SporzResult};
+
+#[group]
+#[commands(gm)]
+#[only_in(guilds)]
+struct GameMaster;
+
+/// A resolution of the night waiting for the game master.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Proposal {
+    /// The game once the resolution is applied.
+    pub game: Game,
+    pub events: Vec<Event>,
+    /// Told to the players before the events.
+    pub narration: Vec<String>,
+}
+
+fn check_gm(table: &Table, user_id: UserId) -> Result<(), TableError> {
+    match table.gm {
+        Some(gm) if gm == user_id => Ok(()),
+        Some(gm) => Err(TableError::NotGameMaster(gm)),
+        None => Err(TableError::NoGameMaster),
+    }
+}
+
+/// Shows `events` to the game master as they happen.
+pub async fn mirror(ctx: &Context, table: &Table, events: &[Event]) {
+    let lines = events.iter().map(history::describe_event).collect();
+    announce_lines(ctx, table.gm_channel, lines).await
+}
+
+async fn show(ctx: &Context, table: &Table) {
+    let proposal = match &table.proposal {
+        Some(proposal) => proposal,
+        None => return,
+    };
+    let mut lines = vec!["**Proposed resolution**".to_string()];
+    lines.extend(proposal.events.iter().map(history::describe_event));
+    lines.extend(
+        proposal
+            .narration
+            .iter()
+            .map(|narration| format!("> {}", narration)),
+    );
+    lines.push(
+        "Publish it with `gm confirm`, change it with `gm kill` and `gm revive`, \
+         or add narration with `gm narrate`."
+            .to_string(),
+    );
+    announce_lines(ctx, table.gm_channel, lines).await
+}
+
+/// Resolves `night` without publishing it, for the game master to confirm.
+pub async fn propose(ctx: &Context, guild_id: GuildId, night: u32) -> Result<(), TableError> {
+    let table = update(ctx, guild_id, |table| {
+        let game = table.game.as_ref().ok_or(TableError::NotStarted)?;
+        if game.phase != Phase::Night(night) {
+            return Err(SporzError::WrongPhase {
+                expected: "phase being ended",
+            }
+            .into());
+        }
+        if table.proposal.is_some() {
+            return Err(TableError::AlreadyProposed);
+        }
+        let mut game = game.clone();
+        let events = game.resolve_night()?;
+        table.proposal = Some(Proposal {
+            game,
+            events,
+            narration: Vec::new(),
+        });
+        Ok(table.clone())
+    })
+    .await?;
+    info!(table = table.id, night, "Proposed a night resolution");
+    show(ctx, &table).await;
+    Ok(())
+}
+
+#[command]
+#[sub_commands(mode, skip, kill, revive, pause, narrate, confirm)]
+#[description = "Run a Sporz game as its game master."]
+#[usage = "[mode|skip|kill|revive|pause|narrate|confirm]"]
+async fn gm(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let reply = match super::get(ctx, guild_id).await {
+        Some(Table {
+            gm: Some(gm),
+            paused,
+            proposal,
+            ..
+        }) => format!(
+            "<@{}> is the game master{}{}",
+            gm,
+            if paused { ", the game is paused" } else { "" },
+            if proposal.is_some() {
+                ", a resolution waits for confirmation"
+            } else {
+                ""
+            }
+        ),
+        Some(_) => TableError::NoGameMaster.to_string(),
+        None => TableError::NoTable.to_string(),
+    };
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Run the game you host as its game master instead of playing, or play again."]
+async fn mode(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        check_host(table, user_id)?;
+        if table.game.is_some() {
+            return Err(TableError::Started);
+        }
+        if table.gm.take().is_some() {
+            table.seats.insert(
+                0,
+                Seat {
+                    user_id,
+                    inbox: None,
+                },
+            );
+            Ok("you play again".to_string())
+        } else {
+            table.gm = Some(user_id);
+            table.seats.retain(|seat| seat.user_id != user_id);
+            Ok("you are the game master and no longer play".to_string())
+        }
+    })
+    .await;
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "End the current phase now, even if the game is paused."]
+async fn skip(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let table = update(ctx, guild_id, |table| {
+        check_gm(table, user_id)?;
+        Ok(table.clone())
+    })
+    .await;
+    let result = match table {
+        Ok(table) => match table.game.as_ref().map(|game| game.phase) {
+            Some(phase) => end_phase(ctx, &table, phase)
+                .await
+                .map(|_| format!("{} skipped", phase)),
+            None => Err(TableError::NotStarted),
+        },
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+/// Applies a decision of the game master to the proposed resolution if there
+/// is one, or to the game right away.
+async fn overrule(
+    ctx: &Context,
+    msg: &Message,
+    mut args: Args,
+    f: fn(&mut Game, PlayerId) -> SporzResult<Vec<Event>>,
+) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let target = match args.trimmed().single::<UserId>() {
+        Ok(user_id) => player_id(user_id),
+        Err(_) => {
+            msg.reply(ctx, "mention a player").await?;
+            return Ok(());
+        }
+    };
+    let user_id = msg.author.id;
+    let proposed = update(ctx, guild_id, |table| {
+        check_gm(table, user_id)?;
+        match table.proposal.as_mut() {
+            Some(proposal) => {
+                let events = f(&mut proposal.game, target)?;
+                proposal.events.extend(events);
+                Ok(Some(table.clone()))
+            }
+            None => Ok(None),
+        }
+    })
+    .await;
+    let result = match proposed {
+        Ok(Some(table)) => {
+            show(ctx, &table).await;
+            Ok("the proposed resolution was changed".to_string())
+        }
+        Ok(None) => super::play(ctx, guild_id, |game| f(game, target))
+            .await
+            .map(|_| "done".to_string()),
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Kill a player."]
+#[usage = "@player"]
+async fn kill(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    overrule(ctx, msg, args, Game::strike).await
+}
+
+#[command]
+#[description = "Bring a dead player back to life."]
+#[usage = "@player"]
+async fn revive(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    overrule(ctx, msg, args, Game::revive).await
+}
+
+#[command]
+#[description = "Pause the game, holding the end of the phases and the votes, or resume it."]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        check_gm(table, user_id)?;
+        table.paused = !table.paused;
+        Ok(table.clone())
+    })
+    .await;
+    let result = match result {
+        Ok(table) if table.paused => {
+            announce(
+                ctx,
+                Some(table.channel_id),
+                "⏸️ The game master paused the game.",
+            )
+            .await;
+            Ok("paused".to_string())
+        }
+        Ok(table) => {
+            announce(ctx, Some(table.channel_id), "▶️ The game resumes.").await;
+            Ok("resumed, end the current phase with `gm skip` if its time is up".to_string())
+        }
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Add narration to the proposed resolution, or tell it to the players right away."]
+#[usage = "text"]
+#[example = "A strange noise wakes the crew up."]
+async fn narrate(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let narration = args.rest().trim().to_string();
+    if narration.is_empty() {
+        msg.reply(ctx, "give the text to tell").await?;
+        return Ok(());
+    }
+    let result = update(ctx, guild_id, |table| {
+        check_gm(table, user_id)?;
+        if let Some(proposal) = table.proposal.as_mut() {
+            proposal.narration.push(narration.clone());
+        }
+        Ok(table.clone())
+    })
+    .await;
+    let result = match result {
+        Ok(table) if table.proposal.is_some() => {
+            Ok("the narration will be told with the resolution".to_string())
+        }
+        Ok(table) => {
+            announce(ctx, Some(table.channel_id), format!("📜 {}", narration)).await;
+            Ok("told".to_string())
+        }
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Publish the proposed resolution of the night."]
+async fn confirm(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let confirmed = update(ctx, guild_id, |table| {
+        check_gm(table, user_id)?;
+        let proposal = table.proposal.take().ok_or(TableError::NoProposal)?;
+        table.game = Some(proposal.game.clone());
+        Ok((table.clone(), proposal))
+    })
+    .await;
+    let result = match confirmed {
+        Ok((table, proposal)) => {
+            for narration in &proposal.narration {
+                announce(ctx, Some(table.channel_id), format!("📜 {}", narration)).await;
+            }
+            conclude(ctx, &table, &proposal.events)
+                .await
+                .map(|_| "published".to_string())
+        }
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
@@ -0,0 +1,349 @@
+//! The history of every game: each event of the engine and each vote, kept
+//! once the game is over to replay or export it.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId, UserId};
+
+use super::{describe_cause, describe_info, Table};
+use crate::{
+    db::{self, DbResult},
+    presence::format_time,
+    sporz::{Action, Election, Event, HackTarget, Phase, PlayerId, Role, Side},
+};
+
+const GAMES_TREE: &str = "sporz_games";
+const HISTORY_TREE: &str = "sporz_history";
+/// The longest message Discord accepts.
+pub(super) const MESSAGE_LENGTH: usize = 2000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+pub enum Record {
+    Event {
+        event: Event,
+    },
+    Vote {
+        election: Election,
+        voter: PlayerId,
+        target: PlayerId,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+/// A game as it is archived.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub id: u64,
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub host: UserId,
+    pub players: Vec<UserId>,
+    pub started_at: DateTime<Utc>,
+    /// Unset while the game is played.
+    pub ended_at: Option<DateTime<Utc>>,
+    /// Unset if the game was cancelled.
+    pub winner: Option<Side>,
+}
+
+#[derive(Serialize)]
+struct Export<'a> {
+    #[serde(flatten)]
+    game: &'a GameRecord,
+    history: &'a [Entry],
+}
+
+/// Archives the game of `table`, which just started.
+pub fn begin(db: &sled::Db, table: &Table) -> DbResult<()> {
+    let game = GameRecord {
+        id: table.id,
+        guild_id: table.guild_id,
+        channel_id: table.channel_id,
+        host: table.host,
+        players: table.seats.iter().map(|seat| seat.user_id).collect(),
+        started_at: Utc::now(),
+        ended_at: None,
+        winner: None,
+    };
+    db::insert(&db.open_tree(GAMES_TREE)?, game.id.to_be_bytes(), &game)
+}
+
+pub fn finish(db: &sled::Db, id: u64, winner: Option<Side>) -> DbResult<()> {
+    let tree = db.open_tree(GAMES_TREE)?;
+    if let Some(mut game) = db::get::<GameRecord>(&tree, id.to_be_bytes())? {
+        game.ended_at = Some(Utc::now());
+        game.winner = winner;
+        db::insert(&tree, id.to_be_bytes(), &game)?;
+    }
+    Ok(())
+}
+
+/// Adds `records` to the history of game `id`, after the previous ones.
+pub fn record(db: &sled::Db, id: u64, records: Vec<Record>) -> DbResult<()> {
+    let tree = db.open_tree(HISTORY_TREE)?;
+    let at = Utc::now();
+    for record in records {
+        let mut key = id.to_be_bytes().to_vec();
+        key.extend_from_slice(&db.generate_id()?.to_be_bytes());
+        db::insert(&tree, key, &Entry { at, record })?;
+    }
+    Ok(())
+}
+
+pub fn game(db: &sled::Db, id: u64) -> DbResult<Option<GameRecord>> {
+    db::get(&db.open_tree(GAMES_TREE)?, id.to_be_bytes())
+}
+
+pub fn entries(db: &sled::Db, id: u64) -> DbResult<Vec<Entry>> {
+    db::scan_prefix(&db.open_tree(HISTORY_TREE)?, id.to_be_bytes())
+}
+
+/// The whole game as pretty-printed JSON.
+pub fn export(game: &GameRecord, history: &[Entry]) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&Export { game, history })
+}
+
+fn describe_action(action: &Action) -> String {
+    match action {
+        Action::Hack { role, target } => {
+            let role = match role {
+                HackTarget::Psychologist => Role::Psychologist,
+                HackTarget::Geneticist => Role::Geneticist,
+                HackTarget::ComputerScientist => Role::ComputerScientist,
+            };
+            format!("chose to hack the {} on <@{}>", role, target.0)
+        }
+        _ => format!("chose to {} <@{}>", action.kind(), action.target().0),
+    }
+}
+
+pub(super) fn describe_event(event: &Event) -> String {
+    match event {
+        Event::Assigned {
+            player,
+            role,
+            genome,
+        } => format!(
+            "<@{}> is **{}** with a **{}** genome",
+            player.0, role, genome
+        ),
+        Event::PhaseStarted { phase } => phase.to_string(),
+        Event::ActionSubmitted { player, action } => {
+            format!("<@{}> {}", player.0, describe_action(action))
+        }
+        Event::Paralysed { player } => format!("<@{}> is paralysed", player.0),
+        Event::Mutated { player } => format!("<@{}> is mutated", player.0),
+        Event::MutationResisted { player } => {
+            format!("<@{}> resists the mutation", player.0)
+        }
+        Event::Healed { player } => format!("<@{}> is healed", player.0),
+        Event::HealFailed { player } => format!("<@{}> cannot be healed", player.0),
+        Event::Killed { player, cause } => {
+            format!("<@{}> is {}", player.0, describe_cause(*cause))
+        }
+        Event::Informed { player, info } => {
+            format!("<@{}> learns: {}", player.0, describe_info(info))
+        }
+        Event::CaptainElected { player } => format!("<@{}> is elected captain", player.0),
+        Event::Spared => "nobody is eliminated".to_string(),
+        Event::Revived { player } => format!("<@{}> is revived", player.0),
+        Event::Ended { winner } => format!("the {} win", winner),
+    }
+}
+
+fn describe(record: &Record) -> String {
+    match record {
+        Record::Event { event } => describe_event(event),
+        Record::Vote {
+            election: Election::Captain,
+            voter,
+            target,
+        } => format!("<@{}> votes for <@{}> as captain", voter.0, target.0),
+        Record::Vote {
+            election: Election::Elimination,
+            voter,
+            target,
+        } => format!("<@{}> votes to eliminate <@{}>", voter.0, target.0),
+    }
+}
+
+/// A chronological recap of the game, each line hidden behind a spoiler, in
+/// as few messages as possible.
+pub fn replay(game: &GameRecord, history: &[Entry]) -> Vec<String> {
+    let mut lines = vec![format!(
+        "**Game #{}**, {} players, started {}",
+        game.id,
+        game.players.len(),
+        format_time(game.started_at)
+    )];
+    for entry in history {
+        match &entry.record {
+            Record::Event {
+                event: Event::PhaseStarted { phase },
+            } => lines.push(match phase {
+                Phase::Night(night) => format!("\n🌙 **Night {}**", night),
+                Phase::Day(day) => format!("\n☀️ **Day {}**", day),
+                Phase::Ended(_) => continue,
+            }),
+            // Information may span several lines, each one is hidden.
+            record => lines.extend(describe(record).lines().map(|line| format!("||{}||", line))),
+        }
+    }
+    if game.winner.is_none() {
+        lines.push("\nThe game was cancelled.".to_string());
+    }
+    let mut messages = vec![String::new()];
+    for line in lines {
+        let message = messages.last_mut().expect("There is always a message");
+        if !message.is_empty() && message.len() + line.len() + 1 > MESSAGE_LENGTH {
+            messages.push(line);
+        } else {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(&line);
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game_record(winner: Option<Side>) -> GameRecord {
+        GameRecord {
+            id: 3,
+            guild_id: GuildId(1),
+            channel_id: ChannelId(2),
+            host: UserId(10),
+            players: vec![UserId(10), UserId(11)],
+            started_at: Utc::now(),
+            ended_at: None,
+            winner,
+        }
+    }
+
+    fn entry(record: Record) -> Entry {
+        Entry {
+            at: Utc::now(),
+            record,
+        }
+    }
+
+    fn event(event: Event) -> Entry {
+        entry(Record::Event { event })
+    }
+
+    #[test]
+    fn replays_hide_everything_but_the_phases() {
+        let history = [
+            event(Event::PhaseStarted {
+                phase: Phase::Night(1),
+            }),
+            event(Event::Mutated {
+                player: PlayerId(11),
+            }),
+            event(Event::PhaseStarted {
+                phase: Phase::Day(1),
+            }),
+            entry(Record::Vote {
+                election: Election::Elimination,
+                voter: PlayerId(10),
+                target: PlayerId(11),
+            }),
+            event(Event::PhaseStarted {
+                phase: Phase::Ended(Side::Astronauts),
+            }),
+        ];
+        let messages = replay(&game_record(Some(Side::Astronauts)), &history);
+        assert_eq!(messages.len(), 1);
+        let lines: Vec<&str> = messages[0].lines().collect();
+        assert!(lines[0].starts_with("**Game #3**, 2 players"));
+        assert!(lines.contains(&"🌙 **Night 1**"));
+        assert!(lines.contains(&"||<@11> is mutated||"));
+        assert!(lines.contains(&"||<@10> votes to eliminate <@11>||"));
+        assert!(!messages[0].contains("cancelled"));
+
+        let messages = replay(&game_record(None), &[]);
+        assert!(messages[0].ends_with("The game was cancelled."));
+    }
+
+    #[test]
+    fn long_replays_are_split_between_lines() {
+        let history: Vec<Entry> = (0..500)
+            .map(|player| {
+                event(Event::Healed {
+                    player: PlayerId(player),
+                })
+            })
+            .collect();
+        let messages = replay(&game_record(Some(Side::Mutants)), &history);
+        assert!(messages.len() > 1);
+        assert!(messages
+            .iter()
+            .all(|message| message.len() <= MESSAGE_LENGTH));
+        let lines: usize = messages.iter().map(|message| message.lines().count()).sum();
+        assert_eq!(lines, 501);
+    }
+
+    #[test]
+    fn records_are_kept_in_order() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let game = game_record(None);
+        db::insert(
+            &db.open_tree(GAMES_TREE).unwrap(),
+            game.id.to_be_bytes(),
+            &game,
+        )
+        .unwrap();
+        record(
+            &db,
+            game.id,
+            vec![
+                Record::Event {
+                    event: Event::Spared,
+                },
+                Record::Event {
+                    event: Event::Ended {
+                        winner: Side::Mutants,
+                    },
+                },
+            ],
+        )
+        .unwrap();
+        record(
+            &db,
+            4,
+            vec![Record::Event {
+                event: Event::Spared,
+            }],
+        )
+        .unwrap();
+        finish(&db, game.id, Some(Side::Mutants)).unwrap();
+
+        let game = super::game(&db, 3).unwrap().unwrap();
+        assert_eq!(game.winner, Some(Side::Mutants));
+        assert!(game.ended_at.is_some());
+        let history = entries(&db, 3).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(
+            history[1].record,
+            Record::Event {
+                event: Event::Ended { .. }
+            }
+        ));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&export(&game, &history).unwrap()).unwrap();
+        assert_eq!(json["id"], 3);
+        assert_eq!(json["history"].as_array().unwrap().len(), 2);
+        assert_eq!(json["history"][0]["record"], "event");
+    }
+}
@@ -0,0 +1,1266 @@
+//! Sporz tables: the Discord side of a game, which deals the roles, tells the
+//! players what happens to them and manages the channels of the game.
+
+use rand::thread_rng;
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, UserId},
+    },
+    prelude::TypeMapKey,
+};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    config::Config,
+    db::{self, Database, DbError, DbResult},
+    scheduler,
+    sporz::{
+        bot::{self, Statistics, Strategies},
+        Cause, Composition, Election, Event, Game, Genome, Info, Observation, Phase, PlayerId,
+        Role, Side, SporzError, Variants, MIN_PLAYERS,
+    },
+    vote,
+};
+
+mod channels;
+mod gm;
+mod history;
+mod night;
+mod profile;
+
+pub use gm::GAMEMASTER_GROUP;
+use history::Record;
+pub use profile::{load_profiles, Profiles};
+use profile::{PROFILE_COMMAND, TOP_COMMAND};
+
+const TABLES_TREE: &str = "sporz_tables";
+/// Night prompts list the players in a select menu, which holds at most 25
+/// options.
+const MAX_SEATS: usize = 25;
+/// The name of the built-in compositions.
+const STANDARD_PRESET: &str = "standard";
+/// Games simulated by `sporz simulate` when no count is given.
+const SIMULATED_GAMES: usize = 10;
+const MAX_SIMULATED_GAMES: usize = 1000;
+/// Players of simulated games when neither given nor seated.
+const SIMULATED_PLAYERS: usize = 8;
+const MAX_SIMULATED_PLAYERS: usize = 30;
+
+#[group]
+#[commands(sporz, profile)]
+#[only_in(guilds)]
+struct Sporz;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Seat {
+    pub user_id: UserId,
+    /// Where the player receives their private messages once the game started.
+    pub inbox: Option<ChannelId>,
+}
+
+/// A game of a guild, from its creation until it ends.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Table {
+    pub id: u64,
+    pub guild_id: GuildId,
+    /// Where the game is played during the day.
+    pub channel_id: ChannelId,
+    pub host: UserId,
+    pub seats: Vec<Seat>,
+    pub game: Option<Game>,
+    pub category: Option<ChannelId>,
+    pub mutants_channel: Option<ChannelId>,
+    /// Channels created for the game, deleted when it ends.
+    pub channels: Vec<ChannelId>,
+    /// Allowed and denied permission bits of `@everyone` on the public
+    /// channel before the game started.
+    pub public_overwrite: Option<(u64, u64)>,
+    /// The preset of the configuration to play, the standard compositions
+    /// when unset.
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// The host, when they run the game instead of playing it.
+    #[serde(default)]
+    pub gm: Option<UserId>,
+    #[serde(default)]
+    pub gm_channel: Option<ChannelId>,
+    /// The phases do not end on their own while the game is paused.
+    #[serde(default)]
+    pub paused: bool,
+    /// Onlookers, who see the game as the dead players do.
+    #[serde(default)]
+    pub spectators: Vec<UserId>,
+    #[serde(default)]
+    pub spectators_channel: Option<ChannelId>,
+    /// Spectators forbidden to write in the public channel, with their
+    /// overwrite of the public channel before.
+    #[serde(default)]
+    pub muted: Vec<(UserId, Option<(u64, u64)>)>,
+    /// The resolution of the night, until the game master confirms it.
+    #[serde(default)]
+    pub proposal: Option<gm::Proposal>,
+}
+
+/// The open table of each guild.
+pub struct Tables;
+
+impl TypeMapKey for Tables {
+    type Value = HashMap<GuildId, Table>;
+}
+
+#[derive(Debug, Error)]
+pub enum TableError {
+    #[error("no game in this server, create one with `sporz create`")]
+    NoTable,
+    #[error("a game is already open in <#{0}>")]
+    AlreadyOpen(ChannelId),
+    #[error("you already joined the game")]
+    AlreadyJoined,
+    #[error("you are not in the game")]
+    NotJoined,
+    #[error("only <@{0}> can do this")]
+    NotHost(UserId),
+    #[error("the host cannot leave, cancel the game with `sporz stop` instead")]
+    HostCannotLeave,
+    #[error("the game has already started")]
+    Started,
+    #[error("the game is full, {} players at most", MAX_SEATS)]
+    Full,
+    #[error("the game has not started")]
+    NotStarted,
+    #[error("only the game master <@{0}> can do this")]
+    NotGameMaster(UserId),
+    #[error("the game has no game master, the host can be one with `gm mode`")]
+    NoGameMaster,
+    #[error("the game master cannot play")]
+    GameMasterPlays,
+    #[error("the game is paused")]
+    Paused,
+    #[error("a resolution already waits for the game master")]
+    AlreadyProposed,
+    #[error("no resolution waits for confirmation")]
+    NoProposal,
+    #[error("unknown preset `{0}`, see `sporz preset`")]
+    UnknownPreset(String),
+    #[error("no game #{0} in this server")]
+    UnknownGame(u64),
+    #[error("game #{0} is not over yet")]
+    NotOver(u64),
+    #[error(transparent)]
+    Engine(#[from] SporzError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+pub fn load_tables(db: &sled::Db) -> DbResult<HashMap<GuildId, Table>> {
+    let tables: Vec<Table> = db::scan_prefix(&db.open_tree(TABLES_TREE)?, b"")?;
+    Ok(tables
+        .into_iter()
+        .map(|table| (table.guild_id, table))
+        .collect())
+}
+
+fn save(db: &sled::Db, table: &Table) -> DbResult<()> {
+    db::insert(
+        &db.open_tree(TABLES_TREE)?,
+        table.guild_id.0.to_be_bytes(),
+        table,
+    )
+}
+
+pub fn player_id(user_id: UserId) -> PlayerId {
+    PlayerId(user_id.0)
+}
+
+pub fn user_id(player_id: PlayerId) -> UserId {
+    UserId(player_id.0)
+}
+
+/// Runs `f` on the table of `guild_id` and saves it if `f` succeeds.
+pub async fn update<T>(
+    ctx: &Context,
+    guild_id: GuildId,
+    f: impl FnOnce(&mut Table) -> Result<T, TableError>,
+) -> Result<T, TableError> {
+    let mut data = ctx.data.write().await;
+    let db = data
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.");
+    let table = data
+        .get_mut::<Tables>()
+        .expect("Expected Tables in TypeMap.")
+        .get_mut(&guild_id)
+        .ok_or(TableError::NoTable)?;
+    let value = f(table)?;
+    save(&db, table)?;
+    Ok(value)
+}
+
+pub async fn get(ctx: &Context, guild_id: GuildId) -> Option<Table> {
+    ctx.data
+        .read()
+        .await
+        .get::<Tables>()
+        .expect("Expected Tables in TypeMap.")
+        .get(&guild_id)
+        .cloned()
+}
+
+/// Applies `f` to the game of `guild_id`, then tells the players what
+/// happened and closes the table if the game is over.
+pub async fn play(
+    ctx: &Context,
+    guild_id: GuildId,
+    f: impl FnOnce(&mut Game) -> Result<Vec<Event>, SporzError>,
+) -> Result<(), TableError> {
+    let (table, events) = update(ctx, guild_id, |table| {
+        let game = table.game.as_mut().ok_or(TableError::NotStarted)?;
+        let events = f(game)?;
+        Ok((table.clone(), events))
+    })
+    .await?;
+    gm::mirror(ctx, &table, &events).await;
+    conclude(ctx, &table, &events).await
+}
+
+/// Records and tells `events`, to the spectators too, then closes the table
+/// if the game is over. The spectators are refreshed before, as the table is
+/// gone once closed.
+async fn conclude(ctx: &Context, table: &Table, events: &[Event]) -> Result<(), TableError> {
+    record_events(ctx, table.id, events).await;
+    publish(ctx, table, events).await;
+    let lines = events.iter().map(history::describe_event).collect();
+    announce_lines(ctx, table.spectators_channel, lines).await;
+    refresh_spectators(ctx, table.guild_id).await?;
+    if table.game.as_ref().is_some_and(Game::is_over) {
+        close(ctx, table.guild_id).await?;
+    }
+    Ok(())
+}
+
+/// Updates the permissions of the spectators of the game of `guild_id`.
+async fn refresh_spectators(ctx: &Context, guild_id: GuildId) -> Result<(), TableError> {
+    let mut table = get(ctx, guild_id).await.ok_or(TableError::NoTable)?;
+    channels::sync_spectators(ctx, &mut table).await;
+    update(ctx, guild_id, move |current| {
+        current.muted = table.muted;
+        Ok(())
+    })
+    .await
+}
+
+/// Adds `records` to the history of table `id`. The game goes on if this
+/// fails, its replay will only be incomplete.
+async fn record(ctx: &Context, id: u64, records: Vec<Record>) {
+    let db = db::from_context(ctx).await;
+    if let Err(why) = history::record(&db, id, records) {
+        warn!(table = id, "Could not record the history: {}", why);
+    }
+}
+
+async fn record_events(ctx: &Context, id: u64, events: &[Event]) {
+    let records = events
+        .iter()
+        .map(|event| Record::Event {
+            event: event.clone(),
+        })
+        .collect();
+    record(ctx, id, records).await
+}
+
+/// Records the votes of an election of the game of `guild_id`, as pairs of
+/// voter and target.
+pub async fn record_votes(
+    ctx: &Context,
+    guild_id: GuildId,
+    election: Election,
+    votes: Vec<(PlayerId, PlayerId)>,
+) {
+    if let Some(table) = get(ctx, guild_id).await {
+        let records = votes
+            .into_iter()
+            .map(|(voter, target)| Record::Vote {
+                election,
+                voter,
+                target,
+            })
+            .collect();
+        record(ctx, table.id, records).await
+    }
+}
+
+/// Ends `phase` if the game is still in it and not paused.
+pub async fn advance(ctx: &Context, guild_id: GuildId, phase: Phase) -> Result<(), TableError> {
+    let table = get(ctx, guild_id).await.ok_or(TableError::NoTable)?;
+    if table.paused {
+        return Err(TableError::Paused);
+    }
+    end_phase(ctx, &table, phase).await
+}
+
+pub async fn is_paused(ctx: &Context, guild_id: GuildId) -> bool {
+    get(ctx, guild_id).await.is_some_and(|table| table.paused)
+}
+
+/// Ends `phase` if the game is still in it: resolves the night, or closes the
+/// day without eliminating anybody. With a game master, the resolution of the
+/// night is only proposed to them.
+async fn end_phase(ctx: &Context, table: &Table, phase: Phase) -> Result<(), TableError> {
+    if let (Some(_), Phase::Night(night)) = (table.gm, phase) {
+        return gm::propose(ctx, table.guild_id, night).await;
+    }
+    play(ctx, table.guild_id, |game| {
+        if game.phase != phase {
+            return Err(SporzError::WrongPhase {
+                expected: "phase being ended",
+            });
+        }
+        match game.phase {
+            Phase::Night(_) => game.resolve_night(),
+            Phase::Day(_) => game.eliminate(None),
+            Phase::Ended(_) => Err(SporzError::Ended),
+        }
+    })
+    .await
+}
+
+/// Reminds the players that `night` is about to end, if it is not over.
+pub async fn remind_night(ctx: &Context, guild_id: GuildId, night: u32) {
+    let table = match get(ctx, guild_id).await {
+        Some(table) => table,
+        None => return,
+    };
+    if table
+        .game
+        .is_some_and(|game| game.phase == Phase::Night(night))
+    {
+        let content = format!(
+            "⏳ {} minutes left for the night actions.",
+            scheduler::REMINDER_MINUTES
+        );
+        announce(ctx, Some(table.channel_id), content).await;
+    }
+}
+
+/// Removes the table and the channels of its game.
+pub async fn close(ctx: &Context, guild_id: GuildId) -> Result<(), TableError> {
+    let table = {
+        let mut data = ctx.data.write().await;
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
+        let table = data
+            .get_mut::<Tables>()
+            .expect("Expected Tables in TypeMap.")
+            .remove(&guild_id)
+            .ok_or(TableError::NoTable)?;
+        db.open_tree(TABLES_TREE)
+            .and_then(|tree| tree.remove(guild_id.0.to_be_bytes()))
+            .map_err(DbError::from)?;
+        if let Some(game) = &table.game {
+            let winner = match game.phase {
+                Phase::Ended(winner) => Some(winner),
+                _ => None,
+            };
+            if let Err(why) = history::finish(&db, table.id, winner) {
+                warn!(table = table.id, "Could not archive the game: {}", why);
+            }
+            if let Some(winner) = winner {
+                let profiles = data
+                    .get_mut::<Profiles>()
+                    .expect("Expected Profiles in TypeMap.");
+                if let Err(why) = profile::record(&db, profiles, &table, game, winner) {
+                    warn!(table = table.id, "Could not update the profiles: {}", why);
+                }
+            }
+        }
+        table
+    };
+    vote::cancel_sporz(ctx, guild_id, table.channel_id).await?;
+    channels::cleanup(ctx, &table).await;
+    info!(table = table.id, "Closed the table");
+    Ok(())
+}
+
+pub fn describe_role(role: Role) -> &'static str {
+    match role {
+        Role::Astronaut => "You have no special power: find the mutants and eliminate them.",
+        Role::BaseMutant => {
+            "You are the first mutant. Each night, mutate or kill a player and paralyse another, \
+             until nobody is left to stop the mutants."
+        }
+        Role::Doctor => {
+            "Each night, heal a player from their mutation, or kill one if every doctor agrees. \
+             You cannot act once mutated."
+        }
+        Role::Psychologist => "Each night, learn whether a player is a mutant.",
+        Role::Geneticist => "Each night, learn the genome of a player.",
+        Role::ComputerScientist => "Each night, learn how many mutants are aboard.",
+        Role::Hacker => {
+            "Each night, get the information of the psychologist, the geneticist or the \
+             computer scientist on a target of your choice."
+        }
+        Role::Spy => "Each night, learn everything that happened to a player.",
+        Role::Painter => {
+            "Each night, paint the door of a player and learn whether anyone visited them."
+        }
+        Role::Traitor => "You look like an astronaut, but you win with the mutants.",
+    }
+}
+
+fn describe_genome(genome: Genome) -> &'static str {
+    match genome {
+        Genome::Normal => "you can be mutated and healed",
+        Genome::Host => "you cannot be healed once mutated",
+        Genome::Resistant => "you cannot be mutated",
+    }
+}
+
+fn describe_info(info: &Info) -> String {
+    match info {
+        Info::MutantCount { count } => format!("There are {} mutants aboard.", count),
+        Info::IsMutant { target, mutant } => format!(
+            "<@{}> {} a mutant.",
+            target.0,
+            if *mutant { "is" } else { "is not" }
+        ),
+        Info::Genome { target, genome } => {
+            format!("<@{}> has a **{}** genome.", target.0, genome)
+        }
+        Info::Spied {
+            target,
+            observations,
+        } if observations.is_empty() => format!("Nothing happened to <@{}> tonight.", target.0),
+        Info::Spied {
+            target,
+            observations,
+        } => {
+            let lines: Vec<String> = observations
+                .iter()
+                .map(|observation| match observation {
+                    Observation::Targeted(kind) => format!("- someone chose to {} them", kind),
+                    Observation::Mutated => "- they were mutated".to_string(),
+                    Observation::Healed => "- they were healed".to_string(),
+                    Observation::Paralysed => "- they were paralysed".to_string(),
+                })
+                .collect();
+            format!("Tonight, <@{}>:\n{}", target.0, lines.join("\n"))
+        }
+        Info::Painted { target, visited } => format!(
+            "{} visited <@{}> tonight.",
+            if *visited { "Someone" } else { "Nobody" },
+            target.0
+        ),
+    }
+}
+
+fn describe_cause(cause: Cause) -> &'static str {
+    match cause {
+        Cause::Mutants => "killed by the mutants",
+        Cause::Doctors => "killed by the doctors",
+        Cause::Vote => "eliminated by the crew",
+        Cause::GameMaster => "struck down by the game master",
+    }
+}
+
+/// Sends a private message to a player, in their inbox or by direct message.
+pub async fn tell(ctx: &Context, table: &Table, player: PlayerId, content: impl AsRef<str>) {
+    let user_id = user_id(player);
+    let inbox = table
+        .seats
+        .iter()
+        .find(|seat| seat.user_id == user_id)
+        .and_then(|seat| seat.inbox);
+    let result = match inbox {
+        Some(channel_id) => channel_id.say(&ctx.http, content.as_ref()).await,
+        None => match user_id.create_dm_channel(ctx).await {
+            Ok(channel) => channel.say(&ctx.http, content.as_ref()).await,
+            Err(why) => Err(why),
+        },
+    };
+    if let Err(why) = result {
+        warn!(table = table.id, %user_id, "Could not send a private message: {}", why);
+    }
+}
+
+async fn announce(ctx: &Context, channel_id: Option<ChannelId>, content: impl AsRef<str>) {
+    if let Some(channel_id) = channel_id {
+        if let Err(why) = channel_id.say(&ctx.http, content.as_ref()).await {
+            warn!(%channel_id, "Could not announce an event: {}", why);
+        }
+    }
+}
+
+/// Announces `lines` in as few messages as possible.
+async fn announce_lines(ctx: &Context, channel_id: Option<ChannelId>, lines: Vec<String>) {
+    let mut content = String::new();
+    for line in lines {
+        if !content.is_empty() && content.len() + line.len() + 1 > history::MESSAGE_LENGTH {
+            announce(ctx, channel_id, &content).await;
+            content.clear();
+        }
+        content += &line;
+        content.push('\n');
+    }
+    if !content.is_empty() {
+        announce(ctx, channel_id, &content).await;
+    }
+}
+
+/// Tells the players about `events`: public events in the game channel, the
+/// others to the players concerned only.
+pub async fn publish(ctx: &Context, table: &Table, events: &[Event]) {
+    let game = match &table.game {
+        Some(game) => game,
+        None => return,
+    };
+    let public = Some(table.channel_id);
+    let role_of = |player: PlayerId| game.player(player).map_or(Role::Astronaut, |p| p.role);
+    for event in events {
+        match event {
+            Event::Assigned {
+                player,
+                role,
+                genome,
+            } => {
+                let description = match role {
+                    Role::Traitor => format!(
+                        "You look like an astronaut, but you win with the {}.",
+                        game.variants.traitor_side
+                    ),
+                    _ => describe_role(*role).to_string(),
+                };
+                let content = format!(
+                    "You are **{}** with a **{}** genome: {}.\n{}",
+                    role,
+                    genome,
+                    describe_genome(*genome),
+                    description
+                );
+                tell(ctx, table, *player, content).await;
+            }
+            Event::PhaseStarted {
+                phase: Phase::Night(night),
+            } => {
+                if let Err(why) = channels::lock_public(ctx, table).await {
+                    warn!(
+                        table = table.id,
+                        "Could not lock the public channel: {}", why
+                    );
+                }
+                if let Err(why) = vote::cancel_sporz(ctx, table.guild_id, table.channel_id).await {
+                    warn!(table = table.id, "Could not close the day vote: {}", why);
+                }
+                announce(
+                    ctx,
+                    public,
+                    format!("🌙 Night {} falls on the ship.", night),
+                )
+                .await;
+                tokio::spawn(night::run(ctx.clone(), table.guild_id, *night));
+            }
+            Event::PhaseStarted {
+                phase: Phase::Day(day),
+            } => {
+                if let Err(why) = channels::unlock_public(ctx, table).await {
+                    warn!(
+                        table = table.id,
+                        "Could not unlock the public channel: {}", why
+                    );
+                }
+                announce(ctx, public, format!("☀️ Day {} rises on the ship.", day)).await;
+                vote::open_day(ctx, table.guild_id, *day).await;
+            }
+            Event::Paralysed { player } => {
+                tell(ctx, table, *player, "You were paralysed tonight.").await
+            }
+            Event::Mutated { player } => {
+                tell(
+                    ctx,
+                    table,
+                    *player,
+                    "You have been mutated: you now play with the mutants.",
+                )
+                .await
+            }
+            Event::Healed { player } => {
+                tell(
+                    ctx,
+                    table,
+                    *player,
+                    "You have been healed: you play with the astronauts again.",
+                )
+                .await
+            }
+            Event::Killed { player, cause } => {
+                let content = format!(
+                    "💀 <@{}> was {}, they were **{}**.",
+                    player.0,
+                    describe_cause(*cause),
+                    role_of(*player)
+                );
+                announce(ctx, public, content).await;
+            }
+            Event::Informed { player, info } => {
+                tell(ctx, table, *player, describe_info(info)).await
+            }
+            Event::CaptainElected { player } => {
+                announce(ctx, public, format!("<@{}> is the captain.", player.0)).await
+            }
+            Event::Spared => announce(ctx, public, "Nobody was eliminated.").await,
+            Event::Revived { player } => {
+                let content = format!("✨ <@{}> is back among the living.", player.0);
+                announce(ctx, public, content).await
+            }
+            Event::Ended { winner } => {
+                let mut content = format!("The game is over: victory of the **{}**!\n", winner);
+                for player in &game.players {
+                    content += &format!(
+                        "<@{}>: {}, {} genome{}{}\n",
+                        player.id.0,
+                        player.role,
+                        player.genome,
+                        if player.mutant { ", mutant" } else { "" },
+                        if player.alive { "" } else { ", dead" }
+                    );
+                }
+                announce(ctx, public, content).await;
+            }
+            Event::PhaseStarted { .. }
+            | Event::ActionSubmitted { .. }
+            | Event::MutationResisted { .. }
+            | Event::HealFailed { .. } => {}
+        }
+    }
+    let changed = events.iter().any(|event| {
+        matches!(
+            event,
+            Event::Mutated { .. }
+                | Event::Healed { .. }
+                | Event::Killed { .. }
+                | Event::Revived { .. }
+        )
+    });
+    if changed {
+        channels::sync_mutants(ctx, table, game).await;
+    }
+}
+
+#[command]
+#[sub_commands(
+    create, join, leave, watch, preset, start, next, stop, replay, export, simulate, top
+)]
+#[description = "Play Sporz: create a game, join it and start it."]
+#[usage = "[create|join|leave|watch|preset|start|next|stop|replay|export|simulate|top]"]
+async fn sporz(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let reply = match get(ctx, guild_id).await {
+        Some(table) => {
+            let players: Vec<String> = table
+                .seats
+                .iter()
+                .map(|seat| format!("<@{}>", seat.user_id))
+                .collect();
+            format!(
+                "game #{} in <#{}>, hosted by <@{}>, {} preset, {}\nplayers ({}): {}",
+                table.id,
+                table.channel_id,
+                table.host,
+                table.preset.as_deref().unwrap_or(STANDARD_PRESET),
+                table
+                    .game
+                    .as_ref()
+                    .map_or("waiting for players".to_string(), |game| game
+                        .phase
+                        .to_string()),
+                players.len(),
+                players.join(", ")
+            )
+        }
+        None => "no game in this server, create one with `sporz create`".to_string(),
+    };
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Open a game in this channel, which you host."]
+async fn create(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let mut data = ctx.data.write().await;
+    let db = data
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.");
+    let tables = data
+        .get_mut::<Tables>()
+        .expect("Expected Tables in TypeMap.");
+    if let Some(table) = tables.get(&guild_id) {
+        let reply = TableError::AlreadyOpen(table.channel_id).to_string();
+        drop(data);
+        msg.reply(ctx, reply).await?;
+        return Ok(());
+    }
+    let table = Table {
+        id: db.generate_id()?,
+        guild_id,
+        channel_id: msg.channel_id,
+        host: msg.author.id,
+        seats: vec![Seat {
+            user_id: msg.author.id,
+            inbox: None,
+        }],
+        game: None,
+        category: None,
+        mutants_channel: None,
+        channels: Vec::new(),
+        public_overwrite: None,
+        preset: None,
+        gm: None,
+        gm_channel: None,
+        paused: false,
+        spectators: Vec::new(),
+        spectators_channel: None,
+        muted: Vec::new(),
+        proposal: None,
+    };
+    save(&db, &table)?;
+    info!(table = table.id, %guild_id, "Opened a table");
+    let reply = format!("game #{} created, join it with `sporz join`", table.id);
+    tables.insert(guild_id, table);
+    drop(data);
+    msg.reply(ctx, reply).await?;
+    Ok(())
+}
+
+async fn reply_with(
+    ctx: &Context,
+    msg: &Message,
+    result: Result<String, TableError>,
+) -> CommandResult {
+    match result {
+        Ok(reply) => msg.reply(ctx, reply).await?,
+        Err(TableError::Db(why)) => return Err(why.into()),
+        Err(why) => msg.reply(ctx, why.to_string()).await?,
+    };
+    Ok(())
+}
+
+#[command]
+#[description = "Join the game of this server."]
+async fn join(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        if table.game.is_some() {
+            return Err(TableError::Started);
+        }
+        if table.seats.iter().any(|seat| seat.user_id == user_id) {
+            return Err(TableError::AlreadyJoined);
+        }
+        if table.gm == Some(user_id) {
+            return Err(TableError::GameMasterPlays);
+        }
+        if table.seats.len() >= MAX_SEATS {
+            return Err(TableError::Full);
+        }
+        table.spectators.retain(|spectator| *spectator != user_id);
+        table.seats.push(Seat {
+            user_id,
+            inbox: None,
+        });
+        Ok(format!("you joined, {} players", table.seats.len()))
+    })
+    .await;
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Leave the game of this server before it starts."]
+async fn leave(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        if table.game.is_some() {
+            return Err(TableError::Started);
+        }
+        if table.host == user_id {
+            return Err(TableError::HostCannotLeave);
+        }
+        let count = table.seats.len();
+        table.seats.retain(|seat| seat.user_id != user_id);
+        if table.seats.len() == count {
+            return Err(TableError::NotJoined);
+        }
+        Ok(format!("you left, {} players", table.seats.len()))
+    })
+    .await;
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Watch the game of this server as a spectator, without playing."]
+async fn watch(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        if table.seats.iter().any(|seat| seat.user_id == user_id) {
+            return Err(TableError::AlreadyJoined);
+        }
+        if table.gm == Some(user_id) {
+            return Err(TableError::GameMasterPlays);
+        }
+        if !table.spectators.contains(&user_id) {
+            table.spectators.push(user_id);
+        }
+        Ok(table.game.is_some())
+    })
+    .await;
+    let result = match result {
+        Ok(true) => refresh_spectators(ctx, guild_id).await,
+        Ok(false) => Ok(()),
+        Err(why) => Err(why),
+    }
+    .map(|_| "you watch the game, without writing in its channel".to_string());
+    reply_with(ctx, msg, result).await
+}
+
+fn check_host(table: &Table, user_id: UserId) -> Result<(), TableError> {
+    if table.host == user_id {
+        Ok(())
+    } else {
+        Err(TableError::NotHost(table.host))
+    }
+}
+
+#[command]
+#[description = "List the presets, or choose the one of the game you host before it starts."]
+#[usage = "[preset]"]
+#[example = "interludes"]
+async fn preset(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .cloned()
+            .expect("Expected Config in TypeMap.")
+    };
+    let name = args.rest().trim();
+    if name.is_empty() {
+        let mut lines = vec![format!(
+            "**{}**: roles added as the table grows",
+            STANDARD_PRESET
+        )];
+        for preset in &config.sporz.presets {
+            let sizes: Vec<String> = preset
+                .compositions
+                .iter()
+                .map(|sized| sized.players.to_string())
+                .collect();
+            lines.push(format!(
+                "**{}**: for {} players",
+                preset.name,
+                sizes.join(", ")
+            ));
+        }
+        msg.reply(ctx, lines.join("\n")).await?;
+        return Ok(());
+    }
+    let user_id = msg.author.id;
+    let result = update(ctx, guild_id, |table| {
+        check_host(table, user_id)?;
+        if table.game.is_some() {
+            return Err(TableError::Started);
+        }
+        table.preset = if name == STANDARD_PRESET {
+            None
+        } else if config
+            .sporz
+            .presets
+            .iter()
+            .any(|preset| preset.name == name)
+        {
+            Some(name.to_string())
+        } else {
+            return Err(TableError::UnknownPreset(name.to_string()));
+        };
+        Ok(format!("the game will use the {} preset", name))
+    })
+    .await;
+    reply_with(ctx, msg, result).await
+}
+
+/// The composition and variants of `preset` for `players` players.
+fn rules(
+    config: &Config,
+    preset: Option<&str>,
+    players: usize,
+) -> Result<(Composition, Variants), TableError> {
+    match preset {
+        Some(name) => {
+            let preset = config
+                .sporz
+                .presets
+                .iter()
+                .find(|preset| preset.name == name)
+                .ok_or_else(|| TableError::UnknownPreset(name.to_string()))?;
+            Ok((
+                preset.composition(players)?.clone(),
+                preset.variants.clone(),
+            ))
+        }
+        None => Ok((
+            Composition::standard(players),
+            config.sporz.variants.clone(),
+        )),
+    }
+}
+
+/// Deals the roles and opens the channels of the game, then starts the first
+/// night.
+async fn start_game(ctx: &Context, guild_id: GuildId, host: UserId) -> Result<(), TableError> {
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .cloned()
+            .expect("Expected Config in TypeMap.")
+    };
+    let (mut table, events) = update(ctx, guild_id, |table| {
+        check_host(table, host)?;
+        if table.game.is_some() {
+            return Err(TableError::Started);
+        }
+        if table.seats.len() > MAX_SEATS {
+            return Err(TableError::Full);
+        }
+        let players: Vec<PlayerId> = table
+            .seats
+            .iter()
+            .map(|seat| player_id(seat.user_id))
+            .collect();
+        let (composition, variants) = rules(&config, table.preset.as_deref(), players.len())?;
+        let (game, events) = Game::deal(&players, &composition, &variants, &mut thread_rng())?;
+        table.game = Some(game);
+        Ok((table.clone(), events))
+    })
+    .await?;
+    info!(
+        table = table.id,
+        players = table.seats.len(),
+        "Started a game"
+    );
+
+    if let Err(why) = channels::save_public(ctx, &mut table).await {
+        warn!(
+            table = table.id,
+            "Could not read the public channel: {}", why
+        );
+    }
+    // The game master watches the mutants too.
+    let mutants: Vec<UserId> = table
+        .game
+        .iter()
+        .flat_map(|game| game.players.iter())
+        .filter(|player| player.mutant)
+        .map(|player| user_id(player.id))
+        .chain(table.gm)
+        .collect();
+    match channels::create_private(ctx, &mut table, "mutants", &mutants).await {
+        Ok(channel_id) => table.mutants_channel = Some(channel_id),
+        Err(why) => warn!(
+            table = table.id,
+            "Could not create the mutants channel: {}", why
+        ),
+    }
+    let watchers: Vec<UserId> = table.gm.iter().copied().collect();
+    match channels::create_private(ctx, &mut table, "spectators", &watchers).await {
+        Ok(channel_id) => table.spectators_channel = Some(channel_id),
+        Err(why) => warn!(
+            table = table.id,
+            "Could not create the spectators channel: {}", why
+        ),
+    }
+    if let Some(gm) = table.gm {
+        match channels::create_private(ctx, &mut table, "game-master", &[gm]).await {
+            Ok(channel_id) => table.gm_channel = Some(channel_id),
+            Err(why) => warn!(
+                table = table.id,
+                "Could not create the game master channel: {}", why
+            ),
+        }
+    }
+    // Players who do not accept direct messages get a private channel.
+    for index in 0..table.seats.len() {
+        let user_id = table.seats[index].user_id;
+        let greeting = format!("The game #{} starts, here is your role.", table.id);
+        let inbox = match user_id.create_dm_channel(ctx).await {
+            Ok(channel) => match channel.say(&ctx.http, &greeting).await {
+                Ok(_) => Some(channel.id),
+                Err(_) => None,
+            },
+            Err(_) => None,
+        };
+        let inbox = match inbox {
+            Some(inbox) => Some(inbox),
+            None => {
+                let name = format!("player-{}", index + 1);
+                match channels::create_private(ctx, &mut table, &name, &[user_id]).await {
+                    Ok(channel_id) => {
+                        announce(ctx, Some(channel_id), &greeting).await;
+                        Some(channel_id)
+                    }
+                    Err(why) => {
+                        warn!(table = table.id, %user_id, "Could not create a private channel: {}", why);
+                        None
+                    }
+                }
+            }
+        };
+        table.seats[index].inbox = inbox;
+    }
+    let created = table.clone();
+    update(ctx, guild_id, move |table| {
+        table.seats = created.seats;
+        table.category = created.category;
+        table.mutants_channel = created.mutants_channel;
+        table.channels = created.channels;
+        table.public_overwrite = created.public_overwrite;
+        table.gm_channel = created.gm_channel;
+        table.spectators_channel = created.spectators_channel;
+        Ok(())
+    })
+    .await?;
+    let db = db::from_context(ctx).await;
+    if let Err(why) = history::begin(&db, &table) {
+        warn!(table = table.id, "Could not archive the game: {}", why);
+    }
+    gm::mirror(ctx, &table, &events).await;
+    conclude(ctx, &table, &events).await
+}
+
+#[command]
+#[description = "Deal the roles and start the game you host."]
+async fn start(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let result = start_game(ctx, guild_id, msg.author.id)
+        .await
+        .map(|_| "the game starts, check your private messages".to_string());
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "End the current phase of the game you host."]
+async fn next(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let phase = update(ctx, guild_id, |table| {
+        check_host(table, user_id)?;
+        Ok(table.game.as_ref().map(|game| game.phase))
+    })
+    .await;
+    let result = match phase {
+        Ok(Some(phase)) => advance(ctx, guild_id, phase)
+            .await
+            .map(|_| "next phase".to_string()),
+        Ok(None) => Err(TableError::NotStarted),
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Cancel the game you host and delete its channels."]
+async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let user_id = msg.author.id;
+    let result = match update(ctx, guild_id, |table| check_host(table, user_id)).await {
+        Ok(()) => close(ctx, guild_id)
+            .await
+            .map(|_| "the game was cancelled".to_string()),
+        Err(why) => Err(why),
+    };
+    reply_with(ctx, msg, result).await
+}
+
+/// Reads a game number such as `42` or `#42`.
+fn game_id(args: &mut Args) -> Option<u64> {
+    args.single::<String>()
+        .ok()?
+        .trim_start_matches('#')
+        .parse()
+        .ok()
+}
+
+/// A finished game of `guild_id` and its history.
+async fn finished_game(
+    ctx: &Context,
+    guild_id: GuildId,
+    id: u64,
+) -> Result<(history::GameRecord, Vec<history::Entry>), TableError> {
+    let db = db::from_context(ctx).await;
+    let game = history::game(&db, id)?
+        .filter(|game| game.guild_id == guild_id)
+        .ok_or(TableError::UnknownGame(id))?;
+    if game.ended_at.is_none() {
+        return Err(TableError::NotOver(id));
+    }
+    let entries = history::entries(&db, id)?;
+    Ok((game, entries))
+}
+
+#[command]
+#[description = "Recap everything that happened in a finished game, behind spoilers."]
+#[usage = "game"]
+#[example = "42"]
+async fn replay(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let id = match game_id(&mut args) {
+        Some(id) => id,
+        None => {
+            msg.reply(ctx, "give the number of a game").await?;
+            return Ok(());
+        }
+    };
+    let (game, entries) = match finished_game(ctx, guild_id, id).await {
+        Ok(game) => game,
+        Err(why) => return reply_with(ctx, msg, Err(why)).await,
+    };
+    for content in history::replay(&game, &entries) {
+        msg.channel_id
+            .send_message(&ctx.http, |m| {
+                m.content(content).allowed_mentions(|a| a.empty_parse())
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+#[command]
+#[description = "Export a finished game as JSON."]
+#[usage = "game"]
+#[example = "42"]
+async fn export(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let id = match game_id(&mut args) {
+        Some(id) => id,
+        None => {
+            msg.reply(ctx, "give the number of a game").await?;
+            return Ok(());
+        }
+    };
+    let (game, entries) = match finished_game(ctx, guild_id, id).await {
+        Ok(game) => game,
+        Err(why) => return reply_with(ctx, msg, Err(why)).await,
+    };
+    let json = history::export(&game, &entries)?;
+    let name = format!("sporz-{}.json", id);
+    msg.channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!("game #{}", id))
+                .add_file((json.as_bytes(), name.as_str()))
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "Simulate games between bots, with the preset of the game of this server, for balance statistics."]
+#[usage = "[games[, players]]"]
+#[example = "100, 8"]
+async fn simulate(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild_id = msg.guild_id.ok_or("Must be used in guild")?;
+    let mut counts = Vec::new();
+    while !args.is_empty() {
+        match args.trimmed().single::<usize>() {
+            Ok(count) if counts.len() < 2 => counts.push(count),
+            _ => {
+                msg.reply(ctx, "usage: `sporz simulate [games[, players]]`")
+                    .await?;
+                return Ok(());
+            }
+        }
+    }
+    let games = counts.first().copied().unwrap_or(SIMULATED_GAMES);
+    if games == 0 || games > MAX_SIMULATED_GAMES {
+        msg.reply(
+            ctx,
+            format!(
+                "between 1 and {} games can be simulated",
+                MAX_SIMULATED_GAMES
+            ),
+        )
+        .await?;
+        return Ok(());
+    }
+    let table = get(ctx, guild_id).await;
+    let seated = table
+        .as_ref()
+        .map(|table| table.seats.len())
+        .filter(|seated| *seated >= MIN_PLAYERS);
+    let players = counts
+        .get(1)
+        .copied()
+        .or(seated)
+        .unwrap_or(SIMULATED_PLAYERS);
+    if players > MAX_SIMULATED_PLAYERS {
+        msg.reply(
+            ctx,
+            format!("at most {} players can be simulated", MAX_SIMULATED_PLAYERS),
+        )
+        .await?;
+        return Ok(());
+    }
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<Config>()
+            .cloned()
+            .expect("Expected Config in TypeMap.")
+    };
+    let preset = table.and_then(|table| table.preset);
+    let (composition, variants) = match rules(&config, preset.as_deref(), players) {
+        Ok(rules) => rules,
+        Err(why) => return reply_with(ctx, msg, Err(why)).await,
+    };
+    let statistics = tokio::task::spawn_blocking(move || {
+        let mut rng = thread_rng();
+        let mut statistics = Statistics::default();
+        for _ in 0..games {
+            let simulation = bot::simulate(
+                players,
+                &composition,
+                &variants,
+                Strategies::default(),
+                &mut rng,
+            )?;
+            statistics.add(&simulation);
+        }
+        Ok::<_, SporzError>(statistics)
+    })
+    .await?;
+    let result = statistics
+        .map(|statistics| {
+            format!(
+                "{} games of {} players: the astronauts win {:.0}%, the mutants {:.0}%, \
+                 {} given up, {:.1} nights on average",
+                statistics.games,
+                players,
+                statistics.win_rate(Side::Astronauts),
+                statistics.win_rate(Side::Mutants),
+                statistics.given_up(),
+                statistics.average_nights()
+            )
+        })
+        .map_err(TableError::from);
+    reply_with(ctx, msg, result).await
+}
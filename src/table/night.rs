@@ -0,0 +1,439 @@
+//! Night actions: every role holder gets a prompt with a select menu per
+//! action, in private or in the mutants channel for the mutants. The night is
+//! resolved once every prompt is done or timed out.
+
+use chrono::{DateTime, Utc};
+use futures::{future, future::BoxFuture, StreamExt};
+use serenity::{
+    builder::CreateComponents,
+    client::Context,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+        interactions::{
+            message_component::{ButtonStyle, MessageComponentInteraction},
+            InteractionApplicationCommandCallbackDataFlags, InteractionResponseType,
+        },
+    },
+};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::{player_id, user_id, Table};
+use crate::{
+    config::Config,
+    db::DbResult,
+    presence,
+    scheduler::{self, Job},
+    sporz::{Action, ActionKind, Game, HackTarget, Phase, PlayerId, SporzError},
+};
+
+const DONE: &str = "done";
+const HACK_ROLE: &str = "hack_role";
+/// The option of the hack menu going back to the choice of the role.
+const CHANGE_ROLE: &str = "change_role";
+
+const HACK_TARGETS: [(HackTarget, &str); 3] = [
+    (HackTarget::Psychologist, "psychologist"),
+    (HackTarget::Geneticist, "geneticist"),
+    (HackTarget::ComputerScientist, "computer_scientist"),
+];
+
+/// A prompt to send: to a single player, or to the mutants channel where any
+/// mutant may answer.
+struct Prompt {
+    channel_id: ChannelId,
+    player: Option<PlayerId>,
+    kinds: Vec<ActionKind>,
+}
+
+/// What the menus of the prompts list.
+struct Choices {
+    /// The living players, with their name.
+    targets: Vec<(PlayerId, String)>,
+    /// The roles the hacker may hack.
+    hack_roles: Vec<HackTarget>,
+}
+
+impl Choices {
+    /// The role hacked before any is chosen, when there is no choice.
+    fn default_hack_role(&self) -> Option<HackTarget> {
+        match self.hack_roles.as_slice() {
+            [role] => Some(*role),
+            _ => None,
+        }
+    }
+}
+
+fn hack_role_name(role: HackTarget) -> &'static str {
+    HACK_TARGETS
+        .iter()
+        .find(|(target, _)| *target == role)
+        .map_or("psychologist", |(_, name)| name)
+}
+
+fn custom_id(kind: ActionKind) -> &'static str {
+    match kind {
+        ActionKind::Mutate => "mutate",
+        ActionKind::MutantKill => "mutant_kill",
+        ActionKind::Paralyse => "paralyse",
+        ActionKind::Heal => "heal",
+        ActionKind::DoctorKill => "doctor_kill",
+        ActionKind::Inspect => "inspect",
+        ActionKind::Sequence => "sequence",
+        ActionKind::Hack => "hack",
+        ActionKind::Spy => "spy",
+        ActionKind::Paint => "paint",
+    }
+}
+
+fn prompts(table: &Table, game: &Game) -> Vec<Prompt> {
+    let mut prompts = Vec::new();
+    let mutants: Vec<PlayerId> = game
+        .alive()
+        .filter(|player| player.mutant)
+        .map(|player| player.id)
+        .collect();
+    if let (Some(channel_id), Some(mutant)) = (table.mutants_channel, mutants.first()) {
+        prompts.push(Prompt {
+            channel_id,
+            player: None,
+            kinds: game
+                .available_actions(*mutant)
+                .into_iter()
+                .filter(|kind| kind.is_collective())
+                .collect(),
+        });
+    }
+    for seat in &table.seats {
+        let player = player_id(seat.user_id);
+        let kinds: Vec<ActionKind> = game
+            .available_actions(player)
+            .into_iter()
+            .filter(|kind| !kind.is_collective() || table.mutants_channel.is_none())
+            .collect();
+        if let (false, Some(channel_id)) = (kinds.is_empty(), seat.inbox) {
+            prompts.push(Prompt {
+                channel_id,
+                player: Some(player),
+                kinds,
+            });
+        }
+    }
+    prompts
+}
+
+/// The menus of `prompt` and its done button. A message holds at most 5 rows,
+/// so the hack row lists the roles until `hack_role` is chosen, then the
+/// players.
+fn components<'a>(
+    c: &'a mut CreateComponents,
+    prompt: &Prompt,
+    choices: &Choices,
+    hack_role: Option<HackTarget>,
+) -> &'a mut CreateComponents {
+    for kind in &prompt.kinds {
+        if let (ActionKind::Hack, None) = (kind, hack_role) {
+            c.create_action_row(|row| {
+                row.create_select_menu(|menu| {
+                    menu.custom_id(HACK_ROLE)
+                        .placeholder("Role to hack")
+                        .options(|options| {
+                            for role in &choices.hack_roles {
+                                let name = hack_role_name(*role);
+                                options.create_option(|o| o.label(name).value(name));
+                            }
+                            options
+                        })
+                })
+            });
+            continue;
+        }
+        let placeholder = match (kind, hack_role) {
+            (ActionKind::Hack, Some(role)) => {
+                format!(
+                    "Player to hack as {}",
+                    hack_role_name(role).replace('_', " ")
+                )
+            }
+            _ => format!("Player to {}", kind),
+        };
+        c.create_action_row(|row| {
+            row.create_select_menu(|menu| {
+                menu.custom_id(custom_id(*kind))
+                    .placeholder(placeholder)
+                    .options(|options| {
+                        for (id, name) in &choices.targets {
+                            if prompt.player == Some(*id) && *kind != ActionKind::Heal {
+                                continue;
+                            }
+                            options.create_option(|o| o.label(name).value(id.0.to_string()));
+                        }
+                        if *kind == ActionKind::Hack && choices.hack_roles.len() > 1 {
+                            options
+                                .create_option(|o| o.label("Hack another role").value(CHANGE_ROLE));
+                        }
+                        options
+                    })
+            })
+        });
+    }
+    c.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(DONE)
+                .label("Done")
+                .style(ButtonStyle::Success)
+        })
+    })
+}
+
+async fn send_prompt(
+    ctx: &Context,
+    night: u32,
+    prompt: &Prompt,
+    choices: &Choices,
+) -> serenity::Result<Message> {
+    prompt
+        .channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!(
+                "Night {}: choose your actions, then press done.",
+                night
+            ))
+            .components(|c| components(c, prompt, choices, choices.default_hack_role()))
+        })
+        .await
+}
+
+/// Shows the hack row for `hack_role` in place of the previous one.
+async fn update_prompt(
+    ctx: &Context,
+    interaction: &MessageComponentInteraction,
+    prompt: &Prompt,
+    choices: &Choices,
+    hack_role: Option<HackTarget>,
+) {
+    let result = interaction
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|data| {
+                    data.components(|c| components(c, prompt, choices, hack_role))
+                })
+        })
+        .await;
+    if let Err(why) = result {
+        warn!("Could not update a night prompt: {}", why);
+    }
+}
+
+async fn acknowledge(ctx: &Context, interaction: &MessageComponentInteraction, content: String) {
+    let result = interaction
+        .create_interaction_response(&ctx.http, |response| {
+            response
+                .kind(InteractionResponseType::ChannelMessageWithSource)
+                .interaction_response_data(|data| {
+                    data.content(content)
+                        .flags(InteractionApplicationCommandCallbackDataFlags::EPHEMERAL)
+                })
+        })
+        .await;
+    if let Err(why) = result {
+        warn!("Could not acknowledge a night action: {}", why);
+    }
+}
+
+/// Submits `action` for `actor` if the night is still `night`.
+async fn submit(
+    ctx: &Context,
+    guild_id: GuildId,
+    night: u32,
+    actor: PlayerId,
+    action: Action,
+) -> String {
+    let result = super::play(ctx, guild_id, |game| {
+        if game.phase != Phase::Night(night) {
+            return Err(SporzError::WrongPhase { expected: "night" });
+        }
+        game.submit(actor, action)
+    })
+    .await;
+    match result {
+        Ok(()) => format!("You chose to {} <@{}>.", action.kind(), action.target().0),
+        Err(why) => why.to_string(),
+    }
+}
+
+/// Whether `actor` may answer the prompt shared by the mutants.
+fn is_living_mutant(game: &Game, actor: PlayerId) -> bool {
+    game.player(actor)
+        .is_ok_and(|player| player.alive && player.mutant)
+}
+
+/// Collects the answers to a prompt until done is pressed or time runs out.
+async fn collect(
+    ctx: &Context,
+    guild_id: GuildId,
+    night: u32,
+    message: Message,
+    prompt: &Prompt,
+    choices: &Choices,
+    timeout: Duration,
+) {
+    let mut hack_role = choices.default_hack_role();
+    let mut interactions = message
+        .await_component_interactions(ctx)
+        .timeout(timeout)
+        .await;
+    while let Some(interaction) = interactions.next().await {
+        let actor = player_id(interaction.user.id);
+        let allowed = match prompt.player {
+            Some(player) => player == actor,
+            None => super::get(ctx, guild_id)
+                .await
+                .and_then(|table| table.game)
+                .is_some_and(|game| is_living_mutant(&game, actor)),
+        };
+        if !allowed {
+            acknowledge(ctx, &interaction, "This prompt is not yours.".to_string()).await;
+            continue;
+        }
+        let custom_id = interaction.data.custom_id.as_str();
+        if custom_id == DONE {
+            acknowledge(ctx, &interaction, "Your choices are locked in.".to_string()).await;
+            break;
+        }
+        let value = interaction.data.values.first().cloned().unwrap_or_default();
+        if custom_id == HACK_ROLE {
+            hack_role = HACK_TARGETS
+                .iter()
+                .find(|(_, name)| *name == value)
+                .map(|(role, _)| *role);
+            update_prompt(ctx, &interaction, prompt, choices, hack_role).await;
+            continue;
+        }
+        let kind = ActionKind::ALL
+            .iter()
+            .copied()
+            .find(|kind| self::custom_id(*kind) == custom_id);
+        if kind == Some(ActionKind::Hack) && value == CHANGE_ROLE {
+            hack_role = None;
+            update_prompt(ctx, &interaction, prompt, choices, hack_role).await;
+            continue;
+        }
+        let reply = match (kind, value.parse().map(PlayerId)) {
+            (Some(ActionKind::Hack), Ok(target)) => {
+                let action = Action::Hack {
+                    role: hack_role.unwrap_or(HackTarget::Psychologist),
+                    target,
+                };
+                submit(ctx, guild_id, night, actor, action).await
+            }
+            (Some(kind), Ok(target)) => {
+                submit(ctx, guild_id, night, actor, Action::new(kind, target)).await
+            }
+            _ => "Unknown choice.".to_string(),
+        };
+        acknowledge(ctx, &interaction, reply).await;
+    }
+}
+
+async fn schedule_end(
+    ctx: &Context,
+    guild_id: GuildId,
+    night: u32,
+    deadline: DateTime<Utc>,
+) -> DbResult<()> {
+    scheduler::schedule(ctx, deadline, Job::EndNight { guild_id, night }).await?;
+    scheduler::schedule_reminder(ctx, deadline, Job::NightReminder { guild_id, night }).await
+}
+
+/// Prompts the night actions of `night`, then resolves the night once every
+/// prompt is answered or timed out.
+///
+/// The end of the night is also scheduled, to resolve it at its deadline with
+/// the actions submitted so far if the bot restarts meanwhile.
+///
+/// The future is boxed as resolving the night may start the next one, which
+/// spawns this function again.
+pub fn run(ctx: Context, guild_id: GuildId, night: u32) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        let table = match super::get(&ctx, guild_id).await {
+            Some(table) => table,
+            None => return,
+        };
+        let game = match &table.game {
+            Some(game) if game.phase == Phase::Night(night) => game,
+            _ => return,
+        };
+        let timeout = {
+            let data = ctx.data.read().await;
+            let config = data.get::<Config>().expect("Expected Config in TypeMap.");
+            Duration::from_secs(config.sporz.night_seconds)
+        };
+        let deadline = Utc::now() + chrono::Duration::seconds(timeout.as_secs() as i64);
+        if let Err(why) = schedule_end(&ctx, guild_id, night, deadline).await {
+            warn!(
+                table = table.id,
+                night, "Could not schedule the end of the night: {}", why
+            );
+        }
+        let mut targets = Vec::new();
+        for player in game.alive() {
+            let name = presence::display_name(&ctx, table.guild_id, user_id(player.id)).await;
+            targets.push((player.id, name));
+        }
+        let choices = Choices {
+            targets,
+            hack_roles: HACK_TARGETS
+                .iter()
+                .map(|(role, _)| *role)
+                .filter(|role| game.variants.hack_targets.contains(role))
+                .collect(),
+        };
+        let prompts = prompts(&table, game);
+        let mut collectors = Vec::new();
+        for prompt in &prompts {
+            match send_prompt(&ctx, night, prompt, &choices).await {
+                Ok(message) => collectors.push(collect(
+                    &ctx, guild_id, night, message, prompt, &choices, timeout,
+                )),
+                Err(why) => warn!(table = table.id, "Could not send a night prompt: {}", why),
+            }
+        }
+        future::join_all(collectors).await;
+        info!(table = table.id, night, "Night actions collected");
+        if let Err(why) = super::advance(&ctx, guild_id, Phase::Night(night)).await {
+            warn!(
+                table = table.id,
+                night, "Could not resolve the night: {}", why
+            );
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sporz::{Genome, Role, Variants};
+
+    #[test]
+    fn only_living_mutants_answer_the_mutants_prompt() {
+        let (mut game, _) = Game::new(
+            &[
+                (PlayerId(1), Role::BaseMutant, Genome::Host),
+                (PlayerId(2), Role::BaseMutant, Genome::Host),
+                (PlayerId(3), Role::Doctor, Genome::Normal),
+            ],
+            Variants::default(),
+        )
+        .unwrap();
+        game.players[1].alive = false;
+        assert!(is_living_mutant(&game, PlayerId(1)));
+        assert!(!is_living_mutant(&game, PlayerId(2)));
+        assert!(!is_living_mutant(&game, PlayerId(3)));
+        // Spectators and the game master are not players.
+        assert!(!is_living_mutant(&game, PlayerId(4)));
+    }
+}
@@ -0,0 +1,332 @@
+//! The record of each player over the games they finished, kept across
+//! sessions.
+
+use serde::{Deserialize, Serialize};
+use serenity::{
+    client::Context,
+    framework::standard::{macros::command, Args, CommandResult},
+    model::{channel::Message, id::UserId},
+    prelude::TypeMapKey,
+};
+use std::collections::{BTreeMap, HashMap};
+
+use super::{
+    history::{self, Record},
+    player_id, Table,
+};
+use crate::{
+    db::{self, DbResult},
+    sporz::{Event, Game, Role, Side},
+};
+
+const PROFILES_TREE: &str = "sporz_profiles";
+const TOP_SIZE: usize = 10;
+
+/// The profile of each player, loaded from the database on startup.
+pub struct Profiles;
+
+impl TypeMapKey for Profiles {
+    type Value = HashMap<UserId, Profile>;
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Profile {
+    pub user_id: UserId,
+    pub games: u32,
+    /// Wins by the side the player ended the game on.
+    pub astronaut_wins: u32,
+    pub mutant_wins: u32,
+    /// Games played with each role.
+    pub roles: BTreeMap<Role, u32>,
+    pub mutated: u32,
+    pub killed: u32,
+}
+
+impl Profile {
+    fn new(user_id: UserId) -> Self {
+        Profile {
+            user_id,
+            games: 0,
+            astronaut_wins: 0,
+            mutant_wins: 0,
+            roles: BTreeMap::new(),
+            mutated: 0,
+            killed: 0,
+        }
+    }
+
+    pub fn wins(&self) -> u32 {
+        self.astronaut_wins + self.mutant_wins
+    }
+
+    /// The share of the games won, in percent.
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        self.wins() as f64 * 100.0 / self.games as f64
+    }
+}
+
+pub fn load_profiles(db: &sled::Db) -> DbResult<HashMap<UserId, Profile>> {
+    let profiles: Vec<Profile> = db::scan_prefix(&db.open_tree(PROFILES_TREE)?, b"")?;
+    Ok(profiles
+        .into_iter()
+        .map(|profile| (profile.user_id, profile))
+        .collect())
+}
+
+/// Adds the game of `table`, won by `winner`, to the profiles of its players.
+pub fn record(
+    db: &sled::Db,
+    profiles: &mut HashMap<UserId, Profile>,
+    table: &Table,
+    game: &Game,
+    winner: Side,
+) -> DbResult<()> {
+    let entries = history::entries(db, table.id)?;
+    let tree = db.open_tree(PROFILES_TREE)?;
+    for seat in &table.seats {
+        let id = player_id(seat.user_id);
+        let player = match game.player(id) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        let profile = profiles
+            .entry(seat.user_id)
+            .or_insert_with(|| Profile::new(seat.user_id));
+        profile.games += 1;
+        match game.side(player) {
+            side if side != winner => {}
+            Side::Astronauts => profile.astronaut_wins += 1,
+            Side::Mutants => profile.mutant_wins += 1,
+        }
+        *profile.roles.entry(player.role).or_default() += 1;
+        for entry in &entries {
+            match entry.record {
+                Record::Event {
+                    event: Event::Mutated { player },
+                } if player == id => profile.mutated += 1,
+                Record::Event {
+                    event: Event::Killed { player, .. },
+                } if player == id => profile.killed += 1,
+                _ => {}
+            }
+        }
+        db::insert(&tree, seat.user_id.0.to_be_bytes(), profile)?;
+    }
+    Ok(())
+}
+
+/// Sorts `profiles` by decreasing wins, fewer games for as many wins ranking
+/// higher.
+fn rank(profiles: &mut [Profile]) {
+    profiles.sort_by(|a, b| {
+        b.wins()
+            .cmp(&a.wins())
+            .then_with(|| a.games.cmp(&b.games))
+            .then_with(|| a.user_id.cmp(&b.user_id))
+    });
+}
+
+#[command]
+#[description = "Show the Sporz record of a player, yours by default."]
+#[usage = "[@user]"]
+async fn profile(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let user_id = args.single::<UserId>().unwrap_or(msg.author.id);
+    let profile = {
+        let data = ctx.data.read().await;
+        data.get::<Profiles>()
+            .expect("Expected Profiles in TypeMap.")
+            .get(&user_id)
+            .cloned()
+    };
+    let profile = match profile {
+        Some(profile) => profile,
+        None => {
+            msg.channel_id
+                .send_message(ctx, |m| {
+                    m.content(format!("<@{}> has not finished a Sporz game yet", user_id))
+                        .allowed_mentions(|a| a.empty_parse())
+                })
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut roles: Vec<(Role, u32)> = profile
+        .roles
+        .iter()
+        .map(|(role, count)| (*role, *count))
+        .collect();
+    roles.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let roles: Vec<String> = roles
+        .iter()
+        .map(|(role, count)| format!("{} — {}", role, count))
+        .collect();
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.embed(|e| {
+                e.title("Sporz record")
+                    .description(format!("<@{}>", user_id))
+                    .field("Games", profile.games, true)
+                    .field(
+                        "Wins",
+                        format!("{} ({:.0}%)", profile.wins(), profile.win_rate()),
+                        true,
+                    )
+                    .field(
+                        "Wins by side",
+                        format!(
+                            "astronauts — {}\nmutants — {}",
+                            profile.astronaut_wins, profile.mutant_wins
+                        ),
+                        true,
+                    )
+                    .field("Mutated", profile.mutated, true)
+                    .field("Killed", profile.killed, true)
+                    .field("Roles", roles.join("\n"), false)
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+#[command]
+#[description = "The players with the most Sporz wins."]
+async fn top(ctx: &Context, msg: &Message) -> CommandResult {
+    let mut profiles: Vec<Profile> = {
+        let data = ctx.data.read().await;
+        data.get::<Profiles>()
+            .expect("Expected Profiles in TypeMap.")
+            .values()
+            .cloned()
+            .collect()
+    };
+    rank(&mut profiles);
+    let lines: Vec<String> = profiles
+        .iter()
+        .take(TOP_SIZE)
+        .enumerate()
+        .map(|(rank, profile)| {
+            format!(
+                "{}. <@{}> — {} wins in {} games",
+                rank + 1,
+                profile.user_id,
+                profile.wins(),
+                profile.games
+            )
+        })
+        .collect();
+    let content = if lines.is_empty() {
+        "no Sporz game finished yet".to_string()
+    } else {
+        lines.join("\n")
+    };
+    msg.channel_id
+        .send_message(ctx, |m| {
+            m.content(content).allowed_mentions(|a| a.empty_parse())
+        })
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sporz::{Cause, Genome, PlayerId, Variants};
+
+    fn table(id: u64, users: &[u64]) -> Table {
+        let seats: Vec<_> = users
+            .iter()
+            .map(|user| serde_json::json!({ "user_id": user, "inbox": null }))
+            .collect();
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "guild_id": 1,
+            "channel_id": 1,
+            "host": users[0],
+            "seats": seats,
+            "game": null,
+            "category": null,
+            "mutants_channel": null,
+            "channels": [],
+            "public_overwrite": null,
+        }))
+        .unwrap()
+    }
+
+    fn profile(user: u64, games: u32, wins: u32) -> Profile {
+        Profile {
+            games,
+            astronaut_wins: wins,
+            ..Profile::new(UserId(user))
+        }
+    }
+
+    #[test]
+    fn games_are_added_to_the_profiles() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let (mut game, _) = Game::new(
+            &[
+                (PlayerId(1), Role::BaseMutant, Genome::Host),
+                (PlayerId(2), Role::Doctor, Genome::Normal),
+                (PlayerId(3), Role::Astronaut, Genome::Normal),
+            ],
+            Variants::default(),
+        )
+        .unwrap();
+        game.players[2].mutant = true;
+        let table = table(5, &[1, 2, 3]);
+        history::record(
+            &db,
+            table.id,
+            vec![
+                Record::Event {
+                    event: Event::Mutated {
+                        player: PlayerId(3),
+                    },
+                },
+                Record::Event {
+                    event: Event::Killed {
+                        player: PlayerId(2),
+                        cause: Cause::Mutants,
+                    },
+                },
+            ],
+        )
+        .unwrap();
+        let mut profiles = HashMap::new();
+        record(&db, &mut profiles, &table, &game, Side::Mutants).unwrap();
+        record(&db, &mut profiles, &table, &game, Side::Mutants).unwrap();
+
+        let mutant = &profiles[&UserId(3)];
+        assert_eq!(
+            (mutant.games, mutant.mutant_wins, mutant.mutated),
+            (2, 2, 2)
+        );
+        assert_eq!(mutant.roles[&Role::Astronaut], 2);
+        let doctor = &profiles[&UserId(2)];
+        assert_eq!((doctor.wins(), doctor.killed), (0, 2));
+        assert_eq!(doctor.win_rate(), 0.0);
+        assert_eq!(profiles[&UserId(1)].win_rate(), 100.0);
+
+        let loaded = load_profiles(&db).unwrap();
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[&UserId(3)].mutated, 2);
+    }
+
+    #[test]
+    fn fewer_games_rank_higher_for_as_many_wins() {
+        let mut profiles = vec![
+            profile(1, 10, 3),
+            profile(2, 4, 3),
+            profile(3, 5, 5),
+            profile(4, 4, 3),
+            profile(5, 0, 0),
+        ];
+        rank(&mut profiles);
+        let order: Vec<u64> = profiles.iter().map(|profile| profile.user_id.0).collect();
+        assert_eq!(order, vec![3, 2, 4, 1, 5]);
+        assert_eq!(profiles[4].win_rate(), 0.0);
+    }
+}
@@ -0,0 +1,937 @@
+//! Votes and polls: one open poll per channel, with a live tally, a rule to
+//! decide the winner, a tie-breaking policy and a closing time. Sporz uses it
+//! to elect the captain and eliminate a player each day.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::{seq::SliceRandom, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::CreateEmbed,
+    client::Context,
+    framework::standard::{
+        macros::{command, group},
+        Args, CommandResult,
+    },
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId, MessageId, UserId},
+    },
+    prelude::TypeMapKey,
+};
+use std::collections::HashMap;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::{
+    db::{self, Database, DbError, DbResult},
+    presence::format_time,
+    scheduler::{self, Job},
+    sporz::{Election, Phase, PlayerId},
+    table,
+};
+
+const POLLS_TREE: &str = "polls";
+const DEFAULT_POLL_MINUTES: i64 = 60;
+/// Each choice is a field of the tally, Discord refuses embeds with more.
+const MAX_CHOICES: usize = 25;
+/// How often a vote held by a paused game checks whether it resumed.
+const PAUSE_CHECK_MINUTES: i64 = 1;
+
+#[group]
+#[commands(vote, unvote, poll)]
+#[only_in(guilds)]
+struct Voting;
+
+/// How the winner of a poll is decided.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rule {
+    /// The choice with the most votes wins.
+    Plurality,
+    /// The winner needs more than half of the votes cast.
+    Majority,
+}
+
+/// What happens when several choices have the most votes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TieBreak {
+    /// Nobody wins.
+    None,
+    /// The choice of the captain wins, if it is among the tied ones.
+    Captain,
+    Random,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Choice {
+    pub label: String,
+    /// The user this choice designates, for votes against players.
+    pub user_id: Option<UserId>,
+}
+
+/// What to do with the result of a poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "purpose", rename_all = "snake_case")]
+pub enum Purpose {
+    General,
+    SporzCaptain { guild_id: GuildId, day: u32 },
+    SporzElimination { guild_id: GuildId, day: u32 },
+}
+
+impl Purpose {
+    /// The guild of the Sporz game the poll belongs to.
+    pub fn guild_id(self) -> Option<GuildId> {
+        match self {
+            Purpose::General => None,
+            Purpose::SporzCaptain { guild_id, .. } | Purpose::SporzElimination { guild_id, .. } => {
+                Some(guild_id)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Poll {
+    pub id: u64,
+    pub channel_id: ChannelId,
+    /// The message showing the tally.
+    pub message_id: Option<MessageId>,
+    pub author: Option<UserId>,
+    pub question: String,
+    pub choices: Vec<Choice>,
+    /// When set, only these users may vote.
+    pub voters: Option<Vec<UserId>>,
+    /// Their vote counts double.
+    pub captain: Option<UserId>,
+    pub rule: Rule,
+    pub tie_break: TieBreak,
+    /// The choice of each voter.
+    pub votes: Vec<(UserId, usize)>,
+    pub closes_at: DateTime<Utc>,
+    pub purpose: Purpose,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Winner(usize),
+    Tie(Vec<usize>),
+    NoMajority,
+    NoVotes,
+}
+
+#[derive(Debug, Error)]
+pub enum VoteError {
+    #[error("no poll is open in this channel")]
+    NoPoll,
+    #[error("a poll is already open in this channel")]
+    AlreadyOpen,
+    #[error("you cannot vote in this poll")]
+    NotVoter,
+    #[error("`{0}` is not one of the choices")]
+    UnknownChoice(String),
+    #[error("you have not voted")]
+    NotVoted,
+    #[error("a poll needs a question and at least two choices")]
+    TooFewChoices,
+    #[error("a poll can have at most {} choices", MAX_CHOICES)]
+    TooManyChoices,
+    #[error("only the author of the poll can close it")]
+    NotAuthor,
+    #[error(transparent)]
+    Db(#[from] DbError),
+}
+
+/// The open poll of each channel.
+pub struct Polls;
+
+impl TypeMapKey for Polls {
+    type Value = HashMap<ChannelId, Poll>;
+}
+
+impl Poll {
+    pub fn weight(&self, voter: UserId) -> u32 {
+        if self.captain == Some(voter) {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Finds a choice by the user it designates, its number or its label.
+    pub fn find_choice(&self, input: &str) -> Result<usize, VoteError> {
+        let input = input.trim();
+        let user_id = input
+            .trim_start_matches("<@")
+            .trim_start_matches('!')
+            .trim_end_matches('>')
+            .parse::<u64>()
+            .ok()
+            .map(UserId);
+        let index = self.choices.iter().position(|choice| {
+            (user_id.is_some() && choice.user_id == user_id)
+                || choice.label.eq_ignore_ascii_case(input)
+        });
+        match (index, input.parse::<usize>()) {
+            (Some(index), _) => Ok(index),
+            (None, Ok(number)) if number >= 1 && number <= self.choices.len() => Ok(number - 1),
+            _ => Err(VoteError::UnknownChoice(input.to_string())),
+        }
+    }
+
+    pub fn vote(&mut self, voter: UserId, choice: usize) -> Result<(), VoteError> {
+        if let Some(voters) = &self.voters {
+            if !voters.contains(&voter) {
+                return Err(VoteError::NotVoter);
+            }
+        }
+        self.votes.retain(|(user_id, _)| *user_id != voter);
+        self.votes.push((voter, choice));
+        Ok(())
+    }
+
+    pub fn unvote(&mut self, voter: UserId) -> Result<(), VoteError> {
+        let count = self.votes.len();
+        self.votes.retain(|(user_id, _)| *user_id != voter);
+        if self.votes.len() == count {
+            return Err(VoteError::NotVoted);
+        }
+        Ok(())
+    }
+
+    /// Weighted votes of each choice.
+    pub fn tally(&self) -> Vec<u32> {
+        let mut tally = vec![0; self.choices.len()];
+        for (voter, choice) in &self.votes {
+            tally[*choice] += self.weight(*voter);
+        }
+        tally
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        let tally = self.tally();
+        let total: u32 = tally.iter().sum();
+        let best = tally.iter().copied().max().unwrap_or(0);
+        if best == 0 {
+            return Outcome::NoVotes;
+        }
+        let leaders: Vec<usize> = (0..tally.len()).filter(|i| tally[*i] == best).collect();
+        if self.rule == Rule::Majority && best * 2 <= total {
+            return Outcome::NoMajority;
+        }
+        match leaders.as_slice() {
+            [winner] => Outcome::Winner(*winner),
+            _ => Outcome::Tie(leaders),
+        }
+    }
+
+    /// The winning choice, after breaking ties.
+    pub fn decide<R: Rng>(&self, rng: &mut R) -> Option<usize> {
+        match self.outcome() {
+            Outcome::Winner(winner) => Some(winner),
+            Outcome::Tie(leaders) => match self.tie_break {
+                TieBreak::None => None,
+                TieBreak::Captain => self
+                    .votes
+                    .iter()
+                    .find(|(voter, _)| Some(*voter) == self.captain)
+                    .map(|(_, choice)| *choice)
+                    .filter(|choice| leaders.contains(choice)),
+                TieBreak::Random => leaders.choose(rng).copied(),
+            },
+            Outcome::NoMajority | Outcome::NoVotes => None,
+        }
+    }
+
+    fn embed<'a>(&self, e: &'a mut CreateEmbed) -> &'a mut CreateEmbed {
+        let tally = self.tally();
+        e.title(&self.question);
+        for (index, choice) in self.choices.iter().enumerate() {
+            let voters: Vec<String> = self
+                .votes
+                .iter()
+                .filter(|(_, voted)| *voted == index)
+                .map(|(voter, _)| format!("<@{}>", voter))
+                .collect();
+            e.field(
+                format!("{}. {} — {}", index + 1, choice.label, tally[index]),
+                if voters.is_empty() {
+                    "no votes".to_string()
+                } else {
+                    voters.join(", ")
+                },
+                false,
+            );
+        }
+        let rule = match self.rule {
+            Rule::Plurality => "most votes wins",
+            Rule::Majority => "absolute majority needed",
+        };
+        let captain = match self.captain {
+            Some(captain) => format!(", <@{}> votes twice", captain),
+            None => String::new(),
+        };
+        e.description(format!(
+            "Vote with `vote choice`, {}{}. Closes {}.",
+            rule,
+            captain,
+            format_time(self.closes_at)
+        ))
+    }
+}
+
+pub fn load_polls(db: &sled::Db) -> DbResult<HashMap<ChannelId, Poll>> {
+    let polls: Vec<Poll> = db::scan_prefix(&db.open_tree(POLLS_TREE)?, b"")?;
+    Ok(polls
+        .into_iter()
+        .map(|poll| (poll.channel_id, poll))
+        .collect())
+}
+
+fn save(db: &sled::Db, poll: &Poll) -> DbResult<()> {
+    db::insert(
+        &db.open_tree(POLLS_TREE)?,
+        poll.channel_id.0.to_be_bytes(),
+        poll,
+    )
+}
+
+/// Runs `f` on the poll of `channel_id`, saves it and refreshes its tally.
+async fn update(
+    ctx: &Context,
+    channel_id: ChannelId,
+    f: impl FnOnce(&mut Poll) -> Result<(), VoteError>,
+) -> Result<(), VoteError> {
+    let poll = {
+        let mut data = ctx.data.write().await;
+        let db = data
+            .get::<Database>()
+            .cloned()
+            .expect("Expected Database in TypeMap.");
+        let poll = data
+            .get_mut::<Polls>()
+            .expect("Expected Polls in TypeMap.")
+            .get_mut(&channel_id)
+            .ok_or(VoteError::NoPoll)?;
+        f(poll)?;
+        save(&db, poll)?;
+        poll.clone()
+    };
+    if let Some(message_id) = poll.message_id {
+        let result = channel_id
+            .edit_message(&ctx.http, message_id, |m| m.embed(|e| poll.embed(e)))
+            .await;
+        if let Err(why) = result {
+            warn!(poll = poll.id, "Could not update the tally: {}", why);
+        }
+    }
+    Ok(())
+}
+
+/// Opens `poll` in its channel and schedules its closing.
+pub async fn open(ctx: &Context, mut poll: Poll) -> Result<(), VoteError> {
+    if poll.choices.len() > MAX_CHOICES {
+        return Err(VoteError::TooManyChoices);
+    }
+    let db = db::from_context(ctx).await;
+    poll.id = db.generate_id().map_err(DbError::from)?;
+    {
+        let mut data = ctx.data.write().await;
+        let polls = data.get_mut::<Polls>().expect("Expected Polls in TypeMap.");
+        if polls.contains_key(&poll.channel_id) {
+            return Err(VoteError::AlreadyOpen);
+        }
+        save(&db, &poll)?;
+        polls.insert(poll.channel_id, poll.clone());
+    }
+    match poll
+        .channel_id
+        .send_message(&ctx.http, |m| m.embed(|e| poll.embed(e)))
+        .await
+    {
+        Ok(message) => {
+            update(ctx, poll.channel_id, |poll| {
+                poll.message_id = Some(message.id);
+                Ok(())
+            })
+            .await?
+        }
+        Err(why) => warn!(poll = poll.id, "Could not post the tally: {}", why),
+    }
+    info!(poll = poll.id, channel_id = %poll.channel_id, "Opened a poll");
+    let (channel_id, id) = (poll.channel_id, poll.id);
+    scheduler::schedule(
+        ctx,
+        poll.closes_at,
+        Job::ClosePoll {
+            channel_id,
+            poll: id,
+        },
+    )
+    .await?;
+    scheduler::schedule_reminder(
+        ctx,
+        poll.closes_at,
+        Job::PollReminder {
+            channel_id,
+            poll: id,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Closes poll `id`, unless it was closed before. The votes of a paused
+/// Sporz game stay open until it resumes.
+pub async fn close_if_open(ctx: &Context, channel_id: ChannelId, id: u64) -> Result<(), VoteError> {
+    let purpose = ctx
+        .data
+        .read()
+        .await
+        .get::<Polls>()
+        .expect("Expected Polls in TypeMap.")
+        .get(&channel_id)
+        .filter(|poll| poll.id == id)
+        .map(|poll| poll.purpose);
+    let guild_id = match purpose {
+        Some(purpose) => purpose.guild_id(),
+        None => return Ok(()),
+    };
+    match guild_id {
+        Some(guild_id) if table::is_paused(ctx, guild_id).await => {
+            let at = Utc::now() + Duration::minutes(PAUSE_CHECK_MINUTES);
+            scheduler::schedule(
+                ctx,
+                at,
+                Job::ClosePoll {
+                    channel_id,
+                    poll: id,
+                },
+            )
+            .await?;
+            Ok(())
+        }
+        _ => close_poll(ctx, channel_id).await,
+    }
+}
+
+/// Reminds the voters of poll `id` that it is about to close.
+pub async fn remind(ctx: &Context, channel_id: ChannelId, id: u64) {
+    let poll = ctx
+        .data
+        .read()
+        .await
+        .get::<Polls>()
+        .expect("Expected Polls in TypeMap.")
+        .get(&channel_id)
+        .filter(|poll| poll.id == id)
+        .cloned();
+    let poll = match poll {
+        Some(poll) => poll,
+        None => return,
+    };
+    let pending: Vec<UserId> = match &poll.voters {
+        Some(voters) => voters
+            .iter()
+            .filter(|voter| !poll.votes.iter().any(|(user_id, _)| user_id == *voter))
+            .copied()
+            .collect(),
+        None => Vec::new(),
+    };
+    let voters = pending
+        .iter()
+        .map(|voter| format!("<@{}>", voter))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let content = format!(
+        "⏳ {} minutes left to vote: {} {}",
+        scheduler::REMINDER_MINUTES,
+        poll.question,
+        voters
+    );
+    // The question is typed by users: only the voters are pinged.
+    let result = channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(content.trim_end())
+                .allowed_mentions(|a| a.empty_parse().users(pending))
+        })
+        .await;
+    if let Err(why) = result {
+        warn!(poll = id, "Could not remind the voters: {}", why);
+    }
+}
+
+/// Removes the poll of `channel_id` without applying its result.
+pub async fn cancel(ctx: &Context, channel_id: ChannelId) -> DbResult<Option<Poll>> {
+    let mut data = ctx.data.write().await;
+    let db = data
+        .get::<Database>()
+        .cloned()
+        .expect("Expected Database in TypeMap.");
+    let poll = data
+        .get_mut::<Polls>()
+        .expect("Expected Polls in TypeMap.")
+        .remove(&channel_id);
+    db.open_tree(POLLS_TREE)?
+        .remove(channel_id.0.to_be_bytes())?;
+    Ok(poll)
+}
+
+/// Removes the Sporz poll of the table of `guild_id`, if any.
+pub async fn cancel_sporz(ctx: &Context, guild_id: GuildId, channel_id: ChannelId) -> DbResult<()> {
+    let sporz = ctx
+        .data
+        .read()
+        .await
+        .get::<Polls>()
+        .expect("Expected Polls in TypeMap.")
+        .get(&channel_id)
+        .is_some_and(|poll| poll.purpose.guild_id() == Some(guild_id));
+    if sporz {
+        cancel(ctx, channel_id).await?;
+    }
+    Ok(())
+}
+
+/// Closes the poll of `channel_id`, announces its result and applies it.
+pub async fn close_poll(ctx: &Context, channel_id: ChannelId) -> Result<(), VoteError> {
+    let poll = cancel(ctx, channel_id).await?.ok_or(VoteError::NoPoll)?;
+    let winner = poll.decide(&mut thread_rng());
+    let result = match (winner, poll.outcome()) {
+        (Some(winner), Outcome::Tie(_)) => {
+            format!("**{}** wins after a tie-break.", poll.choices[winner].label)
+        }
+        (Some(winner), _) => format!("**{}** wins.", poll.choices[winner].label),
+        (None, Outcome::Tie(_)) => "The vote is tied.".to_string(),
+        (None, Outcome::NoMajority) => "No choice has a majority.".to_string(),
+        (None, _) => "Nobody voted.".to_string(),
+    };
+    if let Err(why) = channel_id
+        .send_message(&ctx.http, |m| {
+            m.content(format!("The poll is closed. {}", result))
+                .allowed_mentions(|a| a.empty_parse())
+                .embed(|e| poll.embed(e))
+        })
+        .await
+    {
+        warn!(poll = poll.id, "Could not announce the result: {}", why);
+    }
+    info!(poll = poll.id, ?winner, "Closed a poll");
+    let target = winner.and_then(|winner| poll.choices[winner].user_id);
+    let votes: Vec<(PlayerId, PlayerId)> = poll
+        .votes
+        .iter()
+        .filter_map(|(voter, choice)| {
+            let target = poll.choices[*choice].user_id?;
+            Some((table::player_id(*voter), table::player_id(target)))
+        })
+        .collect();
+    let result = match poll.purpose {
+        Purpose::General => Ok(()),
+        Purpose::SporzCaptain { guild_id, day } => {
+            table::record_votes(ctx, guild_id, Election::Captain, votes).await;
+            let result = match target {
+                Some(captain) => {
+                    table::play(ctx, guild_id, |game| {
+                        game.elect_captain(table::player_id(captain))
+                    })
+                    .await
+                }
+                None => Ok(()),
+            };
+            open_elimination(ctx, guild_id, day).await;
+            result
+        }
+        Purpose::SporzElimination { guild_id, day } => {
+            table::record_votes(ctx, guild_id, Election::Elimination, votes).await;
+            table::play(ctx, guild_id, |game| {
+                if game.phase != Phase::Day(day) {
+                    return Err(crate::sporz::SporzError::WrongPhase { expected: "day" });
+                }
+                game.eliminate(target.map(table::player_id))
+            })
+            .await
+        }
+    };
+    if let Err(why) = result {
+        warn!(poll = poll.id, "Could not apply the result: {}", why);
+    }
+    Ok(())
+}
+
+/// A vote of the living players of a Sporz table, against one of them.
+async fn sporz_poll(
+    ctx: &Context,
+    guild_id: GuildId,
+    question: String,
+    purpose: Purpose,
+) -> Option<Poll> {
+    let table = table::get(ctx, guild_id).await?;
+    let game = table.game.as_ref()?;
+    let config = {
+        let data = ctx.data.read().await;
+        data.get::<crate::config::Config>()
+            .cloned()
+            .expect("Expected Config in TypeMap.")
+    };
+    let mut choices = Vec::new();
+    for player in game.alive() {
+        let user_id = table::user_id(player.id);
+        choices.push(Choice {
+            label: crate::presence::display_name(ctx, guild_id, user_id).await,
+            user_id: Some(user_id),
+        });
+    }
+    let (rule, tie_break, seconds) = match purpose {
+        Purpose::SporzCaptain { .. } => (
+            Rule::Plurality,
+            TieBreak::Random,
+            config.sporz.captain_seconds,
+        ),
+        _ => (
+            config.sporz.vote_rule,
+            config.sporz.tie_break,
+            config.sporz.day_seconds,
+        ),
+    };
+    Some(Poll {
+        id: 0,
+        channel_id: table.channel_id,
+        message_id: None,
+        author: None,
+        question,
+        voters: Some(choices.iter().filter_map(|choice| choice.user_id).collect()),
+        choices,
+        captain: game.captain.map(table::user_id),
+        rule,
+        tie_break,
+        votes: Vec::new(),
+        closes_at: Utc::now() + Duration::seconds(seconds as i64),
+        purpose,
+    })
+}
+
+async fn open_elimination(ctx: &Context, guild_id: GuildId, day: u32) {
+    let purpose = Purpose::SporzElimination { guild_id, day };
+    if let Some(poll) = sporz_poll(
+        ctx,
+        guild_id,
+        format!("Who to eliminate on day {}?", day),
+        purpose,
+    )
+    .await
+    {
+        if let Err(why) = open(ctx, poll).await {
+            warn!(%guild_id, day, "Could not open the elimination vote: {}", why);
+        }
+    }
+}
+
+/// Starts the votes of a Sporz day: the captain election when there is no
+/// captain, then the elimination.
+pub async fn open_day(ctx: &Context, guild_id: GuildId, day: u32) {
+    let has_captain = table::get(ctx, guild_id)
+        .await
+        .and_then(|table| table.game)
+        .is_some_and(|game| game.captain.is_some());
+    if has_captain {
+        return open_elimination(ctx, guild_id, day).await;
+    }
+    let purpose = Purpose::SporzCaptain { guild_id, day };
+    if let Some(poll) = sporz_poll(
+        ctx,
+        guild_id,
+        "Who should be the captain?".to_string(),
+        purpose,
+    )
+    .await
+    {
+        if let Err(why) = open(ctx, poll).await {
+            warn!(%guild_id, day, "Could not open the captain election: {}", why);
+        }
+    }
+}
+
+async fn reply_with(
+    ctx: &Context,
+    msg: &Message,
+    result: Result<String, VoteError>,
+) -> CommandResult {
+    match result {
+        Ok(reply) => msg.reply(ctx, reply).await?,
+        Err(VoteError::Db(why)) => return Err(why.into()),
+        Err(why) => msg.reply(ctx, why.to_string()).await?,
+    };
+    Ok(())
+}
+
+#[command]
+#[description = "Vote in the poll of this channel, for a player or a choice."]
+#[usage = "@player|choice"]
+#[example = "@Alice"]
+async fn vote(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let voter = msg.author.id;
+    let input = args.rest().to_string();
+    let result = update(ctx, msg.channel_id, |poll| {
+        let choice = poll.find_choice(&input)?;
+        poll.vote(voter, choice)
+    })
+    .await
+    .map(|_| "vote recorded".to_string());
+    reply_with(ctx, msg, result).await
+}
+
+#[command]
+#[description = "Withdraw your vote from the poll of this channel."]
+async fn unvote(ctx: &Context, msg: &Message) -> CommandResult {
+    let voter = msg.author.id;
+    let result = update(ctx, msg.channel_id, |poll| poll.unvote(voter))
+        .await
+        .map(|_| "vote withdrawn".to_string());
+    reply_with(ctx, msg, result).await
+}
+
+/// Parses durations such as `30s`, `10m` or `2h`. Durations too long to be
+/// added to the current date are rejected.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let input = input.trim();
+    let (index, unit) = input.char_indices().last()?;
+    let number: i64 = input[..index].parse().ok()?;
+    if number <= 0 {
+        return None;
+    }
+    let unit = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        _ => return None,
+    };
+    let seconds = number.checked_mul(unit)?;
+    // `Duration` counts milliseconds in an `i64`.
+    if seconds > i64::MAX / 1000 {
+        return None;
+    }
+    let duration = Duration::seconds(seconds);
+    Utc::now().checked_add_signed(duration)?;
+    Some(duration)
+}
+
+#[command]
+#[sub_commands(close)]
+#[description = "Open a poll in this channel, closed after an hour unless a duration is given."]
+#[usage = "[duration, ][majority, ]question, choice, choice[, choice...]"]
+#[example = "10m, Pizza or sushi?, pizza, sushi"]
+async fn poll(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let mut inputs: Vec<String> = args
+        .iter::<String>()
+        .filter_map(Result::ok)
+        .map(|input| input.trim().to_string())
+        .collect();
+    let duration = match inputs.first().and_then(|input| parse_duration(input)) {
+        Some(duration) => {
+            inputs.remove(0);
+            duration
+        }
+        None => Duration::minutes(DEFAULT_POLL_MINUTES),
+    };
+    let rule = if inputs.first().is_some_and(|input| input == "majority") {
+        inputs.remove(0);
+        Rule::Majority
+    } else {
+        Rule::Plurality
+    };
+    if inputs.len() < 3 {
+        return reply_with(ctx, msg, Err(VoteError::TooFewChoices)).await;
+    }
+    let question = inputs.remove(0);
+    let poll = Poll {
+        id: 0,
+        channel_id: msg.channel_id,
+        message_id: None,
+        author: Some(msg.author.id),
+        question,
+        choices: inputs
+            .into_iter()
+            .map(|label| Choice {
+                label,
+                user_id: None,
+            })
+            .collect(),
+        voters: None,
+        captain: None,
+        rule,
+        tie_break: TieBreak::None,
+        votes: Vec::new(),
+        closes_at: Utc::now() + duration,
+        purpose: Purpose::General,
+    };
+    match open(ctx, poll).await {
+        Ok(()) => Ok(()),
+        Err(why) => reply_with(ctx, msg, Err(why)).await,
+    }
+}
+
+#[command]
+#[description = "Close the poll of this channel now."]
+async fn close(ctx: &Context, msg: &Message) -> CommandResult {
+    let author = ctx
+        .data
+        .read()
+        .await
+        .get::<Polls>()
+        .expect("Expected Polls in TypeMap.")
+        .get(&msg.channel_id)
+        .map(|poll| poll.author);
+    let result = match author {
+        None => Err(VoteError::NoPoll),
+        Some(author) if author != Some(msg.author.id) => Err(VoteError::NotAuthor),
+        Some(_) => close_poll(ctx, msg.channel_id).await,
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(why) => reply_with(ctx, msg, Err(why)).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const CAPTAIN: UserId = UserId(1);
+
+    fn open_poll(rule: Rule, tie_break: TieBreak, votes: &[(u64, usize)]) -> Poll {
+        let choice = |label: &str| Choice {
+            label: label.to_string(),
+            user_id: None,
+        };
+        Poll {
+            id: 1,
+            channel_id: ChannelId(1),
+            message_id: None,
+            author: None,
+            question: "Who?".to_string(),
+            choices: vec![choice("a"), choice("b"), choice("c")],
+            voters: None,
+            captain: Some(CAPTAIN),
+            rule,
+            tie_break,
+            votes: votes
+                .iter()
+                .map(|(voter, choice)| (UserId(*voter), *choice))
+                .collect(),
+            closes_at: Utc::now(),
+            purpose: Purpose::General,
+        }
+    }
+
+    fn decide(poll: &Poll) -> Option<usize> {
+        poll.decide(&mut ChaCha8Rng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn captain_vote_counts_double() {
+        let poll = open_poll(Rule::Plurality, TieBreak::None, &[(1, 0), (2, 1), (3, 2)]);
+        assert_eq!(poll.tally(), vec![2, 1, 1]);
+        assert_eq!(poll.outcome(), Outcome::Winner(0));
+        let poll = open_poll(Rule::Plurality, TieBreak::None, &[(1, 0), (2, 1), (3, 1)]);
+        assert_eq!(poll.outcome(), Outcome::Tie(vec![0, 1]));
+    }
+
+    #[test]
+    fn plurality_and_majority() {
+        let votes = [(2, 0), (3, 0), (4, 1), (5, 2)];
+        assert_eq!(
+            open_poll(Rule::Plurality, TieBreak::None, &votes).outcome(),
+            Outcome::Winner(0)
+        );
+        assert_eq!(
+            open_poll(Rule::Majority, TieBreak::None, &votes).outcome(),
+            Outcome::NoMajority
+        );
+        assert_eq!(
+            open_poll(Rule::Majority, TieBreak::None, &votes[..3]).outcome(),
+            Outcome::Winner(0)
+        );
+        assert_eq!(
+            open_poll(Rule::Plurality, TieBreak::Random, &[]).outcome(),
+            Outcome::NoVotes
+        );
+    }
+
+    #[test]
+    fn tie_without_tie_break() {
+        let poll = open_poll(Rule::Plurality, TieBreak::None, &[(2, 0), (3, 1)]);
+        assert_eq!(poll.outcome(), Outcome::Tie(vec![0, 1]));
+        assert_eq!(decide(&poll), None);
+    }
+
+    #[test]
+    fn tie_broken_by_the_captain() {
+        let poll = open_poll(
+            Rule::Plurality,
+            TieBreak::Captain,
+            &[(1, 1), (2, 0), (3, 0)],
+        );
+        assert_eq!(poll.outcome(), Outcome::Tie(vec![0, 1]));
+        assert_eq!(decide(&poll), Some(1));
+
+        // The captain voted for neither of the tied choices.
+        let votes = [(1, 2), (2, 0), (3, 0), (4, 0), (5, 1), (6, 1), (7, 1)];
+        let poll = open_poll(Rule::Plurality, TieBreak::Captain, &votes);
+        assert_eq!(poll.outcome(), Outcome::Tie(vec![0, 1]));
+        assert_eq!(decide(&poll), None);
+    }
+
+    #[test]
+    fn tie_broken_at_random() {
+        let poll = open_poll(Rule::Plurality, TieBreak::Random, &[(2, 0), (3, 2)]);
+        let mut decided = Vec::new();
+        for seed in 0..20 {
+            let winner = poll.decide(&mut ChaCha8Rng::seed_from_u64(seed)).unwrap();
+            assert!(winner == 0 || winner == 2);
+            decided.push(winner);
+        }
+        assert!(decided.contains(&0) && decided.contains(&2));
+    }
+
+    #[test]
+    fn majority_is_not_tie_broken() {
+        let poll = open_poll(Rule::Majority, TieBreak::Random, &[(2, 0), (3, 1)]);
+        assert_eq!(poll.outcome(), Outcome::NoMajority);
+        assert_eq!(decide(&poll), None);
+    }
+
+    #[test]
+    fn voters_change_their_vote() {
+        let mut poll = open_poll(Rule::Plurality, TieBreak::None, &[]);
+        poll.voters = Some(vec![UserId(2)]);
+        assert!(matches!(poll.vote(UserId(3), 0), Err(VoteError::NotVoter)));
+        poll.vote(UserId(2), 0).unwrap();
+        poll.vote(UserId(2), 1).unwrap();
+        assert_eq!(poll.tally(), vec![0, 1, 0]);
+        poll.unvote(UserId(2)).unwrap();
+        assert!(matches!(poll.unvote(UserId(2)), Err(VoteError::NotVoted)));
+        assert_eq!(poll.find_choice("B").unwrap(), 1);
+        assert_eq!(poll.find_choice("3").unwrap(), 2);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s"), Some(Duration::seconds(30)));
+        assert_eq!(parse_duration(" 10m "), Some(Duration::minutes(10)));
+        assert_eq!(parse_duration("2h"), Some(Duration::hours(2)));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("10d"), None);
+        assert_eq!(parse_duration("Pizza or crêpé"), None);
+        assert_eq!(parse_duration("10é"), None);
+        assert_eq!(parse_duration("99999999999999h"), None);
+        assert_eq!(parse_duration("9223372036854775807s"), None);
+        assert_eq!(parse_duration("-9223372036854775808s"), None);
+        assert_eq!(parse_duration("0s"), None);
+        assert_eq!(parse_duration("-10m"), None);
+        assert_eq!(parse_duration("-1h"), None);
+    }
+}